@@ -0,0 +1,1373 @@
+//! Player accounts, sessions, provably fair seeds, responsible gaming limits (see the
+//! [`limits`](crate::limits) module) and transactions ledger persisted in a local SQLite database.
+//!
+//! Every change to a balance is recorded as an immutable, double-entry transaction in the ledger:
+//! the amounts of a transaction's entries always sum to zero across the ledger accounts. Each
+//! player has its own ledger account (`player:<name>`), each game a house account
+//! (`house:<game>`) collecting the wagers and paying the wins, and the `bank` account is the
+//! counterpart of deposits and adjustments.
+//!
+//! The free spins left to each player are kept next to the ledger, so that they can be played
+//! after a reconnection. The pools of the progressive jackpots are kept next to the ledger as
+//! well, in thousandths of a coin so that the contributions of small wagers add up. They are paid
+//! by the house account of the game.
+//!
+//! The play session of each player is kept as well, across logins and reconnections, so that
+//! logging in again doesn't reset the session limits (see [`AccountStore::resume_play_session`]).
+//!
+//! The `balance` column of an account is a cache of its ledger account, updated in the same SQL
+//! transaction as the ledger so that a crash can never lose or duplicate money. It can be checked
+//! against the ledger at any time with [`AccountStore::check`].
+
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use rusqlite::{params, Connection, OpenFlags, OptionalExtension, Row};
+use serde::{Deserialize, Serialize};
+
+use crate::fairness::{SeedPair, SpinProof};
+use crate::limits::{LimitsStatus, PendingLimits, PlayerLimits};
+
+/// Accounts database path.
+pub const ACCOUNTS_DATABASE: &str = "./data/accounts.db";
+/// Ledger account of the deposits and adjustments counterpart.
+pub const BANK_ACCOUNT: &str = "bank";
+
+/// Returns the ledger account of a player.
+pub fn player_account(player: &str) -> String {
+    format!("player:{}", player)
+}
+
+/// Returns the ledger account of the house for a game.
+pub fn house_account(game: &str) -> String {
+    format!("house:{}", game)
+}
+
+/// Kinds of transactions recorded in the ledger.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TransactionKind {
+    /// Starting balance given to a new account.
+    Deposit,
+    /// Coins wagered on a spin, from the player to the house.
+    Wager,
+    /// Coins won on a spin, from the house to the player.
+    Win,
+    /// Progressive jackpot won on a spin, from the house to the player.
+    Jackpot,
+    /// Free spin played, for no coins.
+    FreeSpin,
+    /// Win of a spin gambled on a double-or-nothing round, from the house to the player if the
+    /// gamble was won and the other way around if it was lost.
+    Gamble,
+    /// Manual correction of a balance (e.g. opening balance of the accounts created before the
+    /// ledger).
+    Adjustment,
+}
+
+impl fmt::Display for TransactionKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad(&format!("{:?}", self))
+    }
+}
+
+impl FromStr for TransactionKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Deposit" => Ok(TransactionKind::Deposit),
+            "Wager" => Ok(TransactionKind::Wager),
+            "Win" => Ok(TransactionKind::Win),
+            "Jackpot" => Ok(TransactionKind::Jackpot),
+            "FreeSpin" => Ok(TransactionKind::FreeSpin),
+            "Gamble" => Ok(TransactionKind::Gamble),
+            "Adjustment" => Ok(TransactionKind::Adjustment),
+            _ => Err(format!("Unknown transaction kind \"{}\"", s)),
+        }
+    }
+}
+
+/// A line of a statement: one transaction seen from a single ledger account.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LedgerEntry {
+    /// Transaction identifier.
+    pub id: u64,
+    /// Time at which the transaction was recorded.
+    pub timestamp: SystemTime,
+    /// Player involved in the transaction.
+    pub player: String,
+    /// Kind of transaction.
+    pub kind: TransactionKind,
+    /// Game identifier of the spin, if any.
+    pub game: Option<String>,
+    /// Number of coins wagered on the spin, if any.
+    pub bet: Option<u64>,
+    /// Reel stops of the spin result, after the respins if any (empty if not a spin).
+    pub stops: Vec<usize>,
+    /// Symbols displayed by the spin result (empty if not a spin).
+    pub result: Vec<String>,
+    /// Generator that drew the spin, if any (see [`SpinRecord::rng`]).
+    #[serde(default)]
+    pub rng: Option<String>,
+    /// Inputs of the spin if it was provably fair.
+    #[serde(default)]
+    pub proof: Option<SpinProof>,
+    /// Amount credited (positive) or debited (negative) on the ledger account.
+    pub amount: i64,
+    /// Balance of the ledger account after the transaction.
+    pub balance: i64,
+}
+
+/// Everything recorded in the ledger about a spin.
+#[derive(Debug, Clone)]
+pub struct SpinRecord {
+    /// Game identifier.
+    pub game: String,
+    /// Number of coins wagered, or bet by the spin that awarded the free spin.
+    pub bet: u64,
+    /// Reel stops of the result, after the respins if any.
+    pub stops: Vec<usize>,
+    /// Symbols displayed by the result.
+    pub result: Vec<String>,
+    /// Amount won, multiplied for a free spin.
+    pub win: u64,
+    /// Whether the spin was a free spin, paid by a previous spin.
+    pub free_spin: bool,
+    /// Number of free spins awarded by the spin.
+    pub free_spins_won: u32,
+    /// Generator that drew the spin: a [`RngKind`](crate::rng::RngKind) or
+    /// [`FAIR_RNG`](crate::fairness::FAIR_RNG). Its seed is never recorded.
+    pub rng: String,
+    /// Inputs of the spin if it was provably fair.
+    pub proof: Option<SpinProof>,
+    /// Progressive jackpot of the game, if it has one.
+    pub jackpot: Option<JackpotSpin>,
+}
+
+/// Progressive jackpot of the game a spin is played on.
+#[derive(Debug, Clone, Copy)]
+pub struct JackpotSpin {
+    /// Value of the pool when it is created and after each hit.
+    pub seed: u64,
+    /// Percentage of the wager added to the pool.
+    pub contribution: f64,
+    /// Whether the spin hit the jackpot combo.
+    pub hit: bool,
+}
+
+/// Outcome of a settled spin.
+#[derive(Debug, Clone, Copy)]
+pub struct SpinSettlement {
+    /// Balance of the player after the spin.
+    pub balance: u64,
+    /// Amount of the progressive jackpot won, on top of the win of the spin.
+    pub jackpot_win: u64,
+    /// Value of the progressive jackpot after the spin, if the game has one.
+    pub jackpot: Option<u64>,
+    /// Free spins left to the player on the game after the spin.
+    pub free_spins: u32,
+}
+
+/// Free spins left to a player on a game.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FreeSpinsLeft {
+    /// Number of free spins left.
+    pub remaining: u32,
+    /// Number of coins bet by the spin that awarded them.
+    pub bet: u64,
+}
+
+/// Inconsistencies found between the accounts and the ledger.
+#[derive(Debug, Default)]
+pub struct LedgerReport {
+    /// Players whose balance doesn't match their ledger account, with both values.
+    pub balance_mismatches: Vec<(String, u64, i64)>,
+    /// Transactions whose entries don't sum to zero.
+    pub unbalanced_transactions: Vec<u64>,
+}
+
+impl LedgerReport {
+    /// Returns `true` if no inconsistency was found.
+    pub fn is_consistent(&self) -> bool {
+        self.balance_mismatches.is_empty() && self.unbalanced_transactions.is_empty()
+    }
+}
+
+impl fmt::Display for LedgerReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (player, balance, ledger) in self.balance_mismatches.iter() {
+            writeln!(
+                f,
+                "[!] Balance of \"{}\" ({}) doesn't match the ledger ({})",
+                player, balance, ledger
+            )?;
+        }
+
+        for transaction in self.unbalanced_transactions.iter() {
+            writeln!(f, "[!] Transaction #{} is unbalanced", transaction)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Storage of the player accounts, sessions and ledger.
+pub struct AccountStore {
+    connection: Mutex<Connection>,
+}
+
+impl AccountStore {
+    /// Opens (or creates) the accounts database at `path`.
+    pub fn open(path: &Path) -> rusqlite::Result<Self> {
+        let mut connection = Connection::open(path)?;
+        connection.execute_batch(
+            "PRAGMA journal_mode = WAL;
+             PRAGMA synchronous = FULL;
+             CREATE TABLE IF NOT EXISTS accounts (
+                 player TEXT PRIMARY KEY NOT NULL,
+                 balance INTEGER NOT NULL CHECK (balance >= 0),
+                 created_at INTEGER NOT NULL DEFAULT (unixepoch())
+             );
+             CREATE TABLE IF NOT EXISTS sessions (
+                 token TEXT PRIMARY KEY NOT NULL,
+                 player TEXT NOT NULL REFERENCES accounts (player),
+                 expires_at INTEGER NOT NULL
+             );
+             CREATE TABLE IF NOT EXISTS transactions (
+                 id INTEGER PRIMARY KEY AUTOINCREMENT,
+                 created_at INTEGER NOT NULL,
+                 player TEXT NOT NULL,
+                 kind TEXT NOT NULL,
+                 game TEXT,
+                 bet INTEGER,
+                 stops TEXT NOT NULL DEFAULT '[]',
+                 result TEXT NOT NULL DEFAULT '[]'
+             );
+             CREATE TABLE IF NOT EXISTS entries (
+                 id INTEGER PRIMARY KEY AUTOINCREMENT,
+                 transaction_id INTEGER NOT NULL REFERENCES transactions (id),
+                 account TEXT NOT NULL,
+                 amount INTEGER NOT NULL
+             );
+             CREATE TABLE IF NOT EXISTS fair_seeds (
+                 commitment TEXT PRIMARY KEY NOT NULL,
+                 player TEXT NOT NULL REFERENCES accounts (player),
+                 server_seed TEXT NOT NULL,
+                 client_seed TEXT NOT NULL,
+                 last_nonce INTEGER,
+                 created_at INTEGER NOT NULL DEFAULT (unixepoch()),
+                 revealed_at INTEGER
+             );
+             CREATE TABLE IF NOT EXISTS limits (
+                 player TEXT PRIMARY KEY NOT NULL REFERENCES accounts (player),
+                 limits TEXT NOT NULL DEFAULT '{}',
+                 pending TEXT,
+                 pending_at INTEGER,
+                 excluded_until INTEGER
+             );
+             CREATE TABLE IF NOT EXISTS free_spins (
+                 player TEXT NOT NULL REFERENCES accounts (player),
+                 game TEXT NOT NULL,
+                 remaining INTEGER NOT NULL CHECK (remaining >= 0),
+                 bet INTEGER NOT NULL,
+                 PRIMARY KEY (player, game)
+             );
+             CREATE TABLE IF NOT EXISTS jackpots (
+                 game TEXT PRIMARY KEY NOT NULL,
+                 pool INTEGER NOT NULL
+             );
+             CREATE TABLE IF NOT EXISTS play_sessions (
+                 player TEXT PRIMARY KEY NOT NULL REFERENCES accounts (player),
+                 started_at INTEGER NOT NULL,
+                 active_at INTEGER NOT NULL
+             );
+             CREATE INDEX IF NOT EXISTS fair_seeds_player ON fair_seeds (player);
+             CREATE INDEX IF NOT EXISTS entries_account ON entries (account);
+             CREATE TRIGGER IF NOT EXISTS transactions_no_update BEFORE UPDATE ON transactions
+                 BEGIN SELECT RAISE(ABORT, 'ledger is append-only'); END;
+             CREATE TRIGGER IF NOT EXISTS transactions_no_delete BEFORE DELETE ON transactions
+                 BEGIN SELECT RAISE(ABORT, 'ledger is append-only'); END;
+             CREATE TRIGGER IF NOT EXISTS entries_no_update BEFORE UPDATE ON entries
+                 BEGIN SELECT RAISE(ABORT, 'ledger is append-only'); END;
+             CREATE TRIGGER IF NOT EXISTS entries_no_delete BEFORE DELETE ON entries
+                 BEGIN SELECT RAISE(ABORT, 'ledger is append-only'); END;",
+        )?;
+
+        // Accounts created before authentication was added have no password until an admin sets one
+        add_column_if_missing(&connection, "accounts", "password_hash", "TEXT")?;
+        // Spins made before the provably fair mode have no proof
+        add_column_if_missing(&connection, "transactions", "proof", "TEXT")?;
+        add_column_if_missing(&connection, "transactions", "rng", "TEXT")?;
+
+        // Accounts created before the ledger get their current balance as an opening adjustment
+        let tx = connection.transaction()?;
+        let unrecorded: Vec<(String, u64)> = tx
+            .prepare(
+                "SELECT player, balance FROM accounts
+                 WHERE balance > 0
+                 AND NOT EXISTS (SELECT 1 FROM entries WHERE account = 'player:' || player)",
+            )?
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<rusqlite::Result<_>>()?;
+        for (player, balance) in unrecorded {
+            insert_transaction(
+                &tx,
+                &player,
+                TransactionKind::Adjustment,
+                None,
+                None,
+                &[
+                    (BANK_ACCOUNT.to_string(), -(balance as i64)),
+                    (player_account(&player), balance as i64),
+                ],
+            )?;
+        }
+        tx.commit()?;
+
+        Ok(Self {
+            connection: Mutex::new(connection),
+        })
+    }
+
+    /// Writes the transactions of the write-ahead log back into the database file, e.g. before
+    /// exiting so that the database can be copied on its own.
+    pub fn checkpoint(&self) -> rusqlite::Result<()> {
+        self.connection
+            .lock()
+            .unwrap()
+            .execute_batch("PRAGMA wal_checkpoint(TRUNCATE);")
+    }
+
+    /// Opens an existing accounts database without write access, e.g. for auditing.
+    pub fn open_read_only(path: &Path) -> rusqlite::Result<Self> {
+        Ok(Self {
+            connection: Mutex::new(Connection::open_with_flags(
+                path,
+                OpenFlags::SQLITE_OPEN_READ_ONLY,
+            )?),
+        })
+    }
+
+    /// Creates the account with `start_balance`, returning its balance or `None` if the player
+    /// already has an account. Accounts created without a password can't be registered over, their
+    /// password is set by an admin (see [`AccountStore::set_password`]).
+    pub fn register(
+        &self,
+        player: &str,
+        password_hash: &str,
+        start_balance: u64,
+    ) -> rusqlite::Result<Option<u64>> {
+        let mut connection = self.connection.lock().unwrap();
+        let tx = connection.transaction()?;
+
+        let exists = tx
+            .query_row(
+                "SELECT 1 FROM accounts WHERE player = ?1",
+                params![player],
+                |_| Ok(()),
+            )
+            .optional()?
+            .is_some();
+        if exists {
+            return Ok(None);
+        }
+
+        tx.execute(
+            "INSERT INTO accounts (player, balance, password_hash) VALUES (?1, ?2, ?3)",
+            params![player, start_balance, password_hash],
+        )?;
+        insert_transaction(
+            &tx,
+            player,
+            TransactionKind::Deposit,
+            None,
+            None,
+            &[
+                (BANK_ACCOUNT.to_string(), -(start_balance as i64)),
+                (player_account(player), start_balance as i64),
+            ],
+        )?;
+        tx.commit()?;
+
+        Ok(Some(start_balance))
+    }
+
+    /// Replaces the password of an existing account and revokes its session tokens, returning
+    /// `false` if the player has no account.
+    pub fn set_password(&self, player: &str, password_hash: &str) -> rusqlite::Result<bool> {
+        let mut connection = self.connection.lock().unwrap();
+        let tx = connection.transaction()?;
+
+        let updated = tx.execute(
+            "UPDATE accounts SET password_hash = ?2 WHERE player = ?1",
+            params![player, password_hash],
+        )?;
+        tx.execute("DELETE FROM sessions WHERE player = ?1", params![player])?;
+        tx.commit()?;
+
+        Ok(updated > 0)
+    }
+
+    /// Returns the password hash of the player, if the account exists and has one.
+    pub fn password_hash(&self, player: &str) -> rusqlite::Result<Option<String>> {
+        self.connection
+            .lock()
+            .unwrap()
+            .query_row(
+                "SELECT password_hash FROM accounts WHERE player = ?1",
+                params![player],
+                |row| row.get(0),
+            )
+            .optional()
+            .map(Option::flatten)
+    }
+
+    /// Stores a new session token for the player, valid for `ttl`. Expired tokens are pruned.
+    pub fn create_session(
+        &self,
+        player: &str,
+        token: &str,
+        ttl: Duration,
+    ) -> rusqlite::Result<SystemTime> {
+        let expires_at = SystemTime::now() + ttl;
+        let connection = self.connection.lock().unwrap();
+        connection.execute("DELETE FROM sessions WHERE expires_at <= unixepoch()", [])?;
+        connection.execute(
+            "INSERT INTO sessions (token, player, expires_at) VALUES (?1, ?2, ?3)",
+            params![token, player, unix_seconds(expires_at)],
+        )?;
+
+        Ok(expires_at)
+    }
+
+    /// Returns the player and expiration time of a session token, if it exists.
+    pub fn session(&self, token: &str) -> rusqlite::Result<Option<(String, SystemTime)>> {
+        self.connection
+            .lock()
+            .unwrap()
+            .query_row(
+                "SELECT player, expires_at FROM sessions WHERE token = ?1",
+                params![token],
+                |row| Ok((row.get(0)?, UNIX_EPOCH + Duration::from_secs(row.get(1)?))),
+            )
+            .optional()
+    }
+
+    /// Returns the balance of the player, if the account exists.
+    pub fn balance(&self, player: &str) -> rusqlite::Result<Option<u64>> {
+        self.connection
+            .lock()
+            .unwrap()
+            .query_row(
+                "SELECT balance FROM accounts WHERE player = ?1",
+                params![player],
+                |row| row.get(0),
+            )
+            .optional()
+    }
+
+    /// Records the wager and win of a spin and updates the balance in one transaction, returning
+    /// the settlement or `None` if the balance doesn't cover the wager (or if there is no free spin
+    /// left to play). The wager contributes to the progressive jackpot of the game, which is paid
+    /// and reset if the spin hit it. A free spin costs nothing and is taken from the free spins
+    /// left, to which the free spins won are added.
+    pub fn settle_spin(
+        &self,
+        player: &str,
+        spin: &SpinRecord,
+    ) -> rusqlite::Result<Option<SpinSettlement>> {
+        let mut connection = self.connection.lock().unwrap();
+        let tx = connection.transaction()?;
+
+        if spin.free_spin {
+            let taken = tx.execute(
+                "UPDATE free_spins SET remaining = remaining - 1
+                 WHERE player = ?1 AND game = ?2 AND remaining > 0",
+                params![player, spin.game],
+            )?;
+            if taken == 0 {
+                return Ok(None);
+            }
+        }
+        if spin.free_spins_won > 0 {
+            tx.execute(
+                "INSERT INTO free_spins (player, game, remaining, bet) VALUES (?1, ?2, ?3, ?4)
+                 ON CONFLICT (player, game)
+                 DO UPDATE SET remaining = remaining + excluded.remaining, bet = excluded.bet",
+                params![player, spin.game, spin.free_spins_won, spin.bet],
+            )?;
+        }
+        let cost = if spin.free_spin { 0 } else { spin.bet };
+
+        let mut jackpot_win = 0;
+        let pool = match spin.jackpot {
+            Some(jackpot) => {
+                let contribution = (spin.bet as f64 * jackpot.contribution * 10.0).round() as u64;
+                let mut pool = jackpot_pool(&tx, &spin.game, jackpot.seed)? + contribution;
+                // The fraction of a coin left in the pool is kept for the next one
+                if jackpot.hit {
+                    jackpot_win = pool / 1000;
+                    pool = pool % 1000 + jackpot.seed * 1000;
+                }
+                tx.execute(
+                    "UPDATE jackpots SET pool = ?2 WHERE game = ?1",
+                    params![spin.game, pool],
+                )?;
+                Some(pool)
+            }
+            None => None,
+        };
+
+        let Some(balance) = tx
+            .query_row(
+                "UPDATE accounts SET balance = balance - ?2 + ?3
+                 WHERE player = ?1 AND balance >= ?2
+                 RETURNING balance",
+                params![player, cost, spin.win + jackpot_win],
+                |row| row.get(0),
+            )
+            .optional()?
+        else {
+            return Ok(None);
+        };
+
+        let (player_account, house_account) = (player_account(player), house_account(&spin.game));
+        insert_transaction(
+            &tx,
+            player,
+            if spin.free_spin {
+                TransactionKind::FreeSpin
+            } else {
+                TransactionKind::Wager
+            },
+            Some(&spin.game),
+            Some(spin),
+            &[
+                (player_account.clone(), -(cost as i64)),
+                (house_account.clone(), cost as i64),
+            ],
+        )?;
+        for (kind, amount) in [
+            (TransactionKind::Win, spin.win),
+            (TransactionKind::Jackpot, jackpot_win),
+        ] {
+            if amount > 0 {
+                insert_transaction(
+                    &tx,
+                    player,
+                    kind,
+                    Some(&spin.game),
+                    Some(spin),
+                    &[
+                        (house_account.clone(), -(amount as i64)),
+                        (player_account.clone(), amount as i64),
+                    ],
+                )?;
+            }
+        }
+        let free_spins = free_spins_left(&tx, player, &spin.game)?.map_or(0, |f| f.remaining);
+        touch_play_session(&tx, player)?;
+        tx.commit()?;
+
+        Ok(Some(SpinSettlement {
+            balance,
+            jackpot_win,
+            jackpot: pool.map(|pool| pool / 1000),
+            free_spins,
+        }))
+    }
+
+    /// Records a gamble of `stake` coins won on a game and updates the balance in one transaction,
+    /// returning the new balance or `None` if the balance doesn't cover the stake anymore.
+    pub fn settle_gamble(
+        &self,
+        player: &str,
+        game: &str,
+        stake: u64,
+        won: bool,
+    ) -> rusqlite::Result<Option<u64>> {
+        let mut connection = self.connection.lock().unwrap();
+        let tx = connection.transaction()?;
+        let amount = if won { stake as i64 } else { -(stake as i64) };
+
+        let Some(balance) = tx
+            .query_row(
+                "UPDATE accounts SET balance = balance + ?3
+                 WHERE player = ?1 AND balance >= ?2
+                 RETURNING balance",
+                params![player, stake, amount],
+                |row| row.get(0),
+            )
+            .optional()?
+        else {
+            return Ok(None);
+        };
+
+        insert_transaction(
+            &tx,
+            player,
+            TransactionKind::Gamble,
+            Some(game),
+            None,
+            &[
+                (house_account(game), -amount),
+                (player_account(player), amount),
+            ],
+        )?;
+        touch_play_session(&tx, player)?;
+        tx.commit()?;
+
+        Ok(Some(balance))
+    }
+
+    /// Returns the free spins left to the player on a game, if any.
+    pub fn free_spins(&self, player: &str, game: &str) -> rusqlite::Result<Option<FreeSpinsLeft>> {
+        free_spins_left(&self.connection.lock().unwrap(), player, game)
+    }
+
+    /// Returns the current value of the progressive jackpot of a game, created with `seed` if it
+    /// doesn't exist yet.
+    pub fn jackpot(&self, game: &str, seed: u64) -> rusqlite::Result<u64> {
+        Ok(jackpot_pool(&self.connection.lock().unwrap(), game, seed)? / 1000)
+    }
+
+    /// Credits (positive `amount`) or debits (negative `amount`) the player from the bank,
+    /// returning the new balance or `None` if the account doesn't exist or can't cover the debit.
+    pub fn adjust(&self, player: &str, amount: i64) -> rusqlite::Result<Option<u64>> {
+        let mut connection = self.connection.lock().unwrap();
+        let tx = connection.transaction()?;
+
+        let Some(balance) = tx
+            .query_row(
+                "UPDATE accounts SET balance = balance + ?2
+                 WHERE player = ?1 AND balance + ?2 >= 0
+                 RETURNING balance",
+                params![player, amount],
+                |row| row.get(0),
+            )
+            .optional()?
+        else {
+            return Ok(None);
+        };
+
+        insert_transaction(
+            &tx,
+            player,
+            TransactionKind::Adjustment,
+            None,
+            None,
+            &[
+                (BANK_ACCOUNT.to_string(), -amount),
+                (player_account(player), amount),
+            ],
+        )?;
+        tx.commit()?;
+
+        Ok(Some(balance))
+    }
+
+    /// Returns the net loss of the player (coins wagered minus coins won) on the spins and gambles
+    /// settled since `since`, negative if the player won more than they wagered.
+    pub fn net_loss(&self, player: &str, since: SystemTime) -> rusqlite::Result<i64> {
+        self.connection.lock().unwrap().query_row(
+            "SELECT -COALESCE(SUM(e.amount), 0)
+             FROM entries e JOIN transactions t ON t.id = e.transaction_id
+             WHERE e.account = ?1 AND t.kind IN ('Wager', 'Win', 'Jackpot', 'Gamble')
+               AND t.created_at >= ?2",
+            params![player_account(player), unix_millis(since)],
+            |row| row.get(0),
+        )
+    }
+
+    /// Returns the coins wagered and won by the player on the spins and gambles settled since
+    /// `since`. A gamble counts its stake as wagered when lost and as won when won.
+    pub fn wagered_and_won(&self, player: &str, since: SystemTime) -> rusqlite::Result<(u64, u64)> {
+        self.connection.lock().unwrap().query_row(
+            "SELECT COALESCE(SUM(-MIN(e.amount, 0)), 0), COALESCE(SUM(MAX(e.amount, 0)), 0)
+             FROM entries e JOIN transactions t ON t.id = e.transaction_id
+             WHERE e.account = ?1 AND t.kind IN ('Wager', 'Win', 'Jackpot', 'Gamble')
+               AND t.created_at >= ?2",
+            params![player_account(player), unix_millis(since)],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+    }
+
+    /// Continues the play session of the player, or starts a new one if the player didn't log in
+    /// nor play for `break_after`, and returns the time it started. Logging in again or resuming
+    /// a session token therefore doesn't reset the session limits.
+    pub fn resume_play_session(
+        &self,
+        player: &str,
+        break_after: Duration,
+    ) -> rusqlite::Result<SystemTime> {
+        let now = SystemTime::now();
+
+        self.connection.lock().unwrap().query_row(
+            "INSERT INTO play_sessions (player, started_at, active_at) VALUES (?1, ?2, ?2)
+             ON CONFLICT (player) DO UPDATE SET
+                 started_at = IIF(active_at >= ?3, started_at, excluded.started_at),
+                 active_at = excluded.active_at
+             RETURNING started_at",
+            params![
+                player,
+                unix_millis(now),
+                unix_millis(now.checked_sub(break_after).unwrap_or(UNIX_EPOCH))
+            ],
+            |row| Ok(UNIX_EPOCH + Duration::from_millis(row.get(0)?)),
+        )
+    }
+
+    /// Returns the limits of the player. Pending limits whose delay has passed replace the current
+    /// ones.
+    pub fn limits(&self, player: &str) -> rusqlite::Result<LimitsStatus> {
+        current_limits(&self.connection.lock().unwrap(), player)
+    }
+
+    /// Sets the limits of the player: the stricter values apply right away, the looser ones after
+    /// `delay`. Returns the new limits.
+    pub fn set_limits(
+        &self,
+        player: &str,
+        limits: &PlayerLimits,
+        delay: Duration,
+    ) -> rusqlite::Result<LimitsStatus> {
+        let connection = self.connection.lock().unwrap();
+        let mut status = current_limits(&connection, player)?;
+
+        status.limits = status.limits.strictest(limits);
+        status.pending = (status.limits != *limits).then(|| PendingLimits {
+            limits: *limits,
+            effective_at: SystemTime::now() + delay,
+        });
+        write_limits(&connection, player, &status)?;
+
+        Ok(status)
+    }
+
+    /// Excludes the player from playing until `until`, unless already excluded for longer.
+    /// Returns the new limits.
+    pub fn self_exclude(&self, player: &str, until: SystemTime) -> rusqlite::Result<LimitsStatus> {
+        let connection = self.connection.lock().unwrap();
+        let mut status = current_limits(&connection, player)?;
+
+        status.excluded_until = Some(status.excluded_until.map_or(until, |u| u.max(until)));
+        write_limits(&connection, player, &status)?;
+
+        Ok(status)
+    }
+
+    /// Returns the provably fair seeds currently used by the player, if the mode is enabled.
+    pub fn active_seed(&self, player: &str) -> rusqlite::Result<Option<SeedPair>> {
+        self.connection
+            .lock()
+            .unwrap()
+            .query_row(
+                "SELECT server_seed, commitment, client_seed, last_nonce FROM fair_seeds
+                 WHERE player = ?1 AND revealed_at IS NULL",
+                params![player],
+                seed_pair,
+            )
+            .optional()
+    }
+
+    /// Reveals the active provably fair seeds of the player, if any, and replaces them with `next`
+    /// (`None` disables the mode). Returns the revealed seeds.
+    pub fn rotate_seed(
+        &self,
+        player: &str,
+        next: Option<&SeedPair>,
+    ) -> rusqlite::Result<Option<SeedPair>> {
+        let mut connection = self.connection.lock().unwrap();
+        let tx = connection.transaction()?;
+
+        let revealed = tx
+            .query_row(
+                "UPDATE fair_seeds SET revealed_at = unixepoch()
+                 WHERE player = ?1 AND revealed_at IS NULL
+                 RETURNING server_seed, commitment, client_seed, last_nonce",
+                params![player],
+                seed_pair,
+            )
+            .optional()?;
+        if let Some(next) = next {
+            tx.execute(
+                "INSERT INTO fair_seeds (commitment, player, server_seed, client_seed)
+                 VALUES (?1, ?2, ?3, ?4)",
+                params![next.commitment, player, next.server_seed, next.client_seed],
+            )?;
+        }
+        tx.commit()?;
+
+        Ok(revealed)
+    }
+
+    /// Marks the nonce as used with the active seeds of the given commitment, returning `false` if
+    /// the seeds were revealed or the nonce isn't greater than the last one used.
+    pub fn use_nonce(&self, commitment: &str, nonce: u64) -> rusqlite::Result<bool> {
+        let updated = self.connection.lock().unwrap().execute(
+            "UPDATE fair_seeds SET last_nonce = ?2
+             WHERE commitment = ?1 AND revealed_at IS NULL
+             AND (last_nonce IS NULL OR last_nonce < ?2)",
+            params![commitment, nonce],
+        )?;
+
+        Ok(updated > 0)
+    }
+
+    /// Returns the provably fair seeds of a commitment if they were revealed.
+    pub fn revealed_seed(&self, commitment: &str) -> rusqlite::Result<Option<SeedPair>> {
+        self.connection
+            .lock()
+            .unwrap()
+            .query_row(
+                "SELECT server_seed, commitment, client_seed, last_nonce FROM fair_seeds
+                 WHERE commitment = ?1 AND revealed_at IS NOT NULL",
+                params![commitment],
+                seed_pair,
+            )
+            .optional()
+    }
+
+    /// Returns the last `limit` entries of the player's statement, optionally for a single game,
+    /// most recent first.
+    pub fn player_statement(
+        &self,
+        player: &str,
+        game: Option<&str>,
+        limit: usize,
+    ) -> rusqlite::Result<Vec<LedgerEntry>> {
+        self.statement(&player_account(player), game, limit)
+    }
+
+    /// Returns the last `limit` entries of the house account of a game, across all players, most
+    /// recent first. The running balance is the house profit on the game.
+    pub fn game_statement(&self, game: &str, limit: usize) -> rusqlite::Result<Vec<LedgerEntry>> {
+        self.statement(&house_account(game), None, limit)
+    }
+
+    fn statement(
+        &self,
+        account: &str,
+        game: Option<&str>,
+        limit: usize,
+    ) -> rusqlite::Result<Vec<LedgerEntry>> {
+        let connection = self.connection.lock().unwrap();
+        // The running balance is computed over the whole account before filtering by game
+        let mut statement = connection.prepare(
+            "SELECT * FROM (
+                 SELECT t.id, t.created_at, t.player, t.kind, t.game, t.bet, t.stops, t.result,
+                        t.rng, t.proof, e.amount, SUM(e.amount) OVER (ORDER BY e.id) AS running, e.id AS entry_id
+                 FROM entries e JOIN transactions t ON t.id = e.transaction_id
+                 WHERE e.account = ?1
+             )
+             WHERE ?2 IS NULL OR game = ?2
+             ORDER BY entry_id DESC
+             LIMIT ?3",
+        )?;
+
+        let entries = statement
+            .query_map(params![account, game, limit], ledger_entry)?
+            .collect();
+        entries
+    }
+
+    /// Checks that every balance matches its ledger account and every transaction is balanced.
+    pub fn check(&self) -> rusqlite::Result<LedgerReport> {
+        let connection = self.connection.lock().unwrap();
+
+        let balance_mismatches = connection
+            .prepare(
+                "SELECT a.player, a.balance, COALESCE(SUM(e.amount), 0) AS ledger
+                 FROM accounts a LEFT JOIN entries e ON e.account = 'player:' || a.player
+                 GROUP BY a.player
+                 HAVING a.balance != ledger",
+            )?
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+            .collect::<rusqlite::Result<_>>()?;
+
+        let unbalanced_transactions = connection
+            .prepare(
+                "SELECT transaction_id FROM entries
+                 GROUP BY transaction_id
+                 HAVING SUM(amount) != 0",
+            )?
+            .query_map([], |row| row.get(0))?
+            .collect::<rusqlite::Result<_>>()?;
+
+        Ok(LedgerReport {
+            balance_mismatches,
+            unbalanced_transactions,
+        })
+    }
+}
+
+fn insert_transaction(
+    connection: &Connection,
+    player: &str,
+    kind: TransactionKind,
+    game: Option<&str>,
+    spin: Option<&SpinRecord>,
+    entries: &[(String, i64)],
+) -> rusqlite::Result<()> {
+    connection.execute(
+        "INSERT INTO transactions (created_at, player, kind, game, bet, stops, result, rng, proof)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+        params![
+            unix_millis(SystemTime::now()),
+            player,
+            kind.to_string(),
+            game,
+            spin.map(|s| s.bet),
+            serde_json::to_string(&spin.map_or(&vec![], |s| &s.stops)).unwrap(),
+            serde_json::to_string(&spin.map_or(&vec![], |s| &s.result)).unwrap(),
+            spin.map(|s| s.rng.as_str()),
+            spin.and_then(|s| s.proof.as_ref())
+                .map(|p| serde_json::to_string(p).unwrap()),
+        ],
+    )?;
+
+    let transaction_id = connection.last_insert_rowid();
+    for (account, amount) in entries {
+        connection.execute(
+            "INSERT INTO entries (transaction_id, account, amount) VALUES (?1, ?2, ?3)",
+            params![transaction_id, account, amount],
+        )?;
+    }
+
+    Ok(())
+}
+
+fn free_spins_left(
+    connection: &Connection,
+    player: &str,
+    game: &str,
+) -> rusqlite::Result<Option<FreeSpinsLeft>> {
+    connection
+        .query_row(
+            "SELECT remaining, bet FROM free_spins
+             WHERE player = ?1 AND game = ?2 AND remaining > 0",
+            params![player, game],
+            |row| {
+                Ok(FreeSpinsLeft {
+                    remaining: row.get(0)?,
+                    bet: row.get(1)?,
+                })
+            },
+        )
+        .optional()
+}
+
+// Pool of the progressive jackpot of a game in thousandths of a coin
+fn jackpot_pool(connection: &Connection, game: &str, seed: u64) -> rusqlite::Result<u64> {
+    connection.execute(
+        "INSERT OR IGNORE INTO jackpots (game, pool) VALUES (?1, ?2)",
+        params![game, seed * 1000],
+    )?;
+
+    connection.query_row(
+        "SELECT pool FROM jackpots WHERE game = ?1",
+        params![game],
+        |row| row.get(0),
+    )
+}
+
+fn current_limits(connection: &Connection, player: &str) -> rusqlite::Result<LimitsStatus> {
+    let mut status = read_limits(connection, player)?;
+
+    if let Some(pending) = status
+        .pending
+        .take_if(|pending| pending.effective_at <= SystemTime::now())
+    {
+        status.limits = pending.limits;
+        write_limits(connection, player, &status)?;
+    }
+
+    Ok(status)
+}
+
+fn read_limits(connection: &Connection, player: &str) -> rusqlite::Result<LimitsStatus> {
+    let invalid = |i, err: serde_json::Error| {
+        rusqlite::Error::FromSqlConversionFailure(i, rusqlite::types::Type::Text, err.into())
+    };
+
+    let row = connection
+        .query_row(
+            "SELECT limits, pending, pending_at, excluded_until FROM limits WHERE player = ?1",
+            params![player],
+            |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, Option<String>>(1)?,
+                    row.get::<_, Option<u64>>(2)?,
+                    row.get::<_, Option<u64>>(3)?,
+                ))
+            },
+        )
+        .optional()?;
+    let Some((limits, pending, pending_at, excluded_until)) = row else {
+        return Ok(LimitsStatus::default());
+    };
+
+    Ok(LimitsStatus {
+        limits: serde_json::from_str(&limits).map_err(|e| invalid(0, e))?,
+        pending: match (pending, pending_at) {
+            (Some(pending), Some(pending_at)) => Some(PendingLimits {
+                limits: serde_json::from_str(&pending).map_err(|e| invalid(1, e))?,
+                effective_at: UNIX_EPOCH + Duration::from_secs(pending_at),
+            }),
+            _ => None,
+        },
+        excluded_until: excluded_until.map(|until| UNIX_EPOCH + Duration::from_secs(until)),
+    })
+}
+
+fn write_limits(
+    connection: &Connection,
+    player: &str,
+    status: &LimitsStatus,
+) -> rusqlite::Result<()> {
+    connection.execute(
+        "INSERT OR REPLACE INTO limits (player, limits, pending, pending_at, excluded_until)
+         VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![
+            player,
+            serde_json::to_string(&status.limits).unwrap(),
+            status
+                .pending
+                .as_ref()
+                .map(|p| serde_json::to_string(&p.limits).unwrap()),
+            status
+                .pending
+                .as_ref()
+                .map(|p| unix_seconds(p.effective_at)),
+            status.excluded_until.map(unix_seconds),
+        ],
+    )?;
+
+    Ok(())
+}
+
+fn ledger_entry(row: &Row) -> rusqlite::Result<LedgerEntry> {
+    let invalid = |i, err: String| {
+        rusqlite::Error::FromSqlConversionFailure(i, rusqlite::types::Type::Text, err.into())
+    };
+
+    Ok(LedgerEntry {
+        id: row.get(0)?,
+        timestamp: UNIX_EPOCH + Duration::from_millis(row.get(1)?),
+        player: row.get(2)?,
+        kind: row
+            .get::<_, String>(3)?
+            .parse()
+            .map_err(|e| invalid(3, e))?,
+        game: row.get(4)?,
+        bet: row.get(5)?,
+        stops: serde_json::from_str(&row.get::<_, String>(6)?)
+            .map_err(|e| invalid(6, e.to_string()))?,
+        result: serde_json::from_str(&row.get::<_, String>(7)?)
+            .map_err(|e| invalid(7, e.to_string()))?,
+        rng: row.get(8)?,
+        proof: row
+            .get::<_, Option<String>>(9)?
+            .map(|p| serde_json::from_str(&p))
+            .transpose()
+            .map_err(|e| invalid(9, e.to_string()))?,
+        amount: row.get(10)?,
+        balance: row.get(11)?,
+    })
+}
+
+fn seed_pair(row: &Row) -> rusqlite::Result<SeedPair> {
+    Ok(SeedPair {
+        server_seed: row.get(0)?,
+        commitment: row.get(1)?,
+        client_seed: row.get(2)?,
+        last_nonce: row.get(3)?,
+    })
+}
+
+fn add_column_if_missing(
+    connection: &Connection,
+    table: &str,
+    column: &str,
+    definition: &str,
+) -> rusqlite::Result<()> {
+    let exists: bool = connection.query_row(
+        "SELECT COUNT(*) > 0 FROM pragma_table_info(?1) WHERE name = ?2",
+        params![table, column],
+        |row| row.get(0),
+    )?;
+    if !exists {
+        connection.execute(
+            &format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition),
+            [],
+        )?;
+    }
+
+    Ok(())
+}
+
+// Keeps the play session of the player going, see `AccountStore::resume_play_session`
+fn touch_play_session(connection: &Connection, player: &str) -> rusqlite::Result<()> {
+    connection.execute(
+        "UPDATE play_sessions SET active_at = ?2 WHERE player = ?1",
+        params![player, unix_millis(SystemTime::now())],
+    )?;
+
+    Ok(())
+}
+
+fn unix_seconds(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+fn unix_millis(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Opens an in-memory store with a player holding `balance` coins.
+    fn store_with_player(balance: u64) -> AccountStore {
+        let store = AccountStore::open(Path::new(":memory:")).unwrap();
+        store.register("alice", "hash", balance).unwrap();
+        store
+    }
+
+    fn spin(bet: u64, win: u64) -> SpinRecord {
+        SpinRecord {
+            game: "blaze7".to_string(),
+            bet,
+            stops: vec![0, 1, 2],
+            result: vec!["7".to_string(); 3],
+            win,
+            free_spin: false,
+            free_spins_won: 0,
+            rng: "chacha20".to_string(),
+            proof: None,
+            jackpot: None,
+        }
+    }
+
+    /// Amounts of the statement of an account, oldest first.
+    fn amounts(entries: Vec<LedgerEntry>) -> Vec<(TransactionKind, i64, i64)> {
+        entries
+            .into_iter()
+            .rev()
+            .map(|e| (e.kind, e.amount, e.balance))
+            .collect()
+    }
+
+    #[test]
+    fn spins_are_recorded_as_balanced_transactions() {
+        let store = store_with_player(100);
+
+        let settlement = store.settle_spin("alice", &spin(10, 25)).unwrap().unwrap();
+        assert_eq!(settlement.balance, 115);
+        assert_eq!(
+            store
+                .settle_spin("alice", &spin(5, 0))
+                .unwrap()
+                .unwrap()
+                .balance,
+            110
+        );
+
+        assert_eq!(store.balance("alice").unwrap(), Some(110));
+        assert_eq!(
+            amounts(store.player_statement("alice", None, 10).unwrap()),
+            [
+                (TransactionKind::Deposit, 100, 100),
+                (TransactionKind::Wager, -10, 90),
+                (TransactionKind::Win, 25, 115),
+                (TransactionKind::Wager, -5, 110),
+            ]
+        );
+        assert_eq!(
+            amounts(store.game_statement("blaze7", 10).unwrap()),
+            [
+                (TransactionKind::Wager, 10, 10),
+                (TransactionKind::Win, -25, -15),
+                (TransactionKind::Wager, 5, -10),
+            ]
+        );
+        assert!(store.check().unwrap().is_consistent());
+    }
+
+    #[test]
+    fn spins_the_balance_no_longer_covers_are_rejected() {
+        let store = store_with_player(100);
+
+        // Two sessions validated a spin of 80 coins against the same balance
+        assert_eq!(
+            store
+                .settle_spin("alice", &spin(80, 0))
+                .unwrap()
+                .unwrap()
+                .balance,
+            20
+        );
+        assert!(store.settle_spin("alice", &spin(80, 0)).unwrap().is_none());
+        assert!(store
+            .settle_spin("alice", &spin(21, 100))
+            .unwrap()
+            .is_none());
+
+        assert_eq!(store.balance("alice").unwrap(), Some(20));
+        assert_eq!(store.player_statement("alice", None, 10).unwrap().len(), 2);
+        assert!(store.check().unwrap().is_consistent());
+    }
+
+    #[test]
+    fn gambles_move_the_stake_between_the_player_and_the_house() {
+        let store = store_with_player(100);
+        store.settle_spin("alice", &spin(10, 30)).unwrap().unwrap();
+
+        assert_eq!(
+            store.settle_gamble("alice", "blaze7", 30, true).unwrap(),
+            Some(150)
+        );
+        assert_eq!(
+            store.settle_gamble("alice", "blaze7", 60, false).unwrap(),
+            Some(90)
+        );
+        // The stake was spent by another session since the gamble was validated
+        assert_eq!(
+            store.settle_gamble("alice", "blaze7", 91, true).unwrap(),
+            None
+        );
+
+        assert_eq!(
+            amounts(store.game_statement("blaze7", 10).unwrap()),
+            [
+                (TransactionKind::Wager, 10, 10),
+                (TransactionKind::Win, -30, -20),
+                (TransactionKind::Gamble, -30, -50),
+                (TransactionKind::Gamble, 60, 10),
+            ]
+        );
+        assert_eq!(store.net_loss("alice", UNIX_EPOCH).unwrap(), 10);
+        assert!(store.check().unwrap().is_consistent());
+    }
+
+    #[test]
+    fn free_spins_are_taken_and_retriggered() {
+        let store = store_with_player(100);
+        let free_spin = |win, free_spins_won| SpinRecord {
+            free_spin: true,
+            free_spins_won,
+            ..spin(10, win)
+        };
+
+        let awarding = SpinRecord {
+            free_spins_won: 2,
+            ..spin(10, 0)
+        };
+        assert_eq!(
+            store
+                .settle_spin("alice", &awarding)
+                .unwrap()
+                .unwrap()
+                .free_spins,
+            2
+        );
+        let left = FreeSpinsLeft {
+            remaining: 2,
+            bet: 10,
+        };
+        assert_eq!(store.free_spins("alice", "blaze7").unwrap(), Some(left));
+
+        // A free spin costs nothing, and a retrigger adds to the ones left
+        let settlement = store
+            .settle_spin("alice", &free_spin(40, 0))
+            .unwrap()
+            .unwrap();
+        assert_eq!((settlement.balance, settlement.free_spins), (130, 1));
+        let settlement = store
+            .settle_spin("alice", &free_spin(0, 2))
+            .unwrap()
+            .unwrap();
+        assert_eq!((settlement.balance, settlement.free_spins), (130, 2));
+        for left in [1, 0] {
+            let settlement = store
+                .settle_spin("alice", &free_spin(0, 0))
+                .unwrap()
+                .unwrap();
+            assert_eq!(settlement.free_spins, left);
+        }
+
+        // Another session played the last free spin
+        assert!(store
+            .settle_spin("alice", &free_spin(40, 0))
+            .unwrap()
+            .is_none());
+        assert_eq!(store.free_spins("alice", "blaze7").unwrap(), None);
+        assert_eq!(store.balance("alice").unwrap(), Some(130));
+        assert!(amounts(store.player_statement("alice", None, 10).unwrap())
+            .iter()
+            .filter(|(kind, ..)| *kind == TransactionKind::FreeSpin)
+            .all(|(_, amount, _)| *amount == 0));
+        assert!(store.check().unwrap().is_consistent());
+    }
+
+    #[test]
+    fn jackpot_pools_add_up_contributions_in_thousandths() {
+        let store = store_with_player(100);
+        let jackpot_spin = |bet, hit| SpinRecord {
+            jackpot: Some(JackpotSpin {
+                seed: 1000,
+                contribution: 2.5,
+                hit,
+            }),
+            ..spin(bet, 0)
+        };
+
+        assert_eq!(store.jackpot("blaze7", 1000).unwrap(), 1000);
+        // A quarter of a coin per spin
+        for jackpot in [1000, 1000, 1000, 1001] {
+            let settlement = store
+                .settle_spin("alice", &jackpot_spin(10, false))
+                .unwrap();
+            assert_eq!(settlement.unwrap().jackpot, Some(jackpot));
+        }
+        // A rejected spin doesn't contribute
+        assert!(store
+            .settle_spin("alice", &jackpot_spin(100, false))
+            .unwrap()
+            .is_none());
+        assert_eq!(store.jackpot("blaze7", 1000).unwrap(), 1001);
+
+        // The hit is paid on top of the win, and the fraction of a coin left stays in the pool
+        let settlement = store
+            .settle_spin("alice", &jackpot_spin(10, true))
+            .unwrap()
+            .unwrap();
+        assert_eq!(settlement.jackpot_win, 1001);
+        assert_eq!(settlement.jackpot, Some(1000));
+        assert_eq!(settlement.balance, 50 + 1001);
+        for jackpot in [1000, 1000, 1001] {
+            let settlement = store
+                .settle_spin("alice", &jackpot_spin(10, false))
+                .unwrap();
+            assert_eq!(settlement.unwrap().jackpot, Some(jackpot));
+        }
+
+        assert!(
+            amounts(store.player_statement("alice", None, 20).unwrap()).contains(&(
+                TransactionKind::Jackpot,
+                1001,
+                1051
+            ))
+        );
+        assert!(store.check().unwrap().is_consistent());
+    }
+}
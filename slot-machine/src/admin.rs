@@ -0,0 +1,119 @@
+//! Messages of the admin channel, used by `slotctl` to manage a running daemon.
+//!
+//! The daemon listens for admin connections on a UNIX socket only readable and writable by its
+//! user, so that access to the channel is controlled by the file permissions. Like the client
+//! protocol, each message is a single line of JSON, and every [`AdminCommand`] is answered by an
+//! [`AdminResponse`].
+
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+/// Admin socket path.
+pub const ADMIN_SOCKET: &str = "./data/admin.sock";
+
+/// Commands accepted on the admin channel.
+#[derive(Debug, Serialize, Deserialize)]
+pub enum AdminCommand {
+    /// Lists the connected clients.
+    Sessions,
+    /// Disconnects a client.
+    Kick {
+        /// Identifier of the client, as listed by [`AdminCommand::Sessions`].
+        session: u64,
+    },
+    /// Credits (positive `amount`) or debits (negative `amount`) a player account, recorded as an
+    /// adjustment in the ledger.
+    Adjust {
+        /// Player name of the account.
+        player: String,
+        /// Coins added to the balance.
+        amount: i64,
+    },
+    /// Sets the password of a player account, e.g. one created before authentication, and
+    /// revokes its session tokens.
+    SetPassword {
+        /// Player name of the account.
+        player: String,
+        /// New password, at least
+        /// [`MIN_PASSWORD_LENGTH`](crate::protocol::MIN_PASSWORD_LENGTH) characters.
+        password: String,
+    },
+    /// Lists the loaded games.
+    Games,
+    /// Serves a disabled game to the clients again.
+    EnableGame {
+        /// Identifier of the game.
+        game: String,
+    },
+    /// Stops serving a game to the clients until it is enabled again or the daemon restarts.
+    DisableGame {
+        /// Identifier of the game.
+        game: String,
+    },
+    /// Reloads the games folder.
+    Reload,
+    /// Renders the metrics in the Prometheus text format.
+    Metrics,
+    /// Disconnects the clients and stops the daemon.
+    Shutdown,
+}
+
+/// A connected client, as listed by [`AdminCommand::Sessions`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionInfo {
+    /// Identifier of the client.
+    pub id: u64,
+    /// Address of the client.
+    pub peer: String,
+    /// Player the client is logged in as.
+    pub player: Option<String>,
+    /// Balance of the player account.
+    pub balance: Option<u64>,
+    /// Time since the client connected.
+    pub connected_for: Duration,
+}
+
+/// A loaded game, as listed by [`AdminCommand::Games`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GameStatus {
+    /// Identifier of the game.
+    pub id: String,
+    /// Display name of the game.
+    pub name: String,
+    /// Whether the game is served to the clients.
+    pub enabled: bool,
+}
+
+/// Responses sent on the admin channel.
+#[derive(Debug, Serialize, Deserialize)]
+pub enum AdminResponse {
+    /// Answer to [`AdminCommand::Sessions`], sorted by identifier.
+    Sessions(Vec<SessionInfo>),
+    /// Answer to [`AdminCommand::Adjust`] with the new balance of the player.
+    Balance {
+        /// Player name of the account.
+        player: String,
+        /// Balance after the adjustment.
+        balance: u64,
+    },
+    /// Answer to [`AdminCommand::Games`], sorted by identifier.
+    Games(Vec<GameStatus>),
+    /// Answer to [`AdminCommand::Reload`] with the identifiers of the changed games.
+    Reloaded {
+        /// Games loaded for the first time.
+        added: Vec<String>,
+        /// Games whose files changed.
+        updated: Vec<String>,
+        /// Games whose folder was removed.
+        removed: Vec<String>,
+        /// Games that could not be loaded, with the reason.
+        failed: Vec<(String, String)>,
+    },
+    /// Answer to [`AdminCommand::Metrics`].
+    Metrics(String),
+    /// The command was executed.
+    Done,
+    /// The command could not be executed, with the reason.
+    Error(String),
+}
@@ -0,0 +1,104 @@
+//! Audit log of every spin played on the daemon, written as JSON lines.
+//!
+//! Each [`AuditRecord`] holds everything needed to evaluate the spin again: the reel stops, the
+//! generator and its seed with the index of the spin in its sequence, and the symbols, combo and
+//! payout computed by the daemon at the time. The `replay` binary evaluates the log against the
+//! current games to catch regressions in the win calculation or changes to the game files.
+//!
+//! The seeds of the generators are written as is, the log must stay on the server.
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+
+use crate::fairness::SpinProof;
+use crate::game::RespinStep;
+
+/// Audit log path.
+pub const AUDIT_LOG: &str = "./data/audit.jsonl";
+
+/// A spin recorded in the audit log.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditRecord {
+    /// Time at which the spin was played.
+    pub timestamp: SystemTime,
+    /// Identifier of the client connection, unique while the daemon runs.
+    pub session: u64,
+    /// Player who made the spin.
+    pub player: String,
+    /// Game string identifier (subfolder name in `GAMES_FOLDER`).
+    pub game: String,
+    /// Bet size, as the index of the payouts vector of the par table.
+    pub bet: usize,
+    /// Generator that drew the spin as a [`RngSpec`](crate::rng::RngSpec) with its actual seed,
+    /// or [`FAIR_RNG`](crate::fairness::FAIR_RNG).
+    pub rng: String,
+    /// Index of the spin in the sequence of the generator, `None` for provably fair spins.
+    pub draw: Option<u64>,
+    /// Number of stops drawn for the spin and number of rows they were drawn from (see
+    /// [`Game::draw_size`](crate::game::Game::draw_size)), `None` for provably fair spins.
+    #[serde(default)]
+    pub draw_size: Option<(usize, usize)>,
+    /// Inputs of the spin if it was provably fair.
+    pub proof: Option<SpinProof>,
+    /// Reel stops drawn.
+    pub stops: Vec<usize>,
+    /// Respins triggered by the stops, the last one giving the symbols of the result.
+    #[serde(default)]
+    pub respins: Vec<RespinStep>,
+    /// Symbols displayed by the result of the spin.
+    pub symbols: Vec<String>,
+    /// Winning combo of the paytable matched by the symbols, if any.
+    pub combo: Option<Vec<String>>,
+    /// Amount won with the combo, multiplied for a free spin.
+    pub win: u64,
+    /// Whether the spin was a free spin, evaluated with the free spins reels and multiplier.
+    #[serde(default)]
+    pub free_spin: bool,
+    /// Amount of the progressive jackpot won, on top of `win`.
+    #[serde(default)]
+    pub jackpot: u64,
+    /// Balance of the player after the spin.
+    pub balance: u64,
+}
+
+/// Append-only writer of the audit log.
+pub struct AuditLog {
+    file: Mutex<File>,
+}
+
+impl AuditLog {
+    /// Opens (or creates) the audit log at `path` for appending.
+    pub fn open(path: &Path) -> io::Result<Self> {
+        Ok(Self {
+            file: Mutex::new(OpenOptions::new().create(true).append(true).open(path)?),
+        })
+    }
+
+    /// Appends a spin to the log as a single line, synced to the disk.
+    pub fn append(&self, record: &AuditRecord) -> io::Result<()> {
+        let line = serde_json::to_string(record)? + "\n";
+        let mut file = self.file.lock().unwrap();
+        file.write_all(line.as_bytes())?;
+        file.sync_data()
+    }
+}
+
+/// Reads every record of the audit log at `path`, with the line number of each one. Lines that
+/// can't be parsed are returned as errors.
+pub fn read_audit_log(
+    path: &Path,
+) -> io::Result<impl Iterator<Item = (usize, io::Result<AuditRecord>)>> {
+    let reader = BufReader::new(File::open(path)?);
+
+    Ok(reader.lines().enumerate().map(|(i, line)| {
+        (
+            i + 1,
+            line.and_then(|l| serde_json::from_str(&l).map_err(io::Error::from)),
+        )
+    }))
+}
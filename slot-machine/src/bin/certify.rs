@@ -0,0 +1,290 @@
+//! Run a battery of statistical tests on the reel stops drawn for each game, as done when
+//! certifying the random number generator of a slot machine.
+//!
+//! ```text
+//! certify [--games <folder>] [<kind>[:<seed>]] [spins]
+//!                 Certify the given generator (unseeded ChaCha20 by default) on the games of the
+//!                 folder (`GAMES_FOLDER` by default)
+//! ```
+//!
+//! The stops of each reel are drawn exactly like the daemon does, along with the stops of the
+//! respins drawn with them, for the spins and the free spins of each game. The stops drawn for
+//! each reel go through the following tests, which all fail if their p-value is below
+//! [`SIGNIFICANCE`]:
+//! - Chi-square: every stop of the reel is drawn equally often.
+//! - Serial correlation: a stop doesn't depend on the previous one (lag-1 autocorrelation).
+//! - Runs: the stops don't stay above or below the middle of the reel for too long or too short.
+//! - Gap: the gaps between two stops in the first half of the reel follow a geometric distribution.
+//!
+//! Even a perfect generator fails a test once in a while, a failure should be confirmed by running
+//! the certification again with another seed. Run it with `--release`, the default number of spins
+//! takes a while otherwise.
+use std::process::ExitCode;
+
+use slot_machine::game::load_games;
+use slot_machine::rng::{RngSpec, SlotRng};
+use slot_machine::utils::take_option;
+use slot_machine::GAMES_FOLDER;
+
+const DEFAULT_SPINS: usize = 1_000_000;
+/// Probability for a single test to fail with a perfect generator. It is kept low as every game
+/// runs a handful of tests per reel.
+const SIGNIFICANCE: f64 = 0.001;
+/// Gaps at least this long are counted together in the gap test.
+const MAX_GAP: usize = 16;
+
+/// Result of a statistical test.
+struct TestResult {
+    name: &'static str,
+    statistic: f64,
+    p_value: f64,
+}
+
+impl TestResult {
+    fn passed(&self) -> bool {
+        self.p_value >= SIGNIFICANCE
+    }
+}
+
+/// Upper tail probability of the standard normal distribution.
+fn normal_sf(z: f64) -> f64 {
+    // Abramowitz and Stegun approximation of erfc, accurate to 1.5e-7
+    let x = z.abs() / std::f64::consts::SQRT_2;
+    let t = 1.0 / (1.0 + 0.3275911 * x);
+    let poly = t
+        * (0.254829592
+            + t * (-0.284496736 + t * (1.421413741 + t * (-1.453152027 + t * 1.061405429))));
+    let erfc = poly * (-x * x).exp();
+
+    if z >= 0.0 {
+        erfc / 2.0
+    } else {
+        1.0 - erfc / 2.0
+    }
+}
+
+/// Two-sided p-value of a standard normal statistic.
+fn normal_p_value(z: f64) -> f64 {
+    (2.0 * normal_sf(z.abs())).min(1.0)
+}
+
+/// Upper tail probability of the chi-square distribution with `df` degrees of freedom, using the
+/// Wilson-Hilferty approximation.
+fn chi_square_sf(x: f64, df: f64) -> f64 {
+    let variance = 2.0 / (9.0 * df);
+    normal_sf(((x / df).cbrt() - (1.0 - variance)) / variance.sqrt())
+}
+
+/// Pearson's chi-square statistic of the observed counts against the expected ones.
+fn chi_square(observed: &[usize], expected: &[f64]) -> f64 {
+    observed
+        .iter()
+        .zip(expected)
+        .map(|(o, e)| (*o as f64 - e).powi(2) / e)
+        .sum()
+}
+
+fn uniformity_test(draws: &[usize], stops: usize) -> TestResult {
+    let mut counts = vec![0; stops];
+    draws.iter().for_each(|d| counts[*d] += 1);
+
+    let statistic = chi_square(&counts, &vec![draws.len() as f64 / stops as f64; stops]);
+    TestResult {
+        name: "Chi-square",
+        statistic,
+        p_value: chi_square_sf(statistic, (stops - 1) as f64),
+    }
+}
+
+fn serial_correlation_test(draws: &[usize]) -> TestResult {
+    let n = draws.len() as f64;
+    let mean = draws.iter().sum::<usize>() as f64 / n;
+    let variance: f64 = draws.iter().map(|d| (*d as f64 - mean).powi(2)).sum();
+    let covariance: f64 = draws
+        .windows(2)
+        .map(|w| (w[0] as f64 - mean) * (w[1] as f64 - mean))
+        .sum();
+
+    // The autocorrelation of independent draws is approximately normal with variance 1/n
+    let statistic = covariance / variance;
+    TestResult {
+        name: "Serial correlation",
+        statistic,
+        p_value: normal_p_value(statistic * n.sqrt()),
+    }
+}
+
+fn runs_test(draws: &[usize], stops: usize) -> TestResult {
+    // Draws on the middle stop of a reel with an odd number of stops are left out
+    let middle = (stops - 1) as f64 / 2.0;
+    let above: Vec<bool> = draws
+        .iter()
+        .filter(|d| **d as f64 != middle)
+        .map(|d| *d as f64 > middle)
+        .collect();
+
+    let n1 = above.iter().filter(|a| **a).count() as f64;
+    let n2 = above.len() as f64 - n1;
+    let n = n1 + n2;
+    let runs = 1 + above.windows(2).filter(|w| w[0] != w[1]).count();
+
+    let mean = 2.0 * n1 * n2 / n + 1.0;
+    let variance = 2.0 * n1 * n2 * (2.0 * n1 * n2 - n) / (n * n * (n - 1.0));
+    let statistic = (runs as f64 - mean) / variance.sqrt();
+    TestResult {
+        name: "Runs",
+        statistic,
+        p_value: normal_p_value(statistic),
+    }
+}
+
+fn gap_test(draws: &[usize], stops: usize) -> TestResult {
+    let hits = stops / 2;
+    let p = hits as f64 / stops as f64;
+
+    let mut counts = vec![0; MAX_GAP + 1];
+    let mut gap = None;
+    for draw in draws {
+        if *draw < hits {
+            if let Some(length) = gap {
+                counts[usize::min(length, MAX_GAP)] += 1;
+            }
+            gap = Some(0);
+        } else if let Some(length) = gap.as_mut() {
+            *length += 1;
+        }
+    }
+
+    // Geometric distribution of the gap lengths, the last bin holding the tail
+    let total = counts.iter().sum::<usize>() as f64;
+    let mut expected: Vec<f64> = (0..MAX_GAP)
+        .map(|length| total * p * (1.0 - p).powi(length as i32))
+        .collect();
+    expected.push(total * (1.0 - p).powi(MAX_GAP as i32));
+
+    let statistic = chi_square(&counts, &expected);
+    TestResult {
+        name: "Gap",
+        statistic,
+        p_value: chi_square_sf(statistic, MAX_GAP as f64),
+    }
+}
+
+/// Draws `spins` spins of `size` stops like the daemon does and tests the stops drawn at each
+/// position of the draws, returning whether they all passed. The positions past the `reels` of the
+/// spin are the ones of its respins.
+fn certify(spec: RngSpec, spins: usize, size: usize, stops: usize, reels: usize) -> bool {
+    let mut rng = SlotRng::new(spec);
+    let mut draws = vec![Vec::with_capacity(spins); size];
+    for _ in 0..spins {
+        for (position, stop) in rng.draw_stops(size, stops).into_iter().enumerate() {
+            draws[position].push(stop);
+        }
+    }
+
+    println!(
+        "{:<6} {:<20} {:>14} {:>10}  Result",
+        "Reel", "Test", "Statistic", "p-value"
+    );
+    println!("{:-<60}", "");
+
+    let mut passed = true;
+    for (position, draws) in draws.iter().enumerate() {
+        // Reel of the stop, followed by the respin it is drawn for if any
+        let reel = match position / reels {
+            0 => format!("{}", position % reels + 1),
+            respin => format!("{} r{}", position % reels + 1, respin),
+        };
+        for result in [
+            uniformity_test(draws, stops),
+            serial_correlation_test(draws),
+            runs_test(draws, stops),
+            gap_test(draws, stops),
+        ] {
+            println!(
+                "{:<6} {:<20} {:>14.6} {:>10.6}  {}",
+                reel,
+                result.name,
+                result.statistic,
+                result.p_value,
+                if result.passed() { "PASS" } else { "FAIL" }
+            );
+            passed &= result.passed();
+        }
+    }
+
+    passed
+}
+
+fn main() -> ExitCode {
+    const USAGE: &str = "Usage: certify [--games <folder>] [<kind>[:<seed>]] [spins]";
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let mut args: Vec<&str> = args.iter().map(String::as_str).collect();
+    let folder = match take_option(&mut args, "--games") {
+        Ok(folder) => folder.unwrap_or(GAMES_FOLDER),
+        Err(err) => {
+            eprintln!("{}\n{}", err, USAGE);
+            return ExitCode::FAILURE;
+        }
+    };
+    let mut args = args.into_iter();
+    let Ok(spec) = args
+        .next()
+        .map_or(Ok(RngSpec::default()), |s| s.parse::<RngSpec>())
+    else {
+        eprintln!("{}", USAGE);
+        return ExitCode::FAILURE;
+    };
+    let Ok(spins @ 2..) = args
+        .next()
+        .map_or(Ok(DEFAULT_SPINS), |s| s.parse::<usize>())
+    else {
+        eprintln!("Invalid number of spins");
+        return ExitCode::FAILURE;
+    };
+
+    let games = load_games(folder).expect("Failed to load games");
+    let mut ids: Vec<&String> = games.keys().collect();
+    ids.sort();
+
+    let mut failed_games = 0;
+    for id in ids {
+        let game = &games[id];
+        let mut passed = true;
+
+        // Free spins draw from their own reels, which may have another number of stops
+        for free_spin in [false, true] {
+            if free_spin && game.free_spins.is_none() {
+                continue;
+            }
+            let (size, stops) = game.draw_size(free_spin);
+            let reels = game.spin_table(free_spin).0.reels[0].len();
+
+            println!(
+                "[*] Certifying the {} of \"{}\" with {} ({} spins, {} stops of {} drawn each)",
+                if free_spin { "free spins" } else { "spins" },
+                id,
+                spec,
+                spins,
+                size,
+                stops
+            );
+            passed &= certify(spec, spins, size, stops, reels);
+        }
+
+        if passed {
+            println!("[+] \"{}\" passed every test\n", id);
+        } else {
+            println!("[!] \"{}\" failed certification\n", id);
+            failed_games += 1;
+        }
+    }
+
+    if failed_games > 0 {
+        println!("[!] {} games failed certification", failed_games);
+        return ExitCode::FAILURE;
+    }
+    println!("[+] Every game passed certification");
+
+    ExitCode::SUCCESS
+}
@@ -1,10 +1,13 @@
 use cfonts::{Colors, Rgb};
 use image::{io::Reader as ImageReader, GenericImageView};
-use slot_machine::{protocol::Status, GAMES_FOLDER};
+use slot_machine::{
+    game::{Gamble, GameDefinition, GameInfo, RespinStep},
+    protocol::{EventKind, Status},
+    transport::{Address, Connection},
+};
 use std::{
-    collections::HashMap,
+    collections::{HashMap, VecDeque},
     fs, io, panic,
-    path::{Path, PathBuf},
     time::{Duration, Instant},
 };
 
@@ -16,6 +19,7 @@ use crossterm::{
 use ratatui::{prelude::Rect, style::Color, widgets::ListState};
 use serde::{Deserialize, Serialize};
 use serde_with::{serde_as, DisplayFromStr};
+use tracing::{debug, info};
 
 pub type CrosstermTerminal = ratatui::Terminal<ratatui::backend::CrosstermBackend<std::io::Stderr>>;
 
@@ -24,6 +28,7 @@ pub const FRAMES_PER_SECONDS: u64 = 60;
 pub const ANIMATION_WAIT_TIME: Duration = Duration::from_millis(400);
 pub const ANIMATION_SKIP_TIMEOUT: Duration = Duration::from_millis(200);
 pub const SERVER_PING_TIMEOUT: Duration = Duration::from_secs(5);
+pub const RECONNECT_INTERVAL: Duration = Duration::from_secs(2);
 
 pub const SPIN_BASE_SPEED: f64 = 3.0;
 pub const REEL_SPEED_FACTOR: f64 = 1.08;
@@ -109,11 +114,28 @@ pub enum AnimationState {
 
 #[derive(Debug, PartialEq, Eq)]
 pub enum Screen {
+    Login,
     MainMenu,
     Game,
     Help,
 }
 
+#[derive(Debug, Default, PartialEq, Eq)]
+pub enum LoginField {
+    #[default]
+    Player,
+    Password,
+}
+
+#[derive(Debug, Default)]
+pub struct LoginForm {
+    pub player: String,
+    pub password: String,
+    pub focus: LoginField,
+    pub message: String,
+    pub token: Option<String>,
+}
+
 #[derive(Debug)]
 pub struct State {
     pub symbols_mapping: HashMap<String, SpinSymbol>,
@@ -131,11 +153,30 @@ pub struct State {
     pub next_win: u64,
     pub balance: u64,
     pub next_balance: u64,
-    pub available_games: Vec<String>,
+    pub login: LoginForm,
+    pub available_games: Vec<GameInfo>,
     pub selected_game: ListState,
+    pub game_info: GameInfo,
     pub active_screen: Screen,
     pub daemon_status: Status,
+    pub last_heartbeat: Instant,
+    // Next attempt to reconnect once the connection to the server is lost
+    pub reconnect_at: Option<Instant>,
     pub title_text_bold_line: isize,
+    // Rejected spin or reality check shown in the game screen until the next spin
+    pub notice: String,
+    // Progressive jackpots of the games initialized, kept up to date by the server events
+    pub jackpots: HashMap<String, u64>,
+    // Free spins left on the game, drawn from their own reels if the game has them
+    pub free_spins: u32,
+    pub game_reels: Vec<Vec<String>>,
+    pub free_spins_reels: Option<Vec<Vec<String>>>,
+    pub showing_free_spins_reels: bool,
+    // Respins of the spin in progress, animated one after the other once the reels stop
+    pub respins: VecDeque<RespinStep>,
+    // Gamble of the game and number of gambles offered on the current win
+    pub gamble: Option<Gamble>,
+    pub gambles: u32,
 }
 
 impl Default for State {
@@ -156,11 +197,24 @@ impl Default for State {
             next_win: 0,
             balance: 0,
             next_balance: 0,
+            login: LoginForm::default(),
             available_games: vec![],
             selected_game: ListState::default(),
+            game_info: GameInfo::default(),
             active_screen: Screen::MainMenu,
             daemon_status: Status::default(),
+            last_heartbeat: Instant::now(),
+            reconnect_at: None,
             title_text_bold_line: -1,
+            notice: String::new(),
+            jackpots: HashMap::new(),
+            free_spins: 0,
+            game_reels: vec![],
+            free_spins_reels: None,
+            showing_free_spins_reels: false,
+            respins: VecDeque::new(),
+            gamble: None,
+            gambles: 0,
         }
     }
 }
@@ -168,7 +222,9 @@ impl Default for State {
 impl State {
     pub fn current_game(&self) -> Option<String> {
         let selected_game = self.selected_game.selected()?;
-        self.available_games.get(selected_game).cloned()
+        self.available_games
+            .get(selected_game)
+            .map(|game| game.id.clone())
     }
 }
 
@@ -177,6 +233,7 @@ pub struct App {
     terminal: CrosstermTerminal,
     pub events: EventHandler,
     pub client: StreamHandler,
+    address: Address,
     pub should_quit: bool,
     pub state: State,
 }
@@ -191,30 +248,118 @@ impl App {
         self.should_quit = true;
     }
 
-    pub fn new(terminal: CrosstermTerminal, events: EventHandler, client: StreamHandler) -> Self {
+    pub fn new(
+        terminal: CrosstermTerminal,
+        events: EventHandler,
+        client: StreamHandler,
+        address: Address,
+    ) -> Self {
         Self {
             terminal,
             events,
             client,
+            address,
             should_quit: false,
             state: State { ..State::default() },
         }
     }
 
-    pub fn init_menu(&mut self) {
-        // TODO: Find clean way to periodically query server status and act accordingly on lost connection
+    pub fn init_login(&mut self) {
+        self.subscribe();
+        self.state.active_screen = Screen::Login;
+    }
+
+    fn subscribe(&mut self) {
+        // Status is refreshed on every heartbeat, see `update_connection` for the lost connection detection
+        self.client.send_subscribe_message(vec![
+            EventKind::Balance,
+            EventKind::Jackpot,
+            EventKind::Games,
+            EventKind::Shutdown,
+            EventKind::Heartbeat,
+            EventKind::RealityCheck,
+        ]);
         self.client.send_status_message();
+    }
+
+    /// Connects to the server again after the connection was lost, resuming the player session if
+    /// logged in. Returns whether the server could be reached.
+    pub fn reconnect(&mut self) -> bool {
+        let stream = match Connection::connect(&self.address) {
+            Ok(stream) => stream,
+            Err(err) => {
+                debug!(address = %self.address, error = %err, "Could not reconnect");
+                return false;
+            }
+        };
+        info!(address = %self.address, "Reconnected");
+        self.client = StreamHandler::new(stream);
+        self.subscribe();
+        if let Some(token) = self.state.login.token.clone() {
+            self.client.send_resume_message(token);
+        }
+
+        true
+    }
+
+    pub fn init_menu(&mut self) {
+        self.state.active_screen = Screen::MainMenu;
         self.load_games();
     }
 
+    /// Goes back to the main menu, keeping the player session and the last selected game.
+    pub fn reset_menu(&mut self) {
+        let previous_game = self.state.selected_game.selected();
+        let login = std::mem::take(&mut self.state.login);
+        let daemon_status = std::mem::take(&mut self.state.daemon_status);
+        self.state = State {
+            login,
+            daemon_status,
+            ..State::default()
+        };
+        self.init_menu();
+        self.state.selected_game.select(previous_game);
+    }
+
+    /// Goes back to the login screen, e.g. when the session expired.
+    pub fn reset_login(&mut self, message: String) {
+        self.reset_menu();
+        self.state.login.token = None;
+        self.state.login.message = message;
+        self.state.active_screen = Screen::Login;
+    }
+
     pub fn init_game(&mut self, game: String) {
-        self.client.send_init_message(game.to_string());
-        self.load_symbols_mapping(
-            Path::new(GAMES_FOLDER)
-                .join(game.to_string())
-                .join("display.csv"),
-        );
-        self.load_reels(Path::new(GAMES_FOLDER).join(game).join("reels.csv"));
+        self.client.send_get_game_message(game.to_string());
+        self.client.send_init_message(game);
+    }
+
+    pub fn load_game(&mut self, definition: GameDefinition) {
+        self.load_symbols_mapping(&definition.display_symbols);
+        self.load_reels(&definition.reels);
+        self.state.game_reels = definition.reels;
+        self.state.free_spins_reels = definition.free_spins_reels;
+        self.state.showing_free_spins_reels = false;
+        self.state.gamble = definition.gamble;
+        self.state.gambles = 0;
+        self.state.game_info = definition.info;
+    }
+
+    /// Shows the reels the next spin is drawn from, which differ during the free spins of some
+    /// games.
+    pub fn load_spin_reels(&mut self) {
+        let free_spins = self.state.free_spins > 0 && self.state.free_spins_reels.is_some();
+        if free_spins == self.state.showing_free_spins_reels {
+            return;
+        }
+
+        let reels = if free_spins {
+            self.state.free_spins_reels.clone().unwrap()
+        } else {
+            self.state.game_reels.clone()
+        };
+        self.load_reels(&reels);
+        self.state.showing_free_spins_reels = free_spins;
     }
 
     fn load_spin_symbol(&self, symbol: &Symbol) -> Result<SpinSymbol> {
@@ -236,27 +381,27 @@ impl App {
         })
     }
 
-    pub fn load_symbols_mapping(&mut self, path: PathBuf) {
-        let mut rdr = csv::Reader::from_path(path).unwrap();
-
+    pub fn load_symbols_mapping(&mut self, display_symbols: &HashMap<String, String>) {
         let f = fs::read_to_string(JSON_SYMBOLS_FILE).expect("Unable to read file");
         let symbols: Vec<Symbol> = serde_json::from_str(&f).unwrap();
 
-        self.state.symbols_mapping = HashMap::from_iter(rdr.deserialize().map(|r| {
-            let (symbol, display): (String, String) = r.unwrap();
-            (
-                symbol,
-                self.load_spin_symbol(symbols.iter().find(|s| s.name == display).unwrap())
-                    .unwrap(),
-            )
-        }));
+        self.state.symbols_mapping =
+            HashMap::from_iter(display_symbols.iter().map(|(symbol, display)| {
+                (
+                    symbol.to_string(),
+                    self.load_spin_symbol(symbols.iter().find(|s| s.name == *display).unwrap())
+                        .unwrap(),
+                )
+            }));
     }
 
-    pub fn load_reels(&mut self, path: PathBuf) {
-        let rdr = csv::Reader::from_path(path).unwrap();
-        let records = rdr.into_deserialize::<Vec<String>>().collect::<Vec<_>>();
-
-        self.state.n_reels = records.first().unwrap().as_ref().unwrap().len() as u64;
+    pub fn load_reels(&mut self, reels: &[Vec<String>]) {
+        self.state.n_reels = reels.first().unwrap().len() as u64;
+        // The definition is loaded again when the game is updated on the server
+        self.state.reels_symbols.clear();
+        self.state.scroll_positions.clear();
+        self.state.spin_indexes.clear();
+        self.state.spin_targets.clear();
 
         for _ in 1..=self.state.n_reels {
             self.state.reels_symbols.push(vec![]);
@@ -265,9 +410,7 @@ impl App {
             self.state.spin_targets.push((0, false));
         }
 
-        for result in records {
-            let row: Vec<String> = result.unwrap();
-
+        for row in reels {
             row.iter().enumerate().for_each(|(i, s)| {
                 self.state.reels_symbols[i].push(s.to_string());
             });
@@ -275,14 +418,31 @@ impl App {
     }
 
     pub fn load_games(&mut self) {
-        // TODO: Check for errors / empty directory / etc.
-        self.state.available_games = fs::read_dir(GAMES_FOLDER)
-            .unwrap()
-            .filter(|p| p.as_ref().unwrap().metadata().unwrap().is_dir())
-            .map(|p| p.unwrap().file_name().into_string().unwrap())
-            .collect();
-        //app.state.available_games.extend_from_slice(&["test1".to_string(), "test2".to_string(), "test3".to_string()]);
-        self.state.selected_game.select(Some(0));
+        self.client.send_list_games_message();
+    }
+
+    pub fn update_games(&mut self, games: Vec<GameInfo>) {
+        let current_game = self.state.current_game();
+        self.state.available_games = games;
+
+        // Games may have been added or removed before the selected one
+        let selected = current_game.as_ref().and_then(|id| {
+            self.state
+                .available_games
+                .iter()
+                .position(|game| game.id == *id)
+        });
+        self.state
+            .selected_game
+            .select(selected.or((!self.state.available_games.is_empty()).then_some(0)));
+
+        if matches!(self.state.active_screen, Screen::Game | Screen::Help) {
+            match (selected, current_game) {
+                // The game may have been updated, reload its definition
+                (Some(_), Some(game)) => self.client.send_get_game_message(game),
+                _ => self.state.active_screen = Screen::MainMenu,
+            }
+        }
     }
 
     pub fn enter(&mut self) -> Result<()> {
@@ -298,7 +458,7 @@ impl App {
         self.terminal.hide_cursor()?;
         self.terminal.clear()?;
 
-        self.init_menu();
+        self.init_login();
         Ok(())
     }
 
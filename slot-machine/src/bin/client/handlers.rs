@@ -1,26 +1,27 @@
 use std::{
-    io::{BufRead, Read},
-    os::unix::net::UnixStream,
-    sync::mpsc,
+    collections::VecDeque,
+    sync::{mpsc, Arc, Mutex},
     thread,
     time::{Duration, Instant, SystemTime},
 };
 
 use anyhow::Result;
-use crossterm::event::{self, Event as CrosstermEvent, KeyEvent, MouseEvent};
+use crossterm::event::{self, Event as CrosstermEvent, KeyEvent};
 use slot_machine::{
-    protocol::{ClientCommand, ServerResponse, Status},
-    utils::send_socket_message,
-    MAX_BYTES_READ,
+    game::{GambleGuess, GameDefinition, GameInfo, RespinStep},
+    protocol::{ClientCommand, EventKind, ServerEvent, ServerResponse, Status},
+    transport::Connection,
+    utils::{read_socket_message, send_socket_message},
 };
+use tracing::warn;
 
 #[derive(Clone, Debug)]
 pub enum Event {
     Noop,
     Tick,
     Key(KeyEvent),
-    Mouse(MouseEvent),
-    Resize(u16, u16),
+    Mouse,
+    Resize,
 }
 
 #[derive(Debug)]
@@ -48,8 +49,8 @@ impl EventHandler {
                                 Ok(()) // ignore KeyEventKind::Release on windows
                             }
                         }
-                        CrosstermEvent::Mouse(e) => sender.send(Event::Mouse(e)),
-                        CrosstermEvent::Resize(w, h) => sender.send(Event::Resize(w, h)),
+                        CrosstermEvent::Mouse(_) => sender.send(Event::Mouse),
+                        CrosstermEvent::Resize(_, _) => sender.send(Event::Resize),
                         _ => unimplemented!(),
                     }
                     .expect("failed to send terminal event")
@@ -73,101 +74,176 @@ impl EventHandler {
 #[derive(Clone, Debug)]
 pub enum Stream {
     Noop,
-    Init(u64, u64),
-    SpinResult(Vec<isize>, u64, u64),
-    ServerError(String),
+    Init(u64, u64, Option<u64>, u32),
+    SpinResult(Vec<isize>, u64, u64, u32, Vec<RespinStep>, u32),
+    GambleResult(GambleGuess, u64, u32, u64),
+    // Error code and message, with the name of the command it answers
+    ServerError(u64, String, Option<&'static str>),
+    LoggedIn(String, u64, String),
     Status(Status),
+    Games(Vec<GameInfo>),
+    Game(GameDefinition),
+    Event(ServerEvent),
+    Disconnected,
 }
 
 #[derive(Debug)]
 pub struct StreamHandler {
     receiver: mpsc::Receiver<Stream>,
-    stream: UnixStream,
+    stream: Connection,
+    // The daemon answers the commands in order, one response each
+    pending: Arc<Mutex<VecDeque<&'static str>>>,
 }
 
 impl StreamHandler {
-    pub fn new(stream: UnixStream) -> Self {
+    pub fn new(stream: Connection) -> Self {
         let (sender, receiver) = mpsc::channel();
+        let pending = Arc::new(Mutex::new(VecDeque::new()));
+        let answered = pending.clone();
         let mut _stream = stream.try_clone().expect("Could not clone client socket");
         thread::spawn(move || {
             let reader = _stream.try_clone().unwrap();
-            let mut reader = std::io::BufReader::new(reader).take(MAX_BYTES_READ);
-
-            loop {
-                let mut response = String::new();
-                let bytes_read = reader
-                    .read_line(&mut response)
-                    .expect("Could not read from buffer");
-
-                response = response.trim_end().to_string();
-
-                if bytes_read == 0 {
-                    // TODO: Think about how to deal with client socket errors (e.g. restart thread, server ping)
-                    break;
-                } else if let Ok(server_command) = serde_json::from_str::<ServerResponse>(&response)
-                {
+            let mut reader = std::io::BufReader::new(reader);
+
+            while let Ok(Some(response)) = read_socket_message(&mut reader) {
+                if let Ok(server_command) = serde_json::from_str::<ServerResponse>(&response) {
+                    let command = match server_command {
+                        ServerResponse::Event(_) => None,
+                        _ => answered.lock().unwrap().pop_front(),
+                    };
                     match server_command {
-                        ServerResponse::Init { balance, max_bet } => {
-                            let _ = sender.send(Stream::Init(balance, max_bet));
+                        ServerResponse::Init {
+                            balance,
+                            max_bet,
+                            jackpot,
+                            free_spins,
+                        } => {
+                            let _ =
+                                sender.send(Stream::Init(balance, max_bet, jackpot, free_spins));
                         }
                         ServerResponse::Spin {
                             win,
                             balance,
                             result,
+                            free_spins,
+                            respins,
+                            gambles,
+                            ..
                         } => {
                             let _ = sender.send(Stream::SpinResult(
                                 result.iter().map(|r| *r as isize).collect(),
                                 win,
                                 balance,
+                                free_spins,
+                                respins,
+                                gambles,
                             ));
                         }
+                        ServerResponse::Gamble {
+                            outcome,
+                            win,
+                            gambles,
+                            balance,
+                        } => {
+                            let _ =
+                                sender.send(Stream::GambleResult(outcome, win, gambles, balance));
+                        }
                         ServerResponse::Error { code, message } => {
-                            eprintln!("ERR{}: {}", code, message);
-                            let _ = sender.send(Stream::ServerError(message));
+                            warn!(code, %message, command, "Server error");
+                            let _ = sender.send(Stream::ServerError(code, message, command));
                         }
                         ServerResponse::Status(status) => {
                             let _ = sender.send(Stream::Status(status));
                         }
+                        ServerResponse::Games(games) => {
+                            let _ = sender.send(Stream::Games(games));
+                        }
+                        ServerResponse::Game(definition) => {
+                            let _ = sender.send(Stream::Game(definition));
+                        }
+                        ServerResponse::LoggedIn {
+                            player,
+                            balance,
+                            token,
+                            ..
+                        } => {
+                            let _ = sender.send(Stream::LoggedIn(player, balance, token));
+                        }
+                        ServerResponse::Subscribed { .. }
+                        | ServerResponse::Statement(_)
+                        | ServerResponse::FairPlay { .. }
+                        | ServerResponse::Limits(_) => {}
+                        ServerResponse::Event(event) => {
+                            let _ = sender.send(Stream::Event(event));
+                        }
                     }
                 }
             }
 
-            _stream
-                .shutdown(std::net::Shutdown::Both)
-                .expect("Could not shutdown client stream");
+            let _ = sender.send(Stream::Disconnected);
+
+            let _ = _stream.shutdown(std::net::Shutdown::Both);
         });
-        Self { receiver, stream }
+        Self {
+            receiver,
+            stream,
+            pending,
+        }
     }
 
     pub fn next(&self) -> Result<Stream> {
         Ok(self.receiver.try_recv().unwrap_or(Stream::Noop))
     }
 
+    // A failed write means the connection is lost, which the reader thread reports
+    fn send(&mut self, command: ClientCommand) {
+        self.pending.lock().unwrap().push_back(command.name());
+        let _ = send_socket_message(&mut self.stream, serde_json::to_string(&command).unwrap());
+    }
+
     pub fn send_spin_message(&mut self, game: String, bet: u64) {
-        send_socket_message(
-            &mut self.stream,
-            serde_json::to_string(&ClientCommand::Play {
-                game,
-                bet: bet.saturating_sub(1) as usize,
-            })
-            .unwrap(),
-        );
+        self.send(ClientCommand::Play {
+            game,
+            bet: bet.saturating_sub(1) as usize,
+            nonce: None,
+        });
+    }
+
+    pub fn send_gamble_message(&mut self, game: String, guess: GambleGuess) {
+        self.send(ClientCommand::Gamble { game, guess });
+    }
+
+    pub fn send_login_message(&mut self, player: String, password: String) {
+        self.send(ClientCommand::Login { player, password });
+    }
+
+    pub fn send_resume_message(&mut self, token: String) {
+        self.send(ClientCommand::Resume { token });
+    }
+
+    pub fn send_register_message(&mut self, player: String, password: String) {
+        self.send(ClientCommand::Register { player, password });
     }
 
     pub fn send_init_message(&mut self, game: String) {
-        send_socket_message(
-            &mut self.stream,
-            serde_json::to_string(&ClientCommand::Init { game }).unwrap(),
-        );
+        self.send(ClientCommand::Init { game });
+    }
+
+    pub fn send_list_games_message(&mut self) {
+        self.send(ClientCommand::ListGames);
+    }
+
+    pub fn send_get_game_message(&mut self, game: String) {
+        self.send(ClientCommand::GetGame { game });
     }
 
     pub fn send_status_message(&mut self) {
-        send_socket_message(
-            &mut self.stream,
-            serde_json::to_string(&ClientCommand::Status {
-                clock: SystemTime::now(),
-            })
-            .unwrap(),
-        );
+        self.send(ClientCommand::Status {
+            clock: SystemTime::now(),
+        });
+    }
+
+    pub fn send_subscribe_message(&mut self, events: Vec<EventKind>) {
+        self.send(ClientCommand::Subscribe { events });
     }
 }
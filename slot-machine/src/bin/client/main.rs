@@ -1,6 +1,5 @@
 /* TODO:
 - Require `gameinfo.json` for each game describing the name, author, version, help message, game color scheme, etc.
-- Logging facility for debug and error messages
 - Add sound and more visual effects for better engagment
 - Better handling of help screen (multiple key combinations, ESC / q capture (?))
 - Handle buffered input (i.e. continuously pressed keys)
@@ -8,16 +7,15 @@
 - Show balance status on game chooser (?)
 */
 
-use std::os::unix::net::UnixStream;
-
+use std::fs::OpenOptions;
 use std::time::Instant;
 
-use app::SERVER_PING_TIMEOUT;
 use ratatui::prelude::CrosstermBackend;
 use ratatui::Terminal;
-use slot_machine::SOCKET_PATH;
+use slot_machine::transport::{Address, Connection, DEFAULT_ADDRESS};
 
 use crate::app::App;
+use crate::app::Screen;
 
 use crate::app::EVENT_POLL_INTERVAL_MS;
 use crate::app::FRAMES_PER_SECONDS;
@@ -26,8 +24,14 @@ use crate::handlers::EventHandler;
 use crate::handlers::Stream;
 use crate::handlers::StreamHandler;
 
-use crate::updates::{update_keys, update_spin};
+use crate::updates::{
+    update_connection, update_disconnected, update_error, update_event, update_gamble, update_keys,
+    update_logged_in, update_spin,
+};
 use anyhow::Result;
+use tracing::level_filters::LevelFilter;
+use tracing::{error, info};
+use tracing_appender::non_blocking::WorkerGuard;
 
 mod app;
 mod handlers;
@@ -35,17 +39,55 @@ mod ui;
 mod updates;
 
 pub const JSON_SYMBOLS_FILE: &str = "./data/display_symbols.json";
+pub const DEFAULT_PLAYER: &str = "player";
+// The terminal is used by the interface, the logs go to a file
+pub const LOG_FILE: &str = "./data/client.log";
+// Environment variable holding the log level, `info` by default
+pub const LOG_LEVEL_VAR: &str = "SLOT_MACHINE_LOG";
+
+fn init_logging() -> Result<WorkerGuard> {
+    let level = std::env::var(LOG_LEVEL_VAR)
+        .ok()
+        .and_then(|level| level.parse().ok())
+        .unwrap_or(LevelFilter::INFO);
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(LOG_FILE)?;
+    let (writer, guard) = tracing_appender::non_blocking(file);
+
+    tracing_subscriber::fmt()
+        .with_max_level(level)
+        .with_target(false)
+        .with_ansi(false)
+        .with_writer(writer)
+        .init();
+
+    Ok(guard)
+}
 
 fn main() -> Result<()> {
+    let _log_guard = init_logging()?;
+
     let backend = CrosstermBackend::new(std::io::stderr());
     let terminal = Terminal::new(backend)?;
 
     let events = EventHandler::new(EVENT_POLL_INTERVAL_MS);
 
-    let stream = UnixStream::connect(SOCKET_PATH).unwrap();
+    let address: Address = std::env::args()
+        .nth(1)
+        .unwrap_or(DEFAULT_ADDRESS.to_string())
+        .parse()?;
+    let stream = Connection::connect(&address)
+        .inspect_err(|err| error!(%address, error = %err, "Could not connect"))?;
+    info!(%address, "Connected");
     let client = StreamHandler::new(stream);
 
-    let mut app = App::new(terminal, events, client);
+    let mut app = App::new(terminal, events, client, address);
+    app.state.login.player = std::env::args()
+        .nth(2)
+        .or(std::env::var("USER").ok())
+        .unwrap_or(DEFAULT_PLAYER.to_string());
     app.enter()?;
 
     let mut duration = Instant::now();
@@ -66,25 +108,52 @@ fn main() -> Result<()> {
 
         match app.events.next()? {
             Event::Noop => {}
-            Event::Tick => {}
+            Event::Tick => update_connection(&mut app),
             Event::Key(key_event) => update_keys(&mut app, key_event),
-            Event::Mouse(_) => {}
-            Event::Resize(_, _) => app.autoresize()?,
+            Event::Mouse => {}
+            Event::Resize => app.autoresize()?,
         };
 
         match app.client.next()? {
             Stream::Noop => {}
-            Stream::Init(balance, max_bet) => {
+            Stream::Init(balance, max_bet, jackpot, free_spins) => {
                 app.state.balance = balance;
                 app.state.next_balance = balance;
                 app.state.max_bet = max_bet;
                 app.state.bet = max_bet;
+                app.state.free_spins = free_spins;
+                if let Some(game) = app.state.current_game() {
+                    match jackpot {
+                        Some(amount) => app.state.jackpots.insert(game, amount),
+                        None => app.state.jackpots.remove(&game),
+                    };
+                }
+            }
+            Stream::SpinResult(spin, win, balance, free_spins, respins, gambles) => {
+                update_spin(&mut app, spin, win, balance, free_spins, respins, gambles)
+            }
+            Stream::GambleResult(outcome, win, gambles, balance) => {
+                update_gamble(&mut app, outcome, win, gambles, balance)
+            }
+            Stream::ServerError(code, message, command) => {
+                update_error(&mut app, code, message, command)
+            }
+            Stream::LoggedIn(player, balance, token) => {
+                update_logged_in(&mut app, player, balance, token)
             }
-            Stream::SpinResult(spin, win, balance) => update_spin(&mut app, spin, win, balance),
-            Stream::ServerError(_err) => {}
             Stream::Status(status) => {
                 app.state.daemon_status = status;
+                app.state.last_heartbeat = Instant::now();
+            }
+            Stream::Games(games) => app.update_games(games),
+            Stream::Game(definition) => {
+                app.load_game(definition);
+                if app.state.active_screen != Screen::Help {
+                    app.state.active_screen = Screen::Game;
+                }
             }
+            Stream::Event(event) => update_event(&mut app, event),
+            Stream::Disconnected => update_disconnected(&mut app),
         }
     }
 
@@ -1,18 +1,19 @@
 use cfonts::{Fonts, Options};
 use ratatui::{
+    prelude::Alignment,
     prelude::{Constraint, Direction, Layout, Rect},
-    style::{Color, Modifier},
+    style::{Color, Modifier, Style},
     symbols,
     widgets::{
         canvas::{Canvas, Points},
-        Block, Borders,
+        Block, Borders, Paragraph, Wrap,
     },
     Frame,
 };
 
 use crate::app::{
-    State, CFONTS_IDLE_COLORS, CFONTS_TEXT_COLORS, CFONTS_WIN_COLORS, SYMBOLS_DISPLAY_RATIO,
-    SYMBOLS_DISTANCE_RATIO,
+    State, CFONTS_IDLE_COLORS, CFONTS_TEXT_COLORS, CFONTS_WIN_COLORS, GAME_IDLE_COLOR,
+    GAME_WIN_COLOR, SYMBOLS_DISPLAY_RATIO, SYMBOLS_DISTANCE_RATIO,
 };
 
 use super::widgets::{AlignCenter, CFontTextWidget};
@@ -34,10 +35,17 @@ pub fn render_header(state: &State, layout: &Rect, frame: &mut Frame) {
         ..Options::default()
     };
 
-    for (k, text) in vec![
+    for (k, text) in [
         format!("Show help: [{}]", "F1"),
-        format!("{} created by {}", state.current_game().unwrap(), "Unknown"),
-        format!("Version: {}", "0.0.1"),
+        format!(
+            "{} created by {}",
+            state.game_info.name, state.game_info.author
+        ),
+        // Games with a progressive jackpot show it in place of their version
+        match state.jackpots.get(&state.game_info.id) {
+            Some(amount) => format!("Jackpot: {}", amount),
+            None => format!("Version: {}", state.game_info.version),
+        },
     ]
     .iter()
     .enumerate()
@@ -134,7 +142,7 @@ pub fn render_reels(state: &State, layout: &Rect, frame: &mut Frame) {
             .clone(),
         );
 
-        for (j, symbol) in vec![previous_symbol, current_symbol, next_symbol]
+        for (j, symbol) in [previous_symbol, current_symbol, next_symbol]
             .iter()
             .map(|s| state.symbols_mapping.get(s).unwrap())
             .enumerate()
@@ -177,6 +185,36 @@ pub fn render_win_overlay(state: &State, layout: &Rect, frame: &mut Frame) {
     frame.render_widget(win_text_widget, *layout);
 }
 
+pub fn render_gamble_prompt(state: &State, layout: &Rect, frame: &mut Frame) {
+    let Some(gamble) = &state.gamble else {
+        return;
+    };
+    // Outcomes are guessed with the key of their first letter
+    let [first, second] = gamble.kind.outcomes().map(|outcome| {
+        let name = outcome.to_string();
+        format!("[{}]{}", &name[..1], &name[1..])
+    });
+    let prompt_layout = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(0), Constraint::Length(3)])
+        .split(*layout);
+
+    frame.render_widget(
+        Paragraph::new(format!(
+            "Double or nothing ({} left): {} or {}, spin to collect",
+            state.gambles, first, second
+        ))
+        .style(Style::new().fg(Color::Rgb(
+            GAME_WIN_COLOR[0],
+            GAME_WIN_COLOR[1],
+            GAME_WIN_COLOR[2],
+        )))
+        .alignment(Alignment::Center)
+        .block(Block::new().borders(Borders::ALL)),
+        *prompt_layout.get(1).unwrap(),
+    );
+}
+
 pub fn render_footer(state: &State, layout: &Rect, frame: &mut Frame) {
     let footer_layout = Layout::default()
         .direction(Direction::Horizontal)
@@ -194,17 +232,26 @@ pub fn render_footer(state: &State, layout: &Rect, frame: &mut Frame) {
         ..Options::default()
     };
 
-    for (w_text, w_value, w_layout) in vec![
+    for (w_text, w_value, w_layout) in [
         (
             "Balance".to_string(),
             state.balance.to_string(),
-            *footer_layout.get(0).unwrap(),
-        ),
-        (
-            "Bet".to_string(),
-            state.bet.to_string(),
-            *footer_layout.get(2).unwrap(),
+            *footer_layout.first().unwrap(),
         ),
+        // The bet is the one of the spin that awarded the free spins
+        if state.free_spins > 0 {
+            (
+                "Free spins".to_string(),
+                state.free_spins.to_string(),
+                *footer_layout.get(2).unwrap(),
+            )
+        } else {
+            (
+                "Bet".to_string(),
+                state.bet.to_string(),
+                *footer_layout.get(2).unwrap(),
+            )
+        },
     ] {
         let w_info_layout = Layout::default()
             .direction(Direction::Vertical)
@@ -225,7 +272,22 @@ pub fn render_footer(state: &State, layout: &Rect, frame: &mut Frame) {
             })
             .align_center(AlignCenter::Both);
 
-        frame.render_widget(w_text_widget, *w_info_layout.get(0).unwrap());
+        frame.render_widget(w_text_widget, *w_info_layout.first().unwrap());
         frame.render_widget(w_value_widget, *w_info_layout.get(1).unwrap());
     }
+
+    if !state.notice.is_empty() {
+        frame.render_widget(
+            Paragraph::new(state.notice.as_str())
+                .style(Style::new().fg(Color::Rgb(
+                    GAME_IDLE_COLOR[0],
+                    GAME_IDLE_COLOR[1],
+                    GAME_IDLE_COLOR[2],
+                )))
+                .alignment(Alignment::Center)
+                .wrap(Wrap { trim: true })
+                .block(Block::new().borders(Borders::ALL)),
+            *footer_layout.get(1).unwrap(),
+        );
+    }
 }
@@ -0,0 +1,86 @@
+use ratatui::{
+    prelude::{Alignment, Constraint, Direction, Layout, Rect},
+    style::{Color, Modifier, Style, Stylize},
+    text::{Line, Span},
+    widgets::{Block, Borders, Paragraph},
+    Frame,
+};
+
+use crate::app::{LoginField, State, PRIMARY_TEXT_COLOR, SECONDARY_TEXT_COLOR};
+
+const LOGIN_FORM_WIDTH: u16 = 60;
+const LOGIN_FORM_HEIGHT: u16 = 9;
+
+pub fn render_login_form(state: &State, layout: &Rect, frame: &mut Frame) {
+    let label_style = Style::new().fg(Color::Rgb(
+        PRIMARY_TEXT_COLOR[0],
+        PRIMARY_TEXT_COLOR[1],
+        PRIMARY_TEXT_COLOR[2],
+    ));
+    let focused_style = Style::default()
+        .bg(Color::Rgb(
+            SECONDARY_TEXT_COLOR[0],
+            SECONDARY_TEXT_COLOR[1],
+            SECONDARY_TEXT_COLOR[2],
+        ))
+        .add_modifier(Modifier::BOLD);
+    let field_style = |field: LoginField| {
+        if state.login.focus == field {
+            focused_style
+        } else {
+            Style::default().fg(Color::White)
+        }
+    };
+
+    let form_text = vec![
+        Line::from(vec![
+            Span::styled("Player   : ", label_style),
+            Span::styled(
+                format!("{} ", state.login.player),
+                field_style(LoginField::Player),
+            ),
+        ]),
+        Line::from(vec![
+            Span::styled("Password : ", label_style),
+            Span::styled(
+                format!("{} ", "*".repeat(state.login.password.chars().count())),
+                field_style(LoginField::Password),
+            ),
+        ]),
+        Line::default(),
+        Line::from(Span::styled(
+            state.login.message.as_str(),
+            Style::new().red(),
+        )),
+        Line::default(),
+        Line::from(Span::styled(
+            "[TAB] switch field - [ENTER] log in - [F2] register - [ESC] quit",
+            Style::new().italic(),
+        )),
+    ];
+
+    // Center the form both horizontally and vertically in the available space
+    let vertical_layout = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Min(0),
+            Constraint::Length(LOGIN_FORM_HEIGHT),
+            Constraint::Min(0),
+        ])
+        .split(*layout);
+    let form_layout = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([
+            Constraint::Min(0),
+            Constraint::Length(LOGIN_FORM_WIDTH),
+            Constraint::Min(0),
+        ])
+        .split(vertical_layout[1]);
+
+    frame.render_widget(
+        Paragraph::new(form_text)
+            .alignment(Alignment::Left)
+            .block(Block::default().title("Login").borders(Borders::ALL)),
+        form_layout[1],
+    );
+}
@@ -37,7 +37,7 @@ pub fn render_game_chooser(state: &mut State, layout: &Rect, frame: &mut Frame)
             state
                 .available_games
                 .iter()
-                .map(|s| ListItem::new(s.to_string()))
+                .map(|g| ListItem::new(g.name.to_string()))
                 .collect::<Vec<_>>(),
         )
         .block(
@@ -116,7 +116,7 @@ pub fn render_footer(state: &State, layout: &Rect, frame: &mut Frame) {
         ),
     ]);
 
-    let centered_status_layout = *footer_layout.get(0).unwrap();
+    let centered_status_layout = *footer_layout.first().unwrap();
     frame.render_widget(
         Paragraph::new(status_text).alignment(Alignment::Center),
         // Cannot use `inner()` method as the current implementation subtracts double the margin from the width/height
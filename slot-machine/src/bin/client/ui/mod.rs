@@ -1,4 +1,5 @@
 pub mod game;
+pub mod login;
 pub mod menu;
 pub mod render;
 pub mod widgets;
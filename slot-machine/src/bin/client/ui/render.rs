@@ -6,20 +6,18 @@ use ratatui::{
     Frame,
 };
 
-use crate::app::{Screen, State};
+use crate::app::{AnimationState, Screen, State};
 
 use super::{
     game::{self},
-    menu,
+    login, menu,
 };
 
 fn render_help(state: &State, frame: &mut Frame) {
-    // TODO: Load help message from game paytable / symbols
-    // => Has to be defined by user, might be too cumbersome to generate it programmatically
     frame.render_widget(
-        Paragraph::new("Sample help message").block(
+        Paragraph::new(state.game_info.help.as_str()).block(
             Block::new()
-                .title(format!("Help for {}", state.current_game().unwrap()))
+                .title(format!("Help for {}", state.game_info.name))
                 .borders(Borders::ALL),
         ),
         frame.size(),
@@ -28,6 +26,20 @@ fn render_help(state: &State, frame: &mut Frame) {
 
 pub fn render(state: &mut State, frame: &mut Frame) {
     match state.active_screen {
+        Screen::Login => {
+            let login_window_layout = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints([
+                    Constraint::Percentage(30),
+                    Constraint::Percentage(65),
+                    Constraint::Percentage(5),
+                ])
+                .split(frame.size());
+
+            menu::render_header(state, login_window_layout.first().unwrap(), frame);
+            login::render_login_form(state, login_window_layout.get(1).unwrap(), frame);
+            menu::render_footer(state, login_window_layout.get(2).unwrap(), frame);
+        }
         Screen::MainMenu => {
             let menu_window_layout = Layout::default()
                 .direction(Direction::Vertical)
@@ -38,7 +50,7 @@ pub fn render(state: &mut State, frame: &mut Frame) {
                 ])
                 .split(frame.size());
 
-            menu::render_header(state, menu_window_layout.get(0).unwrap(), frame);
+            menu::render_header(state, menu_window_layout.first().unwrap(), frame);
             menu::render_game_chooser(state, menu_window_layout.get(1).unwrap(), frame);
             menu::render_footer(state, menu_window_layout.get(2).unwrap(), frame);
         }
@@ -52,12 +64,16 @@ pub fn render(state: &mut State, frame: &mut Frame) {
                 ])
                 .split(frame.size());
 
-            game::render_header(state, game_window_layout.get(0).unwrap(), frame);
+            game::render_header(state, game_window_layout.first().unwrap(), frame);
             game::render_reels(state, game_window_layout.get(1).unwrap(), frame);
             game::render_footer(state, game_window_layout.get(2).unwrap(), frame);
 
             if state.win > 0 {
                 game::render_win_overlay(state, game_window_layout.get(1).unwrap(), frame);
+
+                if state.gambles > 0 && state.animation_state == AnimationState::Idle {
+                    game::render_gamble_prompt(state, game_window_layout.get(1).unwrap(), frame);
+                }
             }
         }
         Screen::Help => {
@@ -52,11 +52,7 @@ impl CFontTextWidget {
     }
 
     fn stylized_text(&self, options_override: Option<Options>) -> RenderedString {
-        if options_override.is_some() {
-            cfonts::render(options_override.unwrap())
-        } else {
-            cfonts::render(self.options.clone())
-        }
+        cfonts::render(options_override.unwrap_or_else(|| self.options.clone()))
     }
 }
 
@@ -144,7 +140,7 @@ impl Widget for CFontTextWidget {
                 buf.set_line(
                     area.left() + center_shift.0,
                     area.top() + j as u16 + center_shift.1,
-                    &l,
+                    l,
                     area.width,
                 );
             }
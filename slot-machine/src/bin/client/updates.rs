@@ -1,17 +1,22 @@
 use std::time::Instant;
 
 use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use slot_machine::game::{GambleGuess, GambleKind, RespinStep};
+use slot_machine::protocol::{CommandError, ServerEvent, ServerStatus};
+use slot_machine::utils::format_duration;
+use tracing::{debug, info, warn};
 
 use crate::app::{
-    AnimationState, App, Screen, State, ANIMATION_SKIP_TIMEOUT, ANIMATION_WAIT_TIME,
-    REEL_SPEED_FACTOR, SPIN_BASE_SPEED, SYMBOLS_DISTANCE_RATIO,
+    AnimationState, App, LoginField, Screen, ANIMATION_SKIP_TIMEOUT, ANIMATION_WAIT_TIME,
+    RECONNECT_INTERVAL, REEL_SPEED_FACTOR, SERVER_PING_TIMEOUT, SPIN_BASE_SPEED,
+    SYMBOLS_DISTANCE_RATIO,
 };
 
 pub fn update_animations(app: &mut App) {
     match app.state.animation_state {
         AnimationState::Idle => match app.state.active_screen {
             // Simulate "shiness effect" for title screen text by bolding the lines one at a time at a fixed interval
-            Screen::MainMenu => {
+            Screen::Login | Screen::MainMenu => {
                 if app
                     .state
                     .animation_duration
@@ -28,8 +33,23 @@ pub fn update_animations(app: &mut App) {
         },
         AnimationState::Spin => {
             if app.state.spin_targets.iter().all(|(_, stopped)| *stopped) {
-                app.state.animation_state = AnimationState::Balance;
-                app.state.animation_duration = Instant::now();
+                // The reels not held spin again towards the stops of each respin
+                if let Some(respin) = app.state.respins.pop_front() {
+                    if app
+                        .state
+                        .animation_duration
+                        .elapsed()
+                        .cmp(&ANIMATION_WAIT_TIME)
+                        .is_ge()
+                    {
+                        set_spin_targets(app, &respin.stops, &respin.held);
+                    } else {
+                        app.state.respins.push_front(respin);
+                    }
+                } else {
+                    app.state.animation_state = AnimationState::Balance;
+                    app.state.animation_duration = Instant::now();
+                }
             } else {
                 let term_h = app.get_term_size().unwrap().height as f64;
                 app.state
@@ -47,6 +67,10 @@ pub fn update_animations(app: &mut App) {
 
                                 if *target as usize == app.state.spin_indexes[i] {
                                     *stopped = true;
+                                    // Pause on the result before respinning
+                                    if !app.state.respins.is_empty() {
+                                        app.state.animation_duration = Instant::now();
+                                    }
                                 }
                             } else {
                                 *y -= SPIN_BASE_SPEED + 1.0 / (i + 1) as f64 * REEL_SPEED_FACTOR;
@@ -104,9 +128,17 @@ fn next_game_animation(app: &mut App) {
 
             app.state.next_win = 0;
             app.state.win = app.state.next_win;
+            app.state.notice.clear();
+            app.state.respins.clear();
+            // Spinning again collects the win instead of gambling it
+            app.state.gambles = 0;
 
-            app.state.next_balance = app.state.next_balance.saturating_sub(app.state.bet);
-            app.state.balance = app.state.next_balance;
+            // Free spins don't cost anything
+            app.load_spin_reels();
+            if app.state.free_spins == 0 {
+                app.state.next_balance = app.state.next_balance.saturating_sub(app.state.bet);
+                app.state.balance = app.state.next_balance;
+            }
 
             app.client
                 .send_spin_message(app.state.current_game().unwrap(), app.state.bet);
@@ -119,6 +151,11 @@ fn next_game_animation(app: &mut App) {
                 .cmp(&ANIMATION_WAIT_TIME)
                 .is_ge()
             {
+                // Skipping the animation skips the respins as well
+                if let Some(respin) = app.state.respins.pop_back() {
+                    app.state.respins.clear();
+                    set_spin_targets(app, &respin.stops, &respin.held);
+                }
                 app.state
                     .spin_targets
                     .iter_mut()
@@ -139,33 +176,67 @@ fn next_game_animation(app: &mut App) {
     }
 }
 
+fn update_login_keys(app: &mut App, key_event: KeyEvent) {
+    let login = &mut app.state.login;
+    let field = match login.focus {
+        LoginField::Player => &mut login.player,
+        LoginField::Password => &mut login.password,
+    };
+
+    match key_event.code {
+        KeyCode::Esc => app.quit(),
+        KeyCode::Tab | KeyCode::BackTab | KeyCode::Up | KeyCode::Down => {
+            login.focus = match login.focus {
+                LoginField::Player => LoginField::Password,
+                LoginField::Password => LoginField::Player,
+            }
+        }
+        KeyCode::Backspace => {
+            field.pop();
+        }
+        KeyCode::Char(c) => field.push(c),
+        KeyCode::Enter => {
+            login.message.clear();
+            app.client
+                .send_login_message(login.player.clone(), login.password.clone());
+        }
+        KeyCode::F(2) => {
+            login.message.clear();
+            app.client
+                .send_register_message(login.player.clone(), login.password.clone());
+        }
+        _ => {}
+    }
+}
+
 pub fn update_keys(app: &mut App, key_event: KeyEvent) {
+    // Every key is text input on the login screen, except for the global exit shortcut
+    if app.state.active_screen == Screen::Login && key_event.modifiers != KeyModifiers::CONTROL {
+        update_login_keys(app, key_event);
+        return;
+    }
+
     match key_event.code {
         KeyCode::Esc | KeyCode::Char('q') => match app.state.active_screen {
-            Screen::MainMenu => app.quit(),
-            Screen::Game => {
-                let previous_game = app.state.selected_game.selected();
-                app.state = State::default();
-                app.init_menu();
-                app.state.selected_game.select(previous_game);
-            }
+            Screen::Login | Screen::MainMenu => app.quit(),
+            Screen::Game => app.reset_menu(),
             Screen::Help => app.state.active_screen = Screen::Game,
         },
-        KeyCode::Char('c') | KeyCode::Char('C') => {
-            if key_event.modifiers == KeyModifiers::CONTROL {
-                app.quit()
-            }
+        KeyCode::Char('c') | KeyCode::Char('C') if key_event.modifiers == KeyModifiers::CONTROL => {
+            app.quit()
         }
         KeyCode::F(1) => match app.state.active_screen {
-            Screen::MainMenu => {}
+            Screen::Login | Screen::MainMenu => {}
             Screen::Game => app.state.active_screen = Screen::Help,
             Screen::Help => app.state.active_screen = Screen::Game,
         },
         KeyCode::Enter | KeyCode::Char(' ') => match app.state.active_screen {
             Screen::MainMenu => {
-                app.init_game(app.state.current_game().unwrap());
-                app.state.title_text_bold_line = -1;
-                app.state.active_screen = Screen::Game;
+                // The game screen is shown once the server sent the game definition
+                if let Some(game) = app.state.current_game() {
+                    app.init_game(game);
+                    app.state.title_text_bold_line = -1;
+                }
             }
             Screen::Game => {
                 if app.state.animation_state == AnimationState::Idle
@@ -183,7 +254,7 @@ pub fn update_keys(app: &mut App, key_event: KeyEvent) {
                     app.state.animation_skip_timeout = Instant::now();
                 }
             }
-            Screen::Help => {}
+            Screen::Login | Screen::Help => {}
         },
         KeyCode::Up | KeyCode::Char('+') | KeyCode::Char('j') => match app.state.active_screen {
             Screen::MainMenu => {
@@ -204,7 +275,7 @@ pub fn update_keys(app: &mut App, key_event: KeyEvent) {
                     app.state.bet += 1;
                 }
             }
-            Screen::Help => {}
+            Screen::Login | Screen::Help => {}
         },
         KeyCode::Down | KeyCode::Char('-') | KeyCode::Char('k') => match app.state.active_screen {
             Screen::MainMenu => {
@@ -219,15 +290,55 @@ pub fn update_keys(app: &mut App, key_event: KeyEvent) {
                     app.state.bet -= 1;
                 }
             }
-            Screen::Help => {}
+            Screen::Login | Screen::Help => {}
         },
+        KeyCode::Char(c) if app.state.active_screen == Screen::Game => {
+            let guess = match (app.state.gamble.as_ref().map(|g| g.kind), c) {
+                (Some(GambleKind::Card), 'r' | 'R') => GambleGuess::Red,
+                (Some(GambleKind::Card), 'b' | 'B') => GambleGuess::Black,
+                (Some(GambleKind::Coin), 'h' | 'H') => GambleGuess::Heads,
+                (Some(GambleKind::Coin), 't' | 'T') => GambleGuess::Tails,
+                _ => return,
+            };
+
+            if app.state.animation_state == AnimationState::Idle && app.state.gambles > 0 {
+                app.state.gambles = 0;
+                app.state.notice.clear();
+                app.client
+                    .send_gamble_message(app.state.current_game().unwrap(), guess);
+            }
+        }
         _ => {}
     };
 }
 
-pub fn update_spin(app: &mut App, spin: Vec<isize>, win: u64, balance: u64) {
+/// Sets the reels to spin towards the given stops, leaving the `held` reels stopped where they are.
+fn set_spin_targets(app: &mut App, stops: &[usize], held: &[usize]) {
+    app.state
+        .spin_targets
+        .iter_mut()
+        .enumerate()
+        .for_each(|(i, (target, stopped))| {
+            *target = app.state.reels_symbols[i].len() as isize - 1 - stops[i] as isize;
+            *stopped = held.contains(&i);
+        });
+}
+
+pub fn update_spin(
+    app: &mut App,
+    spin: Vec<isize>,
+    win: u64,
+    balance: u64,
+    free_spins: u32,
+    respins: Vec<RespinStep>,
+    gambles: u32,
+) {
+    debug!(stops = ?spin, win, balance, free_spins, respins = respins.len(), gambles, "Spin");
     app.state.next_balance = balance;
     app.state.next_win = win;
+    app.state.free_spins = free_spins;
+    app.state.respins = respins.into();
+    app.state.gambles = gambles;
     app.state
         .spin_targets
         .iter_mut()
@@ -236,3 +347,134 @@ pub fn update_spin(app: &mut App, spin: Vec<isize>, win: u64, balance: u64) {
             *target = app.state.reels_symbols[i].len() as isize - 1 - spin[i]
         });
 }
+
+pub fn update_gamble(app: &mut App, outcome: GambleGuess, win: u64, gambles: u32, balance: u64) {
+    debug!(%outcome, win, gambles, balance, "Gamble");
+    app.state.next_balance = balance;
+    app.state.next_win = win;
+    app.state.gambles = gambles;
+    app.state.notice = if win > 0 {
+        format!("{}, the win is doubled !", outcome)
+    } else {
+        format!("{}, the win is lost", outcome)
+    };
+    app.state.animation_state = AnimationState::Balance;
+    app.state.animation_duration = Instant::now();
+}
+
+pub fn update_logged_in(app: &mut App, player: String, balance: u64, token: String) {
+    info!(%player, balance, "Logged in");
+    app.state.login.player = player;
+    app.state.login.password.clear();
+    app.state.login.message.clear();
+    app.state.login.token = Some(token);
+    app.state.balance = balance;
+    app.state.next_balance = balance;
+
+    // A session resumed after a reconnection stays on its screen, with its game initialized again
+    if app.state.active_screen == Screen::Login {
+        app.init_menu();
+    } else {
+        app.load_games();
+        if app.state.active_screen != Screen::MainMenu {
+            if let Some(game) = app.state.current_game() {
+                app.client.send_init_message(game);
+            }
+        }
+    }
+}
+
+pub fn update_error(app: &mut App, code: u64, message: String, command: Option<&str>) {
+    // Other commands are rejected until logged in, e.g. those sent when the session expired
+    if app.state.active_screen == Screen::Login {
+        if matches!(command, Some("Login" | "Register" | "Resume")) {
+            app.state.login.message = message;
+        }
+        return;
+    }
+
+    // The session can't be resumed once its token expired or was revoked
+    if code == CommandError::SessionExpired.code()
+        || code == CommandError::NotAuthenticated.code()
+        || command == Some("Resume")
+    {
+        app.reset_login(message);
+        return;
+    }
+    app.state.notice = message;
+
+    // The server rejected the spin so the reels are stopped and the bet is given back
+    if command == Some("Play") && app.state.animation_state == AnimationState::Spin {
+        if app.state.free_spins == 0 {
+            app.state.next_balance += app.state.bet;
+        }
+        app.state.balance = app.state.next_balance;
+        app.state
+            .spin_targets
+            .iter_mut()
+            .enumerate()
+            .for_each(|(i, (target, stopped))| {
+                *target = app.state.spin_indexes[i] as isize;
+                *stopped = true;
+            });
+        app.state.animation_state = AnimationState::Idle;
+    }
+}
+
+pub fn update_event(app: &mut App, event: ServerEvent) {
+    debug!(?event, "Server event");
+    match event {
+        ServerEvent::BalanceChanged { balance } => {
+            // A spin in progress will set the balance from its own result
+            if app.state.animation_state == AnimationState::Idle {
+                app.state.balance = balance;
+                app.state.next_balance = balance;
+            }
+        }
+        ServerEvent::JackpotChanged { game, amount } => {
+            app.state.jackpots.insert(game, amount);
+        }
+        ServerEvent::GamesChanged { games } => app.update_games(games),
+        ServerEvent::Shutdown { .. } => update_disconnected(app),
+        ServerEvent::Heartbeat { .. } => {
+            app.state.last_heartbeat = Instant::now();
+            app.client.send_status_message();
+        }
+        ServerEvent::RealityCheck {
+            elapsed,
+            wagered,
+            won,
+        } => {
+            app.state.notice = format!(
+                "Reality check: you have been playing for {}, wagered {} and won {}",
+                format_duration(elapsed),
+                wagered,
+                won
+            );
+        }
+    }
+}
+
+pub fn update_connection(app: &mut App) {
+    if app.state.daemon_status.server_status == ServerStatus::Connected
+        && app.state.last_heartbeat.elapsed() > SERVER_PING_TIMEOUT
+    {
+        app.state.daemon_status.server_status = ServerStatus::Disconnected;
+    }
+
+    if let Some(reconnect_at) = app.state.reconnect_at {
+        if Instant::now() >= reconnect_at {
+            app.state.reconnect_at = if app.reconnect() {
+                None
+            } else {
+                Some(Instant::now() + RECONNECT_INTERVAL)
+            };
+        }
+    }
+}
+
+pub fn update_disconnected(app: &mut App) {
+    warn!("Disconnected from the server");
+    app.state.daemon_status.server_status = ServerStatus::Stopped;
+    app.state.reconnect_at = Some(Instant::now() + RECONNECT_INTERVAL);
+}
@@ -0,0 +1,177 @@
+//! Admin channel of the daemon, answering the commands of `slotctl` on a UNIX socket.
+
+use std::fs::{self, Permissions};
+use std::io::{BufReader, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::Path;
+use std::sync::Arc;
+use std::thread;
+
+use anyhow::{bail, Result};
+use slot_machine::admin::{AdminCommand, AdminResponse};
+use slot_machine::protocol::{CommandError, ServerEvent, MIN_PASSWORD_LENGTH};
+use slot_machine::utils::read_socket_message;
+use tracing::{debug, error, info, info_span, warn};
+
+use crate::auth::hash_password;
+use crate::games::reload_games;
+use crate::Server;
+
+/// Listens for admin connections on the UNIX socket at `path`, only accessible to the user of the
+/// daemon.
+pub fn spawn_admin_socket(server: Arc<Server>, path: &Path) -> Result<()> {
+    // A socket file left by a daemon that didn't exit cleanly can be replaced, not a live one
+    if UnixStream::connect(path).is_ok() {
+        bail!("Admin socket {} is used by another daemon", path.display());
+    }
+    let _ = fs::remove_file(path);
+    let listener = UnixListener::bind(path)?;
+    fs::set_permissions(path, Permissions::from_mode(0o600))?;
+    info!(path = %path.display(), "Listening for admin commands...");
+
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let server = server.clone();
+            thread::spawn(move || handle_admin(stream, server));
+        }
+    });
+
+    Ok(())
+}
+
+fn handle_admin(stream: UnixStream, server: Arc<Server>) {
+    let _entered = info_span!("admin").entered();
+    let Ok(reader) = stream.try_clone() else {
+        return;
+    };
+    let mut reader = BufReader::new(reader);
+    let mut writer = stream;
+
+    while let Ok(Some(buf)) = read_socket_message(&mut reader) {
+        let response = match serde_json::from_str::<AdminCommand>(&buf) {
+            Ok(command) => {
+                // Never log the passwords
+                match &command {
+                    AdminCommand::SetPassword { player, .. } => {
+                        info!(%player, "Admin password command")
+                    }
+                    _ => info!(?command, "Admin command"),
+                }
+                handle_command(command, &server)
+            }
+            Err(err) => AdminResponse::Error(format!("Malformed command: {}", err)),
+        };
+        if let AdminResponse::Error(err) = &response {
+            debug!(error = %err, "Rejected admin command");
+        }
+
+        let message = serde_json::to_string(&response).unwrap();
+        if writeln!(writer, "{}", message).is_err() {
+            break;
+        }
+    }
+}
+
+fn handle_command(command: AdminCommand, server: &Server) -> AdminResponse {
+    match command {
+        AdminCommand::Sessions => {
+            let mut sessions = server.bus.sessions();
+            for session in sessions.iter_mut() {
+                if let Some(player) = &session.player {
+                    session.balance = server.accounts.balance(player).ok().flatten();
+                }
+            }
+
+            AdminResponse::Sessions(sessions)
+        }
+        AdminCommand::Kick { session } => {
+            if !server.bus.kick(session) {
+                return AdminResponse::Error(format!("Unknown session {}", session));
+            }
+            warn!(session, "Kicked client");
+
+            AdminResponse::Done
+        }
+        AdminCommand::Adjust { player, amount } => match server.accounts.adjust(&player, amount) {
+            Ok(Some(balance)) => {
+                warn!(%player, amount, balance, "Adjusted balance");
+                server.bus.publish_to_player(
+                    &player,
+                    None,
+                    ServerEvent::BalanceChanged { balance },
+                );
+
+                AdminResponse::Balance { player, balance }
+            }
+            Ok(None) => AdminResponse::Error(format!(
+                "Unknown player \"{}\" or insufficient balance",
+                player
+            )),
+            Err(err) => {
+                error!(%player, error = %err, "Could not adjust balance");
+                AdminResponse::Error(format!("Could not adjust balance: {}", err))
+            }
+        },
+        AdminCommand::SetPassword { player, password } => {
+            if password.chars().count() < MIN_PASSWORD_LENGTH {
+                return AdminResponse::Error(CommandError::PasswordTooShort.to_string());
+            }
+            let hash = match hash_password(&password) {
+                Ok(hash) => hash,
+                Err(err) => {
+                    return AdminResponse::Error(format!("Could not hash password: {}", err))
+                }
+            };
+
+            match server.accounts.set_password(&player, &hash) {
+                Ok(true) => {
+                    warn!(%player, "Set password");
+                    AdminResponse::Done
+                }
+                Ok(false) => AdminResponse::Error(format!("Unknown player \"{}\"", player)),
+                Err(err) => {
+                    error!(%player, error = %err, "Could not set password");
+                    AdminResponse::Error(format!("Could not set password: {}", err))
+                }
+            }
+        }
+        AdminCommand::Games => AdminResponse::Games(server.games.statuses()),
+        AdminCommand::EnableGame { game } => set_game_enabled(server, &game, true),
+        AdminCommand::DisableGame { game } => set_game_enabled(server, &game, false),
+        AdminCommand::Reload => match reload_games(server) {
+            Ok(reload) => AdminResponse::Reloaded {
+                added: reload.added,
+                updated: reload.updated,
+                removed: reload.removed,
+                failed: reload.failed,
+            },
+            Err(err) => AdminResponse::Error(format!("Could not reload games: {:#}", err)),
+        },
+        AdminCommand::Metrics => {
+            AdminResponse::Metrics(server.metrics.render(&server.games.snapshot()))
+        }
+        AdminCommand::Shutdown => {
+            warn!("Shutdown requested by the admin");
+            server.stop();
+
+            AdminResponse::Done
+        }
+    }
+}
+
+fn set_game_enabled(server: &Server, game: &str, enabled: bool) -> AdminResponse {
+    match server.games.set_enabled(game, enabled) {
+        Ok(changed) => {
+            if changed {
+                info!(%game, enabled, "Changed game availability");
+                server.bus.publish(ServerEvent::GamesChanged {
+                    games: server.games.infos(),
+                });
+            }
+
+            AdminResponse::Done
+        }
+        Err(err) => AdminResponse::Error(err.to_string()),
+    }
+}
@@ -0,0 +1,31 @@
+//! Password hashing and session tokens.
+
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+use slot_machine::utils::to_hex;
+
+const SESSION_TOKEN_BYTES: usize = 32;
+
+/// Hashes the password with Argon2id and a random salt, in the PHC string format.
+pub fn hash_password(password: &str) -> argon2::password_hash::Result<String> {
+    let salt = SaltString::generate(&mut OsRng);
+
+    Ok(Argon2::default()
+        .hash_password(password.as_bytes(), &salt)?
+        .to_string())
+}
+
+pub fn verify_password(password: &str, hash: &str) -> bool {
+    PasswordHash::new(hash)
+        .and_then(|hash| Argon2::default().verify_password(password.as_bytes(), &hash))
+        .is_ok()
+}
+
+/// Generates a random hex-encoded session token.
+pub fn generate_token() -> String {
+    let mut bytes = [0u8; SESSION_TOKEN_BYTES];
+    OsRng.fill_bytes(&mut bytes);
+
+    to_hex(&bytes)
+}
@@ -0,0 +1,318 @@
+//! Daemon configuration, read from a JSON file and overridden by the command line arguments.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde::{Deserialize, Deserializer};
+use serde_with::{serde_as, DisplayFromStr};
+use slot_machine::accounts::ACCOUNTS_DATABASE;
+use slot_machine::admin::ADMIN_SOCKET;
+use slot_machine::audit::AUDIT_LOG;
+use slot_machine::protocol::ClientCommand;
+use slot_machine::rng::RngSpec;
+use slot_machine::transport::{Address, DEFAULT_ADDRESS, TLS_KEY_FILE};
+use slot_machine::GAMES_FOLDER;
+use tokio::sync::Semaphore;
+use tracing::level_filters::LevelFilter;
+
+use crate::rate_limit::{RateLimit, DEFAULT_LIMIT_KEY};
+
+/// Configuration file read when no `--config` argument is given, if it exists.
+pub const DEFAULT_CONFIG_FILE: &str = "./data/daemon.json";
+/// Maximum number of connections, each one holding a permit of a semaphore that is acquired all at
+/// once (counted as a `u32`) on shutdown.
+pub const MAX_CONNECTIONS: usize = if (u32::MAX as usize) < Semaphore::MAX_PERMITS {
+    u32::MAX as usize
+} else {
+    Semaphore::MAX_PERMITS
+};
+
+pub const USAGE: &str = "Usage: daemon [options] [address...]
+
+Options override the values of the configuration file (--config, ./data/daemon.json by default):
+  --config <path>           Configuration file
+  --listen <address>        Address to listen on, can be repeated (replaces the configured ones)
+  --games <folder>          Games folder
+  --accounts <path>         Accounts database
+  --audit-log <path>        Spins audit log
+  --tls-key <path>          Private key of the certificate of the tls:// addresses
+  --rate-limit [<command>=]<rate>
+                            Commands per second of every command type or a single one, as
+                            <per_second>[/<burst>] or off
+  --start-balance <coins>   Balance of the new accounts
+  --session-ttl <seconds>   Validity of the session tokens
+  --shutdown-timeout <secs> Time given to the clients to finish their commands on shutdown
+  --max-connections <n>     Clients connected at once, the next ones wait to be accepted
+  --log-level <level>       off, error, warn, info, debug or trace
+  --log-format <format>     text or json (one object per line)
+  --log-file <path>         Append the logs to a file instead of the standard output
+  --metrics <host:port>     Serve Prometheus metrics over HTTP on /metrics
+  --admin-socket <path>     UNIX socket of the admin channel used by slotctl
+  --rng [<game>=]<rng>      Generator of every game or a single one, as <kind>[:<seed>]
+  --print-tables            Print the par table of each game on startup
+  --no-watch                Only reload the games on SIGHUP, not when the games folder changes
+  --help                    Print this message";
+
+// Options taking a value
+const OPTIONS: [&str; 17] = [
+    "--config",
+    "--listen",
+    "--games",
+    "--accounts",
+    "--audit-log",
+    "--tls-key",
+    "--rate-limit",
+    "--start-balance",
+    "--session-ttl",
+    "--shutdown-timeout",
+    "--max-connections",
+    "--log-level",
+    "--log-format",
+    "--log-file",
+    "--metrics",
+    "--admin-socket",
+    "--rng",
+];
+
+/// Format of the daemon logs.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// Human readable lines.
+    #[default]
+    Text,
+    /// One JSON object per line, with the fields of the event and its spans.
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => bail!("Unknown log format \"{}\"", s),
+        }
+    }
+}
+
+#[serde_as]
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Addresses to listen on (see the `transport` module for the format).
+    #[serde_as(as = "Vec<DisplayFromStr>")]
+    pub listen: Vec<Address>,
+    pub games_folder: PathBuf,
+    pub accounts_database: PathBuf,
+    pub audit_log: PathBuf,
+    /// Private key of the certificate given with the `tls://` addresses, both generated if missing.
+    pub tls_key: PathBuf,
+    /// Limits of each client per command type (see `ClientCommand::name`), the `*` one applying
+    /// to the types without a limit of their own. The configured limits replace the default ones
+    /// of their command types only.
+    #[serde(deserialize_with = "merge_rate_limits")]
+    pub rate_limits: HashMap<String, RateLimit>,
+    pub start_balance: u64,
+    /// Validity of the session tokens, in seconds.
+    pub session_ttl_secs: u64,
+    /// Time without logging in nor playing after which the play session of a player ends, resetting
+    /// the session limits, in seconds.
+    pub session_break_secs: u64,
+    /// Delay before looser responsible gaming limits apply, in seconds.
+    pub limit_increase_delay_secs: u64,
+    /// Time given to the clients to finish their commands on shutdown, in seconds.
+    pub shutdown_timeout_secs: u64,
+    /// Clients connected at once, the next ones wait in the backlog of the listeners.
+    pub max_connections: usize,
+    #[serde_as(as = "DisplayFromStr")]
+    pub log_level: LevelFilter,
+    pub log_format: LogFormat,
+    /// File the logs are appended to, the standard output if `None`.
+    pub log_file: Option<PathBuf>,
+    /// `host:port` serving the Prometheus metrics over HTTP, disabled if `None`.
+    pub metrics_listen: Option<String>,
+    /// UNIX socket of the admin channel, disabled if `None`.
+    pub admin_socket: Option<PathBuf>,
+    /// Generator of the games without one in `games_rng`.
+    pub rng: RngSpec,
+    pub games_rng: HashMap<String, RngSpec>,
+    pub print_tables: bool,
+    /// Reload the games when the files of the games folder change.
+    pub watch_games: bool,
+}
+
+fn merge_rate_limits<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<HashMap<String, RateLimit>, D::Error> {
+    let mut limits = RateLimit::defaults();
+    limits.extend(HashMap::<String, RateLimit>::deserialize(deserializer)?);
+
+    Ok(limits)
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            listen: vec![DEFAULT_ADDRESS.parse().unwrap()],
+            games_folder: PathBuf::from(GAMES_FOLDER),
+            accounts_database: PathBuf::from(ACCOUNTS_DATABASE),
+            audit_log: PathBuf::from(AUDIT_LOG),
+            tls_key: PathBuf::from(TLS_KEY_FILE),
+            rate_limits: RateLimit::defaults(),
+            start_balance: 100,
+            session_ttl_secs: 12 * 60 * 60,
+            session_break_secs: 30 * 60,
+            limit_increase_delay_secs: 24 * 60 * 60,
+            shutdown_timeout_secs: 10,
+            max_connections: 1024,
+            log_level: LevelFilter::INFO,
+            log_format: LogFormat::default(),
+            log_file: None,
+            metrics_listen: None,
+            admin_socket: Some(PathBuf::from(ADMIN_SOCKET)),
+            rng: RngSpec::default(),
+            games_rng: HashMap::new(),
+            print_tables: false,
+            watch_games: true,
+        }
+    }
+}
+
+impl Config {
+    /// Reads the configuration file then applies the command line arguments over it. Returns
+    /// `None` if the usage was requested.
+    pub fn load(args: impl Iterator<Item = String>) -> Result<Option<Self>> {
+        let args: Vec<String> = args.collect();
+        if args.iter().any(|a| a == "--help") {
+            return Ok(None);
+        }
+
+        let config_file = args.iter().position(|a| a == "--config");
+        let mut config = match config_file {
+            Some(i) => {
+                let path = args
+                    .get(i + 1)
+                    .ok_or(anyhow!("Missing value for --config"))?;
+                Self::read(Path::new(path))?
+            }
+            None if Path::new(DEFAULT_CONFIG_FILE).exists() => {
+                Self::read(Path::new(DEFAULT_CONFIG_FILE))?
+            }
+            None => Self::default(),
+        };
+
+        let mut listen = vec![];
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            if !arg.starts_with("--") {
+                listen.push(arg.parse()?);
+                continue;
+            }
+            if arg == "--print-tables" {
+                config.print_tables = true;
+                continue;
+            }
+            if arg == "--no-watch" {
+                config.watch_games = false;
+                continue;
+            }
+            if !OPTIONS.contains(&arg.as_str()) {
+                bail!("Unknown option {}", arg);
+            }
+
+            let value = args
+                .next()
+                .ok_or_else(|| anyhow!("Missing value for {}", arg))?;
+            let invalid = || anyhow!("Invalid value {:?} for {}", value, arg);
+            match arg.as_str() {
+                "--config" => {}
+                "--listen" => listen.push(value.parse()?),
+                "--games" => config.games_folder = value.into(),
+                "--accounts" => config.accounts_database = value.into(),
+                "--audit-log" => config.audit_log = value.into(),
+                "--tls-key" => config.tls_key = value.into(),
+                "--rate-limit" => {
+                    let (command, limit) =
+                        value.split_once('=').unwrap_or((DEFAULT_LIMIT_KEY, &value));
+                    let limit = limit.parse().map_err(|_| invalid())?;
+                    config.rate_limits.insert(command.to_string(), limit);
+                }
+                "--start-balance" => config.start_balance = value.parse().map_err(|_| invalid())?,
+                "--session-ttl" => {
+                    config.session_ttl_secs = value.parse().map_err(|_| invalid())?
+                }
+                "--shutdown-timeout" => {
+                    config.shutdown_timeout_secs = value.parse().map_err(|_| invalid())?
+                }
+                "--max-connections" => {
+                    config.max_connections = value.parse().map_err(|_| invalid())?
+                }
+                "--log-level" => config.log_level = value.parse().map_err(|_| invalid())?,
+                "--log-format" => config.log_format = value.parse().map_err(|_| invalid())?,
+                "--log-file" => config.log_file = Some(value.into()),
+                "--metrics" => config.metrics_listen = Some(value),
+                "--admin-socket" => config.admin_socket = Some(value.into()),
+                "--rng" => match value.split_once('=') {
+                    Some((game, spec)) => {
+                        let spec = spec.parse().map_err(|_| invalid())?;
+                        config.games_rng.insert(game.to_string(), spec);
+                    }
+                    None => config.rng = value.parse().map_err(|_| invalid())?,
+                },
+                _ => unreachable!(),
+            }
+        }
+
+        if !listen.is_empty() {
+            config.listen = listen;
+        }
+        if !(1..=MAX_CONNECTIONS).contains(&config.max_connections) {
+            bail!(
+                "The maximum number of connections must be between 1 and {}",
+                MAX_CONNECTIONS
+            );
+        }
+        if let Some(command) = config.rate_limits.keys().find(|command| {
+            *command != DEFAULT_LIMIT_KEY && !ClientCommand::NAMES.contains(&command.as_str())
+        }) {
+            bail!("Unknown command \"{}\" in the rate limits", command);
+        }
+
+        Ok(Some(config))
+    }
+
+    fn read(path: &Path) -> Result<Self> {
+        let file = fs::read_to_string(path)
+            .with_context(|| format!("Could not read configuration file {:?}", path))?;
+
+        serde_json::from_str(&file)
+            .with_context(|| format!("Could not parse configuration file {:?}", path))
+    }
+
+    pub fn session_ttl(&self) -> Duration {
+        Duration::from_secs(self.session_ttl_secs)
+    }
+
+    pub fn session_break(&self) -> Duration {
+        Duration::from_secs(self.session_break_secs)
+    }
+
+    pub fn limit_increase_delay(&self) -> Duration {
+        Duration::from_secs(self.limit_increase_delay_secs)
+    }
+
+    pub fn shutdown_timeout(&self) -> Duration {
+        Duration::from_secs(self.shutdown_timeout_secs)
+    }
+
+    /// Generator of the game, either its own or the default one.
+    pub fn game_rng(&self, game: &str) -> RngSpec {
+        self.games_rng.get(game).copied().unwrap_or(self.rng)
+    }
+}
@@ -0,0 +1,162 @@
+//! Events pushed by the server to the clients that subscribed to them.
+
+use std::collections::{HashMap, HashSet};
+use std::net::Shutdown;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use slot_machine::admin::SessionInfo;
+use slot_machine::protocol::{EventKind, ServerEvent, ServerResponse};
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::sync::mpsc::Sender;
+use tokio::sync::watch;
+use tracing::warn;
+
+pub const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Kinds of events a client is subscribed to, shared between its session and the [`EventBus`].
+pub type Subscriptions = Arc<Mutex<HashSet<EventKind>>>;
+
+/// Asks the task of a connection to stop reading commands ([`Shutdown::Read`]) or to close the
+/// connection right away ([`Shutdown::Both`]).
+pub type Closer = watch::Sender<Option<Shutdown>>;
+
+struct Subscriber {
+    player: Option<String>,
+    peer: String,
+    closer: Closer,
+    connected_at: Instant,
+    subscriptions: Subscriptions,
+    sender: Sender<ServerResponse>,
+}
+
+/// Dispatches the server events to the outgoing channel of every client subscribed to their kind.
+/// It also keeps a handle on the connection of each client so that the admin can disconnect them.
+///
+/// The outgoing channels are bounded: a client that doesn't read its messages fast enough to keep
+/// up with its events is disconnected rather than queuing them without limit.
+#[derive(Default)]
+pub struct EventBus {
+    next_id: AtomicU64,
+    subscribers: Mutex<HashMap<u64, Subscriber>>,
+}
+
+impl EventBus {
+    /// Registers a client outgoing channel, returning the identifier used to unregister it.
+    pub fn register(
+        &self,
+        peer: String,
+        closer: Closer,
+        subscriptions: Subscriptions,
+        sender: Sender<ServerResponse>,
+    ) -> u64 {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.subscribers.lock().unwrap().insert(
+            id,
+            Subscriber {
+                player: None,
+                peer,
+                closer,
+                connected_at: Instant::now(),
+                subscriptions,
+                sender,
+            },
+        );
+
+        id
+    }
+
+    /// Removes a client, dropping its channel sender so its writer can terminate.
+    pub fn unregister(&self, id: u64) {
+        self.subscribers.lock().unwrap().remove(&id);
+    }
+
+    /// Associates a client with the player account it identified as.
+    pub fn identify(&self, id: u64, player: &str) {
+        if let Some(subscriber) = self.subscribers.lock().unwrap().get_mut(&id) {
+            subscriber.player = Some(player.to_string());
+        }
+    }
+
+    /// Lists the registered clients, sorted by identifier. The balances are left for the caller
+    /// to fill in.
+    pub fn sessions(&self) -> Vec<SessionInfo> {
+        let mut sessions: Vec<SessionInfo> = self
+            .subscribers
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(id, s)| SessionInfo {
+                id: *id,
+                peer: s.peer.clone(),
+                player: s.player.clone(),
+                balance: None,
+                connected_for: s.connected_at.elapsed(),
+            })
+            .collect();
+        sessions.sort_by_key(|s| s.id);
+
+        sessions
+    }
+
+    /// Closes the connection of a client, which unregisters once its reader notices. Returns
+    /// `false` if there is no such client.
+    pub fn kick(&self, id: u64) -> bool {
+        match self.subscribers.lock().unwrap().get(&id) {
+            Some(subscriber) => {
+                subscriber.closer.send_replace(Some(Shutdown::Both));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Shuts down the connection of every client. With [`Shutdown::Read`], the clients stop
+    /// receiving commands but the responses to the commands being handled are still sent.
+    pub fn shutdown_all(&self, how: Shutdown) {
+        for subscriber in self.subscribers.lock().unwrap().values() {
+            subscriber.closer.send_replace(Some(how));
+        }
+    }
+
+    /// Sends the event to every subscribed client.
+    pub fn publish(&self, event: ServerEvent) {
+        self.publish_filtered(event, |_, _| true);
+    }
+
+    /// Sends the event to the subscribed clients identified as `player`, other than `except`.
+    pub fn publish_to_player(&self, player: &str, except: Option<u64>, event: ServerEvent) {
+        self.publish_filtered(event, |id, subscriber| {
+            Some(id) != except && subscriber.player.as_deref() == Some(player)
+        });
+    }
+
+    fn publish_filtered(&self, event: ServerEvent, filter: impl Fn(u64, &Subscriber) -> bool) {
+        let kind = event.kind();
+        self.subscribers
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(id, s)| filter(**id, s) && s.subscriptions.lock().unwrap().contains(&kind))
+            .for_each(|(id, s)| {
+                if let Err(TrySendError::Full(_)) =
+                    s.sender.try_send(ServerResponse::Event(event.clone()))
+                {
+                    warn!(id, peer = %s.peer, "Client is too slow to receive its events, disconnecting");
+                    s.closer.send_replace(Some(Shutdown::Both));
+                }
+            });
+    }
+}
+
+/// Periodically publishes a [`ServerEvent::Heartbeat`] for the lifetime of the server.
+pub fn spawn_heartbeat(bus: Arc<EventBus>) {
+    thread::spawn(move || loop {
+        thread::sleep(HEARTBEAT_INTERVAL);
+        bus.publish(ServerEvent::Heartbeat {
+            clock: SystemTime::now(),
+        });
+    });
+}
@@ -0,0 +1,338 @@
+//! Catalog of the games served by the daemon, reloaded while it runs.
+//!
+//! Each reload parses the games of the folder again and swaps the whole catalog at once. A game
+//! whose files didn't change keeps its loaded version, and a changed game keeps the generator of
+//! its previous version (and the state of its sequence) unless its generator spec changed. A game
+//! that fails to load keeps being served in its previous version. Sessions take a snapshot of the
+//! catalog for each command, so a spin always completes with the version it started with.
+//!
+//! The admin can disable a game: it stays loaded (and reloaded) but is left out of the snapshots
+//! served to the clients until it is enabled again.
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc, Mutex, RwLock};
+use std::thread;
+use std::time::Duration;
+
+use anyhow::{bail, Result};
+use notify::{RecursiveMode, Watcher};
+use sha2::{Digest, Sha256};
+use signal_hook::consts::SIGHUP;
+use signal_hook::iterator::Signals;
+use slot_machine::admin::GameStatus;
+use slot_machine::game::{Game, GameInfo};
+use slot_machine::protocol::ServerEvent;
+use slot_machine::rng::{RngSpec, SlotRng};
+use slot_machine::utils::to_hex;
+use tracing::{error, info, warn};
+
+use crate::Server;
+
+// Editors and copies write the files in several steps, wait for the folder to settle down
+const WATCH_DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// Games indexed by their identifier.
+pub type Games = HashMap<String, Arc<Game>>;
+
+/// Games changed by a reload.
+#[derive(Debug, Default)]
+pub struct Reload {
+    pub added: Vec<String>,
+    pub updated: Vec<String>,
+    pub removed: Vec<String>,
+    /// Games that could not be loaded, with the reason.
+    pub failed: Vec<(String, String)>,
+}
+
+impl Reload {
+    /// Returns `true` if the catalog served to the clients changed.
+    pub fn changed(&self) -> bool {
+        !(self.added.is_empty() && self.updated.is_empty() && self.removed.is_empty())
+    }
+}
+
+pub struct GameCatalog {
+    folder: PathBuf,
+    // Every loaded game, and the enabled ones served to the clients
+    loaded: RwLock<Arc<Games>>,
+    games: RwLock<Arc<Games>>,
+    disabled: Mutex<HashSet<String>>,
+    // Digest of the files of each loaded game, also held for the whole reload so that they never
+    // run concurrently
+    digests: Mutex<HashMap<String, String>>,
+}
+
+impl GameCatalog {
+    /// Creates an empty catalog for the games of `folder`, filled by [`GameCatalog::reload`].
+    pub fn new(folder: &Path) -> Self {
+        Self {
+            folder: folder.to_path_buf(),
+            loaded: RwLock::new(Arc::new(Games::new())),
+            games: RwLock::new(Arc::new(Games::new())),
+            disabled: Mutex::new(HashSet::new()),
+            digests: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn folder(&self) -> &Path {
+        &self.folder
+    }
+
+    /// Current version of the enabled games, unaffected by the following reloads.
+    pub fn snapshot(&self) -> Arc<Games> {
+        self.games.read().unwrap().clone()
+    }
+
+    /// Information of the games of the catalog, sorted by identifier.
+    pub fn infos(&self) -> Vec<GameInfo> {
+        let mut infos: Vec<GameInfo> = self
+            .snapshot()
+            .values()
+            .map(|g| g.definition.info.clone())
+            .collect();
+        infos.sort_by(|a, b| a.id.cmp(&b.id));
+
+        infos
+    }
+
+    /// Every loaded game with whether it is enabled, sorted by identifier.
+    pub fn statuses(&self) -> Vec<GameStatus> {
+        let disabled = self.disabled.lock().unwrap();
+        let mut statuses: Vec<GameStatus> = self
+            .loaded
+            .read()
+            .unwrap()
+            .values()
+            .map(|g| GameStatus {
+                id: g.definition.info.id.clone(),
+                name: g.definition.info.name.clone(),
+                enabled: !disabled.contains(&g.definition.info.id),
+            })
+            .collect();
+        statuses.sort_by(|a, b| a.id.cmp(&b.id));
+
+        statuses
+    }
+
+    /// Enables or disables a loaded game. Returns `true` if the games served to the clients
+    /// changed.
+    pub fn set_enabled(&self, id: &str, enabled: bool) -> Result<bool> {
+        let mut disabled = self.disabled.lock().unwrap();
+        let loaded = self.loaded.read().unwrap().clone();
+        if !loaded.contains_key(id) {
+            bail!("Unknown game \"{}\"", id);
+        }
+
+        let changed = if enabled {
+            disabled.remove(id)
+        } else {
+            disabled.insert(id.to_string())
+        };
+        *self.games.write().unwrap() = Arc::new(enabled_games(&loaded, &disabled));
+
+        Ok(changed)
+    }
+
+    /// Loads the new and changed games of the folder and swaps them in. The games are drawn with
+    /// the generator given by `rng` for their identifier.
+    pub fn reload(&self, rng: impl Fn(&str) -> RngSpec) -> Result<Reload> {
+        let mut digests = self.digests.lock().unwrap();
+        let current = self.loaded.read().unwrap().clone();
+        let mut games = Games::new();
+        let mut loaded_digests = HashMap::new();
+        let mut reload = Reload::default();
+
+        for entry in fs::read_dir(&self.folder)? {
+            let entry = entry?;
+            if !entry.metadata()?.is_dir() {
+                continue;
+            }
+            let id = entry.file_name().to_string_lossy().to_string();
+            let previous = current.get(&id).zip(digests.get(&id));
+
+            let loaded = folder_digest(&entry.path()).and_then(|digest| match previous {
+                Some((game, previous)) if *previous == digest => Ok((game.clone(), digest)),
+                _ => {
+                    let mut game = Game::load(&entry.path())?;
+                    let spec = rng(&id);
+                    // A changed game goes on with the sequence of its generator, unless that changed
+                    game.rng = match current.get(&id) {
+                        Some(previous) if previous.rng.lock().unwrap().spec() == spec => {
+                            previous.rng.clone()
+                        }
+                        _ => Arc::new(Mutex::new(SlotRng::new(spec))),
+                    };
+                    // Gambles aren't replayed, their generator is never seeded
+                    *game.gamble_rng.get_mut().unwrap() =
+                        SlotRng::new(RngSpec { seed: None, ..spec });
+                    if current.contains_key(&id) {
+                        reload.updated.push(id.clone());
+                    } else {
+                        reload.added.push(id.clone());
+                    }
+
+                    Ok((Arc::new(game), digest))
+                }
+            });
+
+            match (loaded, previous) {
+                (Ok((game, digest)), _) => {
+                    games.insert(id.clone(), game);
+                    loaded_digests.insert(id, digest);
+                }
+                // Keep serving the previous version until the game is fixed
+                (Err(err), Some((game, digest))) => {
+                    reload.failed.push((id.clone(), format!("{:#}", err)));
+                    games.insert(id.clone(), game.clone());
+                    loaded_digests.insert(id, digest.clone());
+                }
+                (Err(err), None) => reload.failed.push((id, format!("{:#}", err))),
+            }
+        }
+
+        reload.removed = current
+            .keys()
+            .filter(|id| !games.contains_key(*id))
+            .cloned()
+            .collect();
+        for ids in [&mut reload.added, &mut reload.updated, &mut reload.removed] {
+            ids.sort();
+        }
+
+        let disabled = self.disabled.lock().unwrap();
+        *self.games.write().unwrap() = Arc::new(enabled_games(&games, &disabled));
+        *self.loaded.write().unwrap() = Arc::new(games);
+        *digests = loaded_digests;
+
+        Ok(reload)
+    }
+}
+
+fn enabled_games(games: &Games, disabled: &HashSet<String>) -> Games {
+    games
+        .iter()
+        .filter(|(id, _)| !disabled.contains(*id))
+        .map(|(id, game)| (id.clone(), game.clone()))
+        .collect()
+}
+
+/// Hashes the names and contents of the files of a game folder.
+fn folder_digest(folder: &Path) -> Result<String> {
+    let mut files = fs::read_dir(folder)?
+        .map(|entry| Ok(entry?.path()))
+        .collect::<Result<Vec<_>>>()?;
+    files.sort();
+
+    let mut hasher = Sha256::new();
+    for file in files.iter().filter(|f| f.is_file()) {
+        hasher.update(file.file_name().unwrap_or_default().as_encoded_bytes());
+        hasher.update(fs::read(file)?);
+    }
+
+    Ok(to_hex(&hasher.finalize()))
+}
+
+/// Reloads the games of the server and lets the clients know about the new catalog.
+pub fn reload_games(server: &Server) -> Result<Reload> {
+    let reload = server
+        .games
+        .reload(|id| server.config.game_rng(id))
+        .inspect_err(|err| error!(error = format!("{:#}", err), "Could not reload games"))?;
+
+    for (game, err) in reload.failed.iter() {
+        warn!(%game, error = %err, "Could not load game, keeping the previous version");
+    }
+    info!(
+        added = ?reload.added,
+        updated = ?reload.updated,
+        removed = ?reload.removed,
+        "Reloaded games"
+    );
+    if reload.changed() {
+        server.bus.publish(ServerEvent::GamesChanged {
+            games: server.games.infos(),
+        });
+    }
+
+    Ok(reload)
+}
+
+/// Reloads the games whenever the daemon receives a SIGHUP.
+pub fn spawn_reload_on_hangup(server: Arc<Server>) -> Result<()> {
+    let mut signals = Signals::new([SIGHUP])?;
+    thread::spawn(move || {
+        for _ in signals.forever() {
+            info!("Received SIGHUP, reloading games...");
+            let _ = reload_games(&server);
+        }
+    });
+
+    Ok(())
+}
+
+/// Reloads the games whenever a file of the games folder changes.
+pub fn spawn_watcher(server: Arc<Server>) -> Result<()> {
+    let (sender, receiver) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        // Reading the files of the games triggers access events
+        if event.is_ok_and(|e| !e.kind.is_access()) {
+            let _ = sender.send(());
+        }
+    })?;
+    watcher.watch(server.games.folder(), RecursiveMode::Recursive)?;
+
+    thread::spawn(move || {
+        // The watcher stops when dropped
+        let _watcher = watcher;
+        while receiver.recv().is_ok() {
+            thread::sleep(WATCH_DEBOUNCE);
+            while receiver.try_recv().is_ok() {}
+
+            info!("Games folder changed, reloading games...");
+            let _ = reload_games(&server);
+        }
+    });
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn changed_games_keep_their_generator() {
+        let folder =
+            std::env::temp_dir().join(format!("slot-machine-games-{}", std::process::id()));
+        let game = folder.join("blaze7");
+        fs::create_dir_all(&game).unwrap();
+        let source = Path::new(env!("CARGO_MANIFEST_DIR")).join("data/games/blaze7");
+        for file in fs::read_dir(source).unwrap() {
+            let file = file.unwrap();
+            fs::copy(file.path(), game.join(file.file_name())).unwrap();
+        }
+        let catalog = GameCatalog::new(&folder);
+        let seeded: RngSpec = "chacha20:42".parse().unwrap();
+        let draws = || catalog.snapshot()["blaze7"].rng.lock().unwrap().draws();
+
+        catalog.reload(|_| seeded).unwrap();
+        catalog.snapshot()["blaze7"]
+            .rng
+            .lock()
+            .unwrap()
+            .draw_stops(3, 72);
+        fs::write(game.join("gameinfo.json"), r#"{"name": "Blaze 7"}"#).unwrap();
+        let reload = catalog.reload(|_| seeded).unwrap();
+        assert_eq!(reload.updated, ["blaze7"]);
+        assert_eq!(draws(), 1);
+
+        // Another generator starts its own sequence
+        fs::write(game.join("gameinfo.json"), r#"{"name": "Blaze"}"#).unwrap();
+        catalog.reload(|_| "chacha20:7".parse().unwrap()).unwrap();
+        assert_eq!(draws(), 0);
+
+        fs::remove_dir_all(&folder).unwrap();
+    }
+}
@@ -0,0 +1,233 @@
+//! Asynchronous listeners of the daemon, one per address of the `transport` module.
+//!
+//! Whatever the transport, an accepted connection is split into a [`MessageReader`] and a
+//! [`MessageWriter`] exchanging newline-free messages, so the sessions don't depend on the
+//! transport. The TLS and WebSocket handshakes are completed by [`Accepted::handshake`] in the
+//! task of the connection, so a slow client never holds up the listener.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use anyhow::Result;
+use futures_util::stream::{SplitSink, SplitStream};
+use futures_util::{SinkExt, StreamExt};
+use rustls::crypto::ring;
+use rustls::ServerConfig;
+use slot_machine::transport::{load_or_generate_certificate, Address};
+use slot_machine::MAX_BYTES_READ;
+use tokio::io::{
+    AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader, ReadHalf,
+    WriteHalf,
+};
+use tokio::net::{TcpListener, TcpStream, UnixListener, UnixStream};
+use tokio_rustls::TlsAcceptor;
+use tokio_tungstenite::tungstenite::protocol::WebSocketConfig;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::WebSocketStream;
+
+/// Byte stream of a transport, read and written from different tasks.
+pub trait ByteStream: AsyncRead + AsyncWrite + Send + Unpin {}
+impl<T: AsyncRead + AsyncWrite + Send + Unpin> ByteStream for T {}
+
+type BoxStream = Pin<Box<dyn ByteStream>>;
+
+/// A server socket accepting client connections for a given transport.
+pub enum Listener {
+    Unix(UnixListener),
+    Tcp(TcpListener),
+    Tls(TcpListener, TlsAcceptor),
+    WebSocket(TcpListener),
+}
+
+/// A connection accepted by a [`Listener`], before its handshake.
+pub enum Accepted {
+    Unix(UnixStream),
+    Tcp(TcpStream),
+    Tls(TcpStream, TlsAcceptor),
+    WebSocket(TcpStream),
+}
+
+impl Listener {
+    /// Starts listening on the given address, a TLS one serving the certificate of the address with
+    /// the given private key. An existing UNIX socket file is replaced.
+    pub fn bind(address: &Address, tls_key: &Path) -> Result<Listener> {
+        Ok(match address {
+            Address::Unix(path) => {
+                let _ = fs::remove_file(path);
+                Listener::Unix(UnixListener::bind(path)?)
+            }
+            Address::Tcp(host) => Listener::Tcp(bind_tcp(host)?),
+            Address::Tls(host, cert) => {
+                let (certs, key) = load_or_generate_certificate(cert, tls_key)?;
+                let config =
+                    ServerConfig::builder_with_provider(Arc::new(ring::default_provider()))
+                        .with_safe_default_protocol_versions()?
+                        .with_no_client_auth()
+                        .with_single_cert(certs, key)?;
+
+                Listener::Tls(bind_tcp(host)?, TlsAcceptor::from(Arc::new(config)))
+            }
+            Address::WebSocket(host) => Listener::WebSocket(bind_tcp(host)?),
+        })
+    }
+
+    /// Waits for a new client connection, returned with its address in the address format.
+    pub async fn accept(&self) -> io::Result<(Accepted, String)> {
+        Ok(match self {
+            Listener::Unix(listener) => {
+                let (stream, _) = listener.accept().await?;
+                // Local clients have no address of their own, they are told apart by their user
+                let uid = stream.peer_cred()?.uid();
+                (Accepted::Unix(stream), format!("unix://uid:{}", uid))
+            }
+            Listener::Tcp(listener) => {
+                let (stream, address) = listener.accept().await?;
+                (Accepted::Tcp(stream), format!("tcp://{}", address))
+            }
+            Listener::Tls(listener, acceptor) => {
+                let (stream, address) = listener.accept().await?;
+                (
+                    Accepted::Tls(stream, acceptor.clone()),
+                    format!("tls://{}", address),
+                )
+            }
+            Listener::WebSocket(listener) => {
+                let (stream, address) = listener.accept().await?;
+                (Accepted::WebSocket(stream), format!("ws://{}", address))
+            }
+        })
+    }
+}
+
+// Tokio only binds asynchronously, go through the standard listener to bind before serving
+fn bind_tcp(host: &str) -> io::Result<TcpListener> {
+    let listener = std::net::TcpListener::bind(host)?;
+    listener.set_nonblocking(true)?;
+
+    TcpListener::from_std(listener)
+}
+
+impl Accepted {
+    /// Completes the TLS or WebSocket handshake of the connection.
+    pub async fn handshake(self) -> io::Result<(MessageReader, MessageWriter)> {
+        let stream: BoxStream = match self {
+            Accepted::Unix(stream) => Box::pin(stream),
+            Accepted::Tcp(stream) => Box::pin(stream),
+            Accepted::Tls(stream, acceptor) => Box::pin(acceptor.accept(stream).await?),
+            Accepted::WebSocket(stream) => {
+                let config =
+                    WebSocketConfig::default().max_message_size(Some(MAX_BYTES_READ as usize));
+                let websocket = tokio_tungstenite::accept_async_with_config(stream, Some(config))
+                    .await
+                    .map_err(io::Error::other)?;
+                let (sink, stream) = websocket.split();
+
+                return Ok((
+                    MessageReader::WebSocket(stream),
+                    MessageWriter::WebSocket(sink),
+                ));
+            }
+        };
+
+        let (reader, writer) = tokio::io::split(stream);
+        Ok((
+            MessageReader::Stream(BufReader::new(reader), Vec::new()),
+            MessageWriter::Stream(writer),
+        ))
+    }
+}
+
+/// Receiving half of a client connection.
+pub enum MessageReader {
+    /// Reader with the start of the message being read.
+    Stream(BufReader<ReadHalf<BoxStream>>, Vec<u8>),
+    WebSocket(SplitStream<WebSocketStream<TcpStream>>),
+}
+
+impl MessageReader {
+    /// Reads the next message, `None` when the connection is closed. Messages longer than
+    /// `MAX_BYTES_READ` are truncated like in `read_socket_message`.
+    ///
+    /// It can be cancelled (e.g. in a `select!`) without losing the start of a message, which is
+    /// kept until the next call.
+    pub async fn next(&mut self) -> io::Result<Option<String>> {
+        match self {
+            MessageReader::Stream(reader, partial) => {
+                let limit = MAX_BYTES_READ.saturating_sub(partial.len() as u64);
+                let bytes_read = (&mut *reader)
+                    .take(limit)
+                    .read_until(b'\n', partial)
+                    .await?;
+                if bytes_read == 0 && partial.is_empty() {
+                    return Ok(None);
+                }
+                let buf = std::mem::take(partial);
+
+                // Discard the rest of a message that is too long
+                if buf.last() != Some(&b'\n') && buf.len() as u64 == MAX_BYTES_READ {
+                    let mut rest = Vec::new();
+                    while (&mut *reader)
+                        .take(MAX_BYTES_READ)
+                        .read_until(b'\n', &mut rest)
+                        .await?
+                        > 0
+                        && rest.last() != Some(&b'\n')
+                    {
+                        rest.clear();
+                    }
+                }
+
+                Ok(Some(String::from_utf8_lossy(&buf).trim_end().to_string()))
+            }
+            MessageReader::WebSocket(stream) => loop {
+                let message = match stream.next().await {
+                    Some(message) => message.map_err(io::Error::other)?,
+                    None => return Ok(None),
+                };
+
+                match message {
+                    Message::Text(text) => return Ok(Some(text.trim_end().to_string())),
+                    Message::Binary(data) => {
+                        return Ok(Some(String::from_utf8_lossy(&data).trim_end().to_string()))
+                    }
+                    Message::Close(_) => return Ok(None),
+                    Message::Ping(_) | Message::Pong(_) | Message::Frame(_) => continue,
+                }
+            },
+        }
+    }
+}
+
+/// Sending half of a client connection.
+pub enum MessageWriter {
+    Stream(WriteHalf<BoxStream>),
+    WebSocket(SplitSink<WebSocketStream<TcpStream>, Message>),
+}
+
+impl MessageWriter {
+    /// Sends a single message, as a line or a WebSocket text message.
+    pub async fn send(&mut self, message: String) -> io::Result<()> {
+        match self {
+            MessageWriter::Stream(writer) => {
+                writer.write_all(message.as_bytes()).await?;
+                writer.write_all(b"\n").await?;
+                writer.flush().await
+            }
+            MessageWriter::WebSocket(sink) => sink
+                .send(Message::text(message))
+                .await
+                .map_err(io::Error::other),
+        }
+    }
+
+    /// Closes the connection once the pending messages are sent.
+    pub async fn close(&mut self) {
+        let _ = match self {
+            MessageWriter::Stream(writer) => writer.shutdown().await,
+            MessageWriter::WebSocket(sink) => sink.close().await.map_err(io::Error::other),
+        };
+    }
+}
@@ -0,0 +1,468 @@
+//! Server code
+
+use std::collections::HashSet;
+use std::fs::OpenOptions;
+use std::net::Shutdown;
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use slot_machine::accounts::AccountStore;
+use slot_machine::audit::AuditLog;
+use slot_machine::protocol::{ClientCommand, CommandError, ServerEvent, ServerResponse};
+use tokio::runtime::Runtime;
+use tokio::sync::{mpsc, watch, Notify, Semaphore};
+use tokio::task;
+use tokio::time::{sleep, sleep_until, timeout};
+use tracing::{debug, error, field, info, info_span, trace, warn, Instrument, Span};
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
+
+use crate::admin::spawn_admin_socket;
+use crate::config::{Config, LogFormat, USAGE};
+use crate::events::{spawn_heartbeat, EventBus};
+use crate::games::{spawn_reload_on_hangup, spawn_watcher, GameCatalog};
+use crate::listener::{Accepted, Listener};
+use crate::metrics::{spawn_metrics_endpoint, Metrics};
+use crate::rate_limit::{peer_host, AuthLimiter, RateLimiter};
+use crate::session::Session;
+
+mod admin;
+mod auth;
+mod config;
+mod events;
+mod games;
+mod listener;
+mod metrics;
+mod rate_limit;
+mod session;
+
+// Leaves time for the writers to deliver the shutdown notice before closing the connections
+const SHUTDOWN_NOTICE_DELAY: Duration = Duration::from_millis(200);
+// A client that doesn't complete its TLS or WebSocket handshake in time is dropped
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+const ACCEPT_RETRY_DELAY: Duration = Duration::from_millis(100);
+// Messages waiting to be written to a client, see `EventBus` for a client that doesn't keep up
+const OUTGOING_QUEUE_SIZE: usize = 64;
+
+/// State shared by every client connection.
+pub struct Server {
+    pub config: Config,
+    pub games: GameCatalog,
+    pub bus: Arc<EventBus>,
+    pub accounts: AccountStore,
+    pub audit: AuditLog,
+    pub metrics: Metrics,
+    pub auth_limiter: AuthLimiter,
+    running: AtomicBool,
+    stopping: Notify,
+}
+
+impl Server {
+    /// Whether the daemon keeps accepting clients.
+    pub fn is_running(&self) -> bool {
+        self.running.load(Ordering::Relaxed)
+    }
+
+    /// Asks the daemon to disconnect the clients and exit.
+    pub fn stop(&self) {
+        self.running.store(false, Ordering::Relaxed);
+        self.stopping.notify_one();
+    }
+}
+
+async fn handle_client(accepted: Accepted, peer: String, server: Arc<Server>) {
+    let (mut reader, mut writer) = match timeout(HANDSHAKE_TIMEOUT, accepted.handshake()).await {
+        Ok(Ok(halves)) => halves,
+        Ok(Err(err)) => {
+            debug!(%peer, error = %err, "Handshake failed");
+            return;
+        }
+        Err(_) => {
+            debug!(%peer, "Handshake timed out");
+            return;
+        }
+    };
+
+    let subscriptions = Arc::new(Mutex::new(HashSet::new()));
+    // Responses and events share a single writer so that messages never interleave
+    let (sender, mut receiver) = mpsc::channel::<ServerResponse>(OUTGOING_QUEUE_SIZE);
+    let (closer, mut closing) = watch::channel(None);
+    let subscriber_id =
+        server
+            .bus
+            .register(peer.clone(), closer, subscriptions.clone(), sender.clone());
+
+    // Every event of the connection is logged with the client and, once logged in, the player
+    let span = info_span!("client", id = subscriber_id, %peer, player = field::Empty);
+    async move {
+        info!("Accepted client");
+        server.metrics.client_connected();
+
+        let mut session = Session::new(subscriber_id, subscriptions, server.clone());
+        let mut limiter = RateLimiter::new(&server.config.rate_limits);
+        let mut writer_task = tokio::spawn(
+            async move {
+                while let Some(response) = receiver.recv().await {
+                    let message = serde_json::to_string(&response).unwrap();
+                    if writer.send(message).await.is_err() {
+                        break;
+                    }
+                }
+                writer.close().await;
+            }
+            .in_current_span(),
+        );
+
+        loop {
+            trace!("Waiting for next message...");
+            let reality_check = session.next_reality_check();
+            let message = tokio::select! {
+                message = reader.next() => message,
+                _ = closing.changed() => break,
+                _ = sleep_until(reality_check.unwrap_or_else(Instant::now).into()),
+                    if reality_check.is_some() =>
+                {
+                    if let Some(event) = task::block_in_place(|| session.reality_check()) {
+                        if sender.send(ServerResponse::Event(event)).await.is_err() {
+                            break;
+                        }
+                    }
+                    continue;
+                }
+            };
+            let Ok(Some(buf)) = message else {
+                break;
+            };
+
+            let response = match serde_json::from_str::<ClientCommand>(&buf) {
+                Ok(client_command) => {
+                    let received_at = Instant::now();
+                    let name = client_command.name();
+                    // Never log the credentials
+                    match &client_command {
+                        ClientCommand::Register { player, .. }
+                        | ClientCommand::Login { player, .. } => {
+                            debug!(%player, "Parsed authentication command")
+                        }
+                        ClientCommand::Resume { .. } => debug!("Parsed session resume command"),
+                        _ => debug!(command = ?client_command, "Parsed command"),
+                    }
+                    // The whole command is handled with the same version of the games. It
+                    // blocks on the database and the password hashing, let the runtime move the
+                    // other tasks of this worker elsewhere meanwhile.
+                    let limited = match &client_command {
+                        ClientCommand::Login { player, .. } => {
+                            server
+                                .auth_limiter
+                                .check(name, peer_host(&peer), Some(player))
+                        }
+                        ClientCommand::Register { .. } | ClientCommand::Resume { .. } => {
+                            server.auth_limiter.check(name, peer_host(&peer), None)
+                        }
+                        _ => limiter.check(name),
+                    };
+                    let response = limited.and_then(|_| {
+                        task::block_in_place(|| {
+                            session.handle_command(client_command, &server.games.snapshot())
+                        })
+                    });
+                    server.metrics.request(name, received_at.elapsed());
+
+                    response
+                }
+                Err(_) => {
+                    debug!(bytes = buf.len(), "Unrecognized client command");
+                    Err(CommandError::MalformedCommand)
+                }
+            }
+            .unwrap_or_else(|err| {
+                debug!(error = %err, "Rejected command");
+                server.metrics.error(err.code());
+                err.into()
+            });
+
+            // Keep the other sessions of the player up to date with the account balance
+            match (&response, session.player()) {
+                (ServerResponse::LoggedIn { player, .. }, _) => {
+                    Span::current().record("player", player.as_str());
+                    server.bus.identify(subscriber_id, player)
+                }
+                (ServerResponse::Spin { balance, .. }, Some(player))
+                | (ServerResponse::Gamble { balance, .. }, Some(player)) => {
+                    server.bus.publish_to_player(
+                        player,
+                        Some(subscriber_id),
+                        ServerEvent::BalanceChanged { balance: *balance },
+                    )
+                }
+                _ => {}
+            }
+
+            // Waits for room in the outgoing queue: a client that doesn't read its responses
+            // isn't read either
+            if sender.send(response).await.is_err() {
+                break;
+            }
+        }
+
+        // Deliver the last responses before the connection is dropped, unless it must be closed
+        // right away
+        server.bus.unregister(subscriber_id);
+        drop(sender);
+        tokio::select! {
+            _ = &mut writer_task => {}
+            Ok(_) = closing.wait_for(|how| *how == Some(Shutdown::Both)) => writer_task.abort(),
+        }
+
+        server.metrics.client_disconnected();
+        info!("Client connection terminated");
+    }
+    .instrument(span)
+    .await
+}
+
+/// Accepts the clients of a listener, up to the maximum number of connections of the daemon.
+async fn accept_clients(listener: Listener, server: Arc<Server>, connections: Arc<Semaphore>) {
+    let mut at_capacity = false;
+    loop {
+        // At capacity, the new clients wait in the backlog of the listener
+        let permit = match connections.clone().try_acquire_owned() {
+            Ok(permit) => {
+                at_capacity = false;
+                permit
+            }
+            Err(_) => {
+                if !at_capacity {
+                    warn!(
+                        max_connections = server.config.max_connections,
+                        "Too many clients, waiting for one to disconnect"
+                    );
+                    at_capacity = true;
+                }
+                connections
+                    .clone()
+                    .acquire_owned()
+                    .await
+                    .expect("Connections semaphore closed")
+            }
+        };
+
+        match listener.accept().await {
+            Ok((accepted, peer)) => {
+                let server = server.clone();
+                tokio::spawn(async move {
+                    handle_client(accepted, peer, server).await;
+                    drop(permit);
+                });
+            }
+            Err(err) => {
+                // E.g. out of file descriptors, give the clients some time to release them
+                warn!(error = %err, "Could not accept client");
+                sleep(ACCEPT_RETRY_DELAY).await;
+            }
+        }
+    }
+}
+
+/// Serves the clients of every listener until the daemon is stopped.
+async fn serve(server: Arc<Server>, listeners: Vec<Listener>) {
+    let connections = Arc::new(Semaphore::new(server.config.max_connections));
+    let accept_tasks: Vec<_> = listeners
+        .into_iter()
+        .map(|listener| {
+            tokio::spawn(accept_clients(
+                listener,
+                server.clone(),
+                connections.clone(),
+            ))
+        })
+        .collect();
+
+    server.stopping.notified().await;
+    // Dropping the listeners stops accepting
+    accept_tasks.iter().for_each(|task| task.abort());
+
+    shutdown(&server, &connections).await;
+}
+
+/// Sets up the daemon logs with the configured level, format and output.
+fn init_logging(config: &Config) -> Result<Option<WorkerGuard>> {
+    let (writer, guard) = match &config.log_file {
+        Some(path) => {
+            let file = OpenOptions::new().create(true).append(true).open(path)?;
+            let (writer, guard) = tracing_appender::non_blocking(file);
+            (BoxMakeWriter::new(writer), Some(guard))
+        }
+        None => (BoxMakeWriter::new(std::io::stdout), None),
+    };
+
+    let logs = tracing_subscriber::fmt()
+        .with_max_level(config.log_level)
+        .with_target(false)
+        .with_ansi(config.log_file.is_none())
+        .with_writer(writer);
+    match config.log_format {
+        LogFormat::Text => logs.init(),
+        LogFormat::Json => logs.json().with_span_list(false).init(),
+    }
+
+    Ok(guard)
+}
+
+fn main() -> ExitCode {
+    let config = match Config::load(std::env::args().skip(1)) {
+        Ok(Some(config)) => config,
+        Ok(None) => {
+            println!("{}", USAGE);
+            return ExitCode::SUCCESS;
+        }
+        Err(err) => {
+            eprintln!("{:#}\n\n{}", err, USAGE);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    // Keeps writing the logs to the file until the daemon exits
+    let _log_guard = match init_logging(&config) {
+        Ok(guard) => guard,
+        Err(err) => {
+            eprintln!("Could not open log file: {:#}", err);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let runtime = Runtime::new().expect("Could not start the runtime");
+    let listeners: Vec<Listener> = {
+        let _runtime = runtime.enter();
+        config
+            .listen
+            .iter()
+            .map(|address| {
+                info!(%address, "Starting new listening socket...");
+                Listener::bind(address, &config.tls_key).expect("Could not bind listening socket")
+            })
+            .collect()
+    };
+
+    let accounts =
+        AccountStore::open(&config.accounts_database).expect("Could not open accounts database");
+    let audit = AuditLog::open(&config.audit_log).expect("Could not open audit log");
+
+    let report = accounts.check().expect("Could not check the ledger");
+    if !report.is_consistent() {
+        error!(
+            "Ledger is inconsistent with the accounts balances:\n{}",
+            report
+        );
+    }
+
+    let server = Arc::new(Server {
+        games: GameCatalog::new(&config.games_folder),
+        auth_limiter: AuthLimiter::new(&config.rate_limits),
+        config,
+        bus: Arc::new(EventBus::default()),
+        accounts,
+        audit,
+        metrics: Metrics::default(),
+        running: AtomicBool::new(true),
+        stopping: Notify::new(),
+    });
+
+    info!(folder = %server.config.games_folder.display(), "Loading games...");
+    let reload = server
+        .games
+        .reload(|id| server.config.game_rng(id))
+        .expect("Failed to load games");
+    if !reload.failed.is_empty() {
+        reload
+            .failed
+            .iter()
+            .for_each(|(game, err)| error!(%game, error = %err, "Could not load game"));
+        return ExitCode::FAILURE;
+    }
+
+    let games = server.games.snapshot();
+    for id in reload.added.iter() {
+        info!(game = %id, rng = %server.config.game_rng(id), "Loaded game");
+        if server.config.print_tables {
+            println!("{}", games[id].table);
+        }
+    }
+    for id in server.config.games_rng.keys() {
+        if !games.contains_key(id) {
+            warn!(game = %id, "Generator given for unknown game");
+        }
+    }
+
+    if server.config.watch_games {
+        spawn_watcher(server.clone()).expect("Could not watch the games folder");
+    }
+    spawn_reload_on_hangup(server.clone()).expect("Could not handle SIGHUP");
+    if let Some(address) = &server.config.metrics_listen {
+        spawn_metrics_endpoint(server.clone(), address).expect("Could not serve metrics");
+    }
+    if let Some(path) = &server.config.admin_socket {
+        if let Err(err) = spawn_admin_socket(server.clone(), path) {
+            error!(error = format!("{:#}", err), "Could not open admin socket");
+            return ExitCode::FAILURE;
+        }
+    }
+
+    let server_handle = server.clone();
+    spawn_heartbeat(server.bus.clone());
+
+    ctrlc::set_handler(move || {
+        // A second Ctrl-C doesn't wait for the clients
+        if !server_handle.is_running() {
+            warn!("Forced exit");
+            std::process::exit(1);
+        }
+        server_handle.stop();
+    })
+    .expect("Error setting Ctrl-C handler");
+
+    runtime.block_on(serve(server, listeners));
+    // Don't wait for the clients that didn't finish in time
+    runtime.shutdown_timeout(SHUTDOWN_NOTICE_DELAY);
+
+    ExitCode::SUCCESS
+}
+
+/// Notifies the connected clients and lets them finish the commands being handled before
+/// closing the connections and persisting the state.
+async fn shutdown(server: &Server, connections: &Semaphore) {
+    let max_connections =
+        u32::try_from(server.config.max_connections).expect("Validated by the configuration");
+    info!(clients = server.bus.sessions().len(), "Shutting down...");
+
+    server.bus.publish(ServerEvent::Shutdown {
+        message: "Server is shutting down".to_string(),
+    });
+    sleep(SHUTDOWN_NOTICE_DELAY).await;
+    server.bus.shutdown_all(Shutdown::Read);
+
+    // Each client gives its connection back once done, like the listeners once aborted
+    let drained = timeout(
+        server.config.shutdown_timeout(),
+        connections.acquire_many(max_connections),
+    )
+    .await;
+    if drained.is_err() {
+        warn!(
+            clients = server.bus.sessions().len(),
+            "Clients did not finish in time, closing their connections"
+        );
+        server.bus.shutdown_all(Shutdown::Both);
+    }
+
+    if let Err(err) = server.accounts.checkpoint() {
+        error!(error = %err, "Could not checkpoint the accounts database");
+    }
+    if let Some(path) = &server.config.admin_socket {
+        let _ = std::fs::remove_file(path);
+    }
+    info!("Stopped");
+}
@@ -0,0 +1,308 @@
+//! Live statistics of the daemon, served in the Prometheus text format.
+//!
+//! The return to player and hit frequency observed on each game are exposed next to the values
+//! expected from its par sheet for the same bets, so a game drifting from its par sheet shows up as
+//! the two series diverging.
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use slot_machine::par_table::ParStats;
+use tracing::{debug, info};
+
+use crate::games::Games;
+use crate::Server;
+
+// Upper bounds of the buckets of the request latency histograms, in seconds
+const LATENCY_BUCKETS: [f64; 10] = [
+    0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 1.0,
+];
+// A scrape that doesn't send its request in time is dropped
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Default)]
+struct GameMetrics {
+    spins: u64,
+    coin_in: u64,
+    coin_out: u64,
+    hits: u64,
+    // Sums of the par values of each spin, for the bets actually played
+    expected_out: f64,
+    expected_hits: f64,
+}
+
+#[derive(Default)]
+struct Histogram {
+    buckets: [u64; LATENCY_BUCKETS.len()],
+    count: u64,
+    sum: f64,
+}
+
+/// Counters updated by the sessions. Games stay listed once played, even if they are removed.
+#[derive(Default)]
+pub struct Metrics {
+    clients: AtomicU64,
+    games: Mutex<BTreeMap<String, GameMetrics>>,
+    latencies: Mutex<BTreeMap<&'static str, Histogram>>,
+    errors: Mutex<BTreeMap<u64, u64>>,
+}
+
+impl Metrics {
+    pub fn client_connected(&self) {
+        self.clients.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn client_disconnected(&self) {
+        self.clients.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    /// Records a settled spin costing `cost` coins, with the par values of its bet size.
+    pub fn spin(&self, game: &str, cost: u64, win: u64, par: ParStats) {
+        let mut games = self.games.lock().unwrap();
+        let metrics = games.entry(game.to_string()).or_default();
+
+        metrics.spins += 1;
+        metrics.coin_in += cost;
+        metrics.coin_out += win;
+        metrics.hits += (win > 0) as u64;
+        metrics.expected_out += par.rtp * cost as f64;
+        metrics.expected_hits += par.hit_frequency;
+    }
+
+    /// Records a settled free spin. It only adds to the coins won, its expected value being part of
+    /// the par values of the paid spins.
+    pub fn free_spin(&self, game: &str, win: u64) {
+        let mut games = self.games.lock().unwrap();
+        games.entry(game.to_string()).or_default().coin_out += win;
+    }
+
+    /// Records the time taken to handle a command.
+    pub fn request(&self, command: &'static str, latency: Duration) {
+        let mut latencies = self.latencies.lock().unwrap();
+        let histogram = latencies.entry(command).or_default();
+        let seconds = latency.as_secs_f64();
+
+        histogram.count += 1;
+        histogram.sum += seconds;
+        LATENCY_BUCKETS
+            .iter()
+            .zip(histogram.buckets.iter_mut())
+            .filter(|(bound, _)| seconds <= **bound)
+            .for_each(|(_, bucket)| *bucket += 1);
+    }
+
+    /// Records an error sent to a client.
+    pub fn error(&self, code: u64) {
+        *self.errors.lock().unwrap().entry(code).or_default() += 1;
+    }
+
+    /// Renders the metrics in the Prometheus text format, along with the par sheets of `games`.
+    pub fn render(&self, games: &Games) -> String {
+        let mut out = String::new();
+        // Writing to a `String` never fails
+        let mut metric = |name: &str, kind: &str, help: &str, samples: Vec<(String, String)>| {
+            let _ = writeln!(out, "# HELP {} {}\n# TYPE {} {}", name, help, name, kind);
+            for (labels, value) in samples {
+                let _ = writeln!(out, "{}{} {}", name, labels, value);
+            }
+        };
+
+        metric(
+            "slot_clients",
+            "gauge",
+            "Connected clients.",
+            vec![(
+                String::new(),
+                self.clients.load(Ordering::Relaxed).to_string(),
+            )],
+        );
+
+        let played = self.games.lock().unwrap();
+        let per_game = |value: &dyn Fn(&GameMetrics) -> String| -> Vec<(String, String)> {
+            played
+                .iter()
+                .map(|(game, m)| (format!("{{game=\"{}\"}}", escape(game)), value(m)))
+                .collect()
+        };
+        metric(
+            "slot_spins_total",
+            "counter",
+            "Spins played.",
+            per_game(&|m| m.spins.to_string()),
+        );
+        metric(
+            "slot_coin_in_total",
+            "counter",
+            "Coins wagered.",
+            per_game(&|m| m.coin_in.to_string()),
+        );
+        metric(
+            "slot_coin_out_total",
+            "counter",
+            "Coins won.",
+            per_game(&|m| m.coin_out.to_string()),
+        );
+        metric(
+            "slot_hits_total",
+            "counter",
+            "Winning spins.",
+            per_game(&|m| m.hits.to_string()),
+        );
+        metric(
+            "slot_rtp_observed",
+            "gauge",
+            "Coins won per coin wagered.",
+            per_game(&|m| ratio(m.coin_out as f64, m.coin_in)),
+        );
+        metric(
+            "slot_rtp_expected",
+            "gauge",
+            "Return to player of the par sheet for the bets played.",
+            per_game(&|m| ratio(m.expected_out, m.coin_in)),
+        );
+        metric(
+            "slot_hit_frequency_observed",
+            "gauge",
+            "Share of winning spins.",
+            per_game(&|m| ratio(m.hits as f64, m.spins)),
+        );
+        metric(
+            "slot_hit_frequency_expected",
+            "gauge",
+            "Hit frequency of the par sheet for the bets played.",
+            per_game(&|m| ratio(m.expected_hits, m.spins)),
+        );
+        drop(played);
+
+        let mut ids: Vec<&String> = games.keys().collect();
+        ids.sort();
+        let per_bet = |value: fn(&ParStats) -> f64| -> Vec<(String, String)> {
+            ids.iter()
+                .flat_map(|id| {
+                    games[*id].par.iter().enumerate().map(move |(bet, par)| {
+                        (
+                            format!("{{game=\"{}\",coins=\"{}\"}}", escape(id), bet + 1),
+                            value(par).to_string(),
+                        )
+                    })
+                })
+                .collect()
+        };
+        metric(
+            "slot_par_rtp",
+            "gauge",
+            "Return to player of the par sheet for each bet size.",
+            per_bet(|par| par.rtp),
+        );
+        metric(
+            "slot_par_hit_frequency",
+            "gauge",
+            "Hit frequency of the par sheet for each bet size.",
+            per_bet(|par| par.hit_frequency),
+        );
+
+        let latencies = self.latencies.lock().unwrap();
+        let mut samples = vec![];
+        for (command, histogram) in latencies.iter() {
+            for (bound, count) in LATENCY_BUCKETS.iter().zip(histogram.buckets) {
+                samples.push((
+                    format!("_bucket{{command=\"{}\",le=\"{}\"}}", command, bound),
+                    count.to_string(),
+                ));
+            }
+            samples.push((
+                format!("_bucket{{command=\"{}\",le=\"+Inf\"}}", command),
+                histogram.count.to_string(),
+            ));
+            samples.push((
+                format!("_sum{{command=\"{}\"}}", command),
+                histogram.sum.to_string(),
+            ));
+            samples.push((
+                format!("_count{{command=\"{}\"}}", command),
+                histogram.count.to_string(),
+            ));
+        }
+        drop(latencies);
+        metric(
+            "slot_request_duration_seconds",
+            "histogram",
+            "Time taken to handle the client commands.",
+            samples,
+        );
+
+        let errors = self.errors.lock().unwrap();
+        metric(
+            "slot_errors_total",
+            "counter",
+            "Errors sent to the clients, by error code.",
+            errors
+                .iter()
+                .map(|(code, count)| (format!("{{code=\"{}\"}}", code), count.to_string()))
+                .collect(),
+        );
+
+        out
+    }
+}
+
+fn ratio(value: f64, total: u64) -> String {
+    if total == 0 {
+        return "NaN".to_string();
+    }
+
+    (value / total as f64).to_string()
+}
+
+fn escape(label: &str) -> String {
+    label.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Serves the metrics over HTTP on `/metrics` at the given `host:port`.
+pub fn spawn_metrics_endpoint(server: Arc<Server>, address: &str) -> io::Result<()> {
+    let listener = TcpListener::bind(address)?;
+    info!(%address, "Serving metrics on /metrics...");
+
+    thread::spawn(move || {
+        // Scrapes are rare and quick, they are answered one at a time
+        for stream in listener.incoming().flatten() {
+            if let Err(err) = serve_metrics(stream, &server) {
+                debug!(error = %err, "Could not serve metrics");
+            }
+        }
+    });
+
+    Ok(())
+}
+
+fn serve_metrics(mut stream: TcpStream, server: &Server) -> io::Result<()> {
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    let mut reader = BufReader::new(&stream);
+    let mut request = String::new();
+    reader.read_line(&mut request)?;
+    // The headers are not needed, read them up to the empty line ending them
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 2 {
+        header.clear();
+    }
+
+    let (status, body) = match request.split_whitespace().nth(1) {
+        Some("/metrics") => ("200 OK", server.metrics.render(&server.games.snapshot())),
+        _ => ("404 Not Found", "Not found\n".to_string()),
+    };
+
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )?;
+    stream.flush()
+}
@@ -0,0 +1,299 @@
+//! Rate limiting of the client commands, with a token bucket per session and per command type.
+//!
+//! Each command type gets a bucket holding up to `burst` tokens and refilled with `per_second`
+//! tokens every second. A command takes a token from its bucket, or is rejected with the time
+//! left until the next one when the bucket is empty, so a client spinning too fast doesn't hold
+//! up its `Status` pings.
+//!
+//! The buckets of the [`AUTH_COMMANDS`] outlive the connections instead (see [`AuthLimiter`]), so
+//! that a client can't get a fresh burst of password guesses by reconnecting.
+
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use serde_with::DeserializeFromStr;
+use slot_machine::protocol::CommandError;
+
+/// Commands without a limit of their own share the limit of this key.
+pub const DEFAULT_LIMIT_KEY: &str = "*";
+/// Commands limited per client host by the [`AuthLimiter`] rather than per session.
+pub const AUTH_COMMANDS: [&str; 3] = ["Register", "Login", "Resume"];
+// Past this many buckets, the full ones are dropped since they are the same as new ones
+const MAX_IDLE_AUTH_BUCKETS: usize = 4096;
+
+/// Limit of a command type, written as `<per_second>[/<burst>]` or `off`.
+#[derive(Debug, Clone, Copy, PartialEq, DeserializeFromStr)]
+pub enum RateLimit {
+    /// The commands are never rejected.
+    Off,
+    /// The commands are rejected once the bucket is empty.
+    Bucket {
+        /// Tokens added to the bucket every second.
+        per_second: f64,
+        /// Capacity of the bucket, i.e. the commands that can be sent at once.
+        burst: u32,
+    },
+}
+
+impl RateLimit {
+    /// Returns the limit of a command type among the configured limits.
+    fn of(limits: &HashMap<String, RateLimit>, command: &str) -> RateLimit {
+        limits
+            .get(command)
+            .or_else(|| limits.get(DEFAULT_LIMIT_KEY))
+            .copied()
+            .unwrap_or(RateLimit::Off)
+    }
+
+    /// Limits of the daemon when none are configured.
+    pub fn defaults() -> HashMap<String, RateLimit> {
+        let limit = |per_second, burst| RateLimit::Bucket { per_second, burst };
+
+        HashMap::from([
+            (DEFAULT_LIMIT_KEY.to_string(), limit(10.0, 20)),
+            ("Play".to_string(), limit(5.0, 5)),
+            // Slows down password guessing
+            ("Register".to_string(), limit(0.2, 3)),
+            ("Login".to_string(), limit(0.2, 3)),
+            ("Resume".to_string(), limit(0.2, 3)),
+        ])
+    }
+}
+
+impl FromStr for RateLimit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "off" {
+            return Ok(RateLimit::Off);
+        }
+
+        let invalid = || format!("Invalid rate limit \"{}\"", s);
+        let (per_second, burst) = match s.split_once('/') {
+            Some((per_second, burst)) => (
+                per_second.parse::<f64>().map_err(|_| invalid())?,
+                burst.parse::<u32>().map_err(|_| invalid())?,
+            ),
+            // Allows a second worth of commands at once
+            None => {
+                let per_second = s.parse::<f64>().map_err(|_| invalid())?;
+                (per_second, per_second.ceil().max(1.0) as u32)
+            }
+        };
+
+        if !per_second.is_finite() || per_second <= 0.0 || burst == 0 {
+            return Err(format!(
+                "Invalid rate limit \"{}\" (the rate and burst must be positive, or \"off\")",
+                s
+            ));
+        }
+
+        Ok(RateLimit::Bucket { per_second, burst })
+    }
+}
+
+struct TokenBucket {
+    per_second: f64,
+    burst: f64,
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl TokenBucket {
+    fn new(per_second: f64, burst: u32) -> Self {
+        Self {
+            per_second,
+            burst: f64::from(burst),
+            tokens: f64::from(burst),
+            refilled_at: Instant::now(),
+        }
+    }
+
+    // Takes a token, or returns the time until one is available
+    fn take(&mut self, now: Instant) -> Result<(), Duration> {
+        let elapsed = now
+            .saturating_duration_since(self.refilled_at)
+            .as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.per_second).min(self.burst);
+        self.refilled_at = now;
+
+        if self.tokens < 1.0 {
+            return Err(Duration::from_secs_f64(
+                (1.0 - self.tokens) / self.per_second,
+            ));
+        }
+        self.tokens -= 1.0;
+
+        Ok(())
+    }
+
+    fn is_full(&self, now: Instant) -> bool {
+        let elapsed = now
+            .saturating_duration_since(self.refilled_at)
+            .as_secs_f64();
+
+        self.tokens + elapsed * self.per_second >= self.burst
+    }
+}
+
+/// Token buckets of a session, created on the first command of each type. The [`AUTH_COMMANDS`]
+/// are left to the [`AuthLimiter`].
+pub struct RateLimiter {
+    limits: HashMap<String, RateLimit>,
+    buckets: HashMap<&'static str, TokenBucket>,
+}
+
+impl RateLimiter {
+    pub fn new(limits: &HashMap<String, RateLimit>) -> Self {
+        Self {
+            limits: limits.clone(),
+            buckets: HashMap::new(),
+        }
+    }
+
+    /// Takes a token for the command, named as in `ClientCommand::name`.
+    pub fn check(&mut self, command: &'static str) -> Result<(), CommandError> {
+        if AUTH_COMMANDS.contains(&command) {
+            return Ok(());
+        }
+        if !self.buckets.contains_key(command) {
+            match RateLimit::of(&self.limits, command) {
+                RateLimit::Off => return Ok(()),
+                RateLimit::Bucket { per_second, burst } => {
+                    self.buckets
+                        .insert(command, TokenBucket::new(per_second, burst));
+                }
+            }
+        }
+
+        self.buckets
+            .get_mut(command)
+            .unwrap()
+            .take(Instant::now())
+            .map_err(|retry_after| CommandError::RateLimited { retry_after })
+    }
+}
+
+/// Token buckets of the [`AUTH_COMMANDS`] shared by every session of the daemon. Each command
+/// takes a token from the bucket of the client host, and a `Login` from the bucket of the player
+/// too, so that guessing the password of a player from several hosts is slowed down as well.
+pub struct AuthLimiter {
+    limits: HashMap<String, RateLimit>,
+    buckets: Mutex<HashMap<(&'static str, String), TokenBucket>>,
+}
+
+impl AuthLimiter {
+    pub fn new(limits: &HashMap<String, RateLimit>) -> Self {
+        Self {
+            limits: limits.clone(),
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Takes a token for the authentication command from the client `host` (see [`peer_host`])
+    /// and the `player` it logs into, if any.
+    pub fn check(
+        &self,
+        command: &'static str,
+        host: &str,
+        player: Option<&str>,
+    ) -> Result<(), CommandError> {
+        let RateLimit::Bucket { per_second, burst } = RateLimit::of(&self.limits, command) else {
+            return Ok(());
+        };
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.len() > MAX_IDLE_AUTH_BUCKETS {
+            buckets.retain(|_, bucket| !bucket.is_full(now));
+        }
+
+        let keys = [
+            Some(format!("host:{}", host)),
+            player.map(|p| format!("player:{}", p)),
+        ];
+        for key in keys.into_iter().flatten() {
+            buckets
+                .entry((command, key))
+                .or_insert_with(|| TokenBucket::new(per_second, burst))
+                .take(now)
+                .map_err(|retry_after| CommandError::RateLimited { retry_after })?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Returns the host of a client address in the address format, without its transport and port, so
+/// that the connections of a client share their buckets whatever the port and transport. Local
+/// clients are addressed by their user id (`unix://uid:<uid>`), each user getting its own buckets.
+pub fn peer_host(peer: &str) -> &str {
+    match peer.split_once("://") {
+        Some(("unix", _)) | None => peer,
+        Some((_, address)) => address.rsplit_once(':').map_or(address, |(host, _)| host),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn buckets_refill_up_to_their_burst() {
+        let mut bucket = TokenBucket::new(2.0, 3);
+        let start = bucket.refilled_at;
+
+        for _ in 0..3 {
+            assert_eq!(bucket.take(start), Ok(()));
+        }
+        assert_eq!(bucket.take(start), Err(Duration::from_millis(500)));
+        assert_eq!(bucket.take(start + Duration::from_millis(500)), Ok(()));
+        assert!(bucket.take(start + Duration::from_millis(500)).is_err());
+
+        // Idle for long enough to refill more than the burst
+        let later = start + Duration::from_secs(10);
+        assert!(bucket.is_full(later));
+        for _ in 0..3 {
+            assert_eq!(bucket.take(later), Ok(()));
+        }
+        assert!(bucket.take(later).is_err());
+    }
+
+    #[test]
+    fn auth_buckets_are_kept_per_host_and_player() {
+        let limits = HashMap::from([
+            ("Login".to_string(), "0.001/1".parse().unwrap()),
+            ("Register".to_string(), "0.001/1".parse().unwrap()),
+        ]);
+        let limiter = AuthLimiter::new(&limits);
+        let limited = |result: Result<(), CommandError>| {
+            matches!(result, Err(CommandError::RateLimited { .. }))
+        };
+
+        assert!(limiter.check("Login", "10.0.0.1", Some("alice")).is_ok());
+        // The same host guessing another password, or another host guessing the same one
+        assert!(limited(limiter.check("Login", "10.0.0.1", Some("bob"))));
+        assert!(limited(limiter.check("Login", "10.0.0.2", Some("alice"))));
+        assert!(limiter.check("Login", "10.0.0.3", Some("bob")).is_ok());
+
+        // Each command type has its own buckets, and the commands without a limit have none
+        assert!(limiter.check("Register", "10.0.0.1", None).is_ok());
+        assert!(limited(limiter.check("Register", "10.0.0.1", None)));
+        assert!(limiter.check("Resume", "10.0.0.1", None).is_ok());
+        assert!(limiter.check("Resume", "10.0.0.1", None).is_ok());
+    }
+
+    #[test]
+    fn connections_of_a_host_share_its_key() {
+        for (peer, host) in [
+            ("tcp://10.0.0.1:51000", "10.0.0.1"),
+            ("tls://10.0.0.1:51001", "10.0.0.1"),
+            ("ws://[::1]:51002", "[::1]"),
+            ("unix://uid:1000", "unix://uid:1000"),
+        ] {
+            assert_eq!(peer_host(peer), host);
+        }
+        assert_ne!(peer_host("unix://uid:1000"), peer_host("unix://uid:1001"));
+    }
+}
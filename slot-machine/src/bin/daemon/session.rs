@@ -0,0 +1,1044 @@
+//! Per-connection state and command handling.
+
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+
+use itertools::Itertools;
+use slot_machine::accounts::{FreeSpinsLeft, JackpotSpin, SpinRecord};
+use slot_machine::audit::AuditRecord;
+use slot_machine::fairness::{
+    commitment, derive_stops, generate_server_seed, is_valid_client_seed, SeedPair, SpinProof,
+    FAIR_RNG,
+};
+use slot_machine::game::{GameInfo, SpinOutcome};
+use slot_machine::limits::{
+    LimitsStatus, DAILY_LOSS_PERIOD, MAX_SELF_EXCLUSION, MIN_REALITY_CHECK_INTERVAL,
+};
+use slot_machine::protocol::{
+    is_valid_player_name, ClientCommand, CommandError, EventKind, ServerEvent, ServerResponse,
+    ServerStatus, Status, MAX_STATEMENT_ENTRIES, MIN_PASSWORD_LENGTH,
+};
+use slot_machine::rng::RngSpec;
+use slot_machine::utils::format_duration;
+use tracing::{debug, error, info};
+
+use crate::auth::{generate_token, hash_password, verify_password};
+use crate::events::Subscriptions;
+use crate::games::Games;
+use crate::Server;
+
+/// State of a client connection. It is only modified by commands that passed validation.
+pub struct Session {
+    id: u64,
+    uptime: Instant,
+    player: Option<String>,
+    expires_at: SystemTime,
+    game: Option<String>,
+    // Start of the play session of the player, for the session limits and the reality checks
+    play_started_at: SystemTime,
+    reality_check: Option<(Duration, Instant)>,
+    // Win of the last spin that can be gambled, with its game and the gambles already won on it
+    gamble: Option<(String, u64, u32)>,
+    average_latency: f64,
+    status_query_count: u64,
+    subscriptions: Subscriptions,
+    server: Arc<Server>,
+}
+
+impl Session {
+    pub fn new(id: u64, subscriptions: Subscriptions, server: Arc<Server>) -> Self {
+        Self {
+            id,
+            uptime: Instant::now(),
+            player: None,
+            expires_at: SystemTime::UNIX_EPOCH,
+            game: None,
+            play_started_at: SystemTime::UNIX_EPOCH,
+            reality_check: None,
+            gamble: None,
+            average_latency: 0.0,
+            status_query_count: 1,
+            subscriptions,
+            server,
+        }
+    }
+
+    /// Player account the client logged into, if any.
+    pub fn player(&self) -> Option<&str> {
+        self.player.as_deref()
+    }
+
+    /// Returns the logged in player if the session token is still valid.
+    fn authenticated_player(&self) -> Result<&str, CommandError> {
+        let player = self.player.as_ref().ok_or(CommandError::NotAuthenticated)?;
+
+        if SystemTime::now() >= self.expires_at {
+            return Err(CommandError::SessionExpired);
+        }
+
+        Ok(player)
+    }
+
+    /// Reads the balance of the logged in player from the accounts storage.
+    fn balance(&self) -> Result<u64, CommandError> {
+        let player = self.authenticated_player()?;
+
+        self.server
+            .accounts
+            .balance(player)
+            .map_err(|err| {
+                error!(%player, error = %err, "Could not read balance");
+                CommandError::Internal
+            })?
+            .ok_or(CommandError::NotAuthenticated)
+    }
+
+    /// Reads the provably fair seeds of the logged in player, if the mode is enabled.
+    fn active_seed(&self) -> Result<Option<SeedPair>, CommandError> {
+        let player = self.authenticated_player()?;
+
+        self.server.accounts.active_seed(player).map_err(|err| {
+            error!(%player, error = %err, "Could not read seeds");
+            CommandError::Internal
+        })
+    }
+
+    /// Reads the free spins left to the logged in player on a game.
+    fn free_spins(&self, game: &str) -> Result<Option<FreeSpinsLeft>, CommandError> {
+        let player = self.authenticated_player()?;
+
+        self.server
+            .accounts
+            .free_spins(player, game)
+            .map_err(|err| {
+                error!(%player, %game, error = %err, "Could not read free spins");
+                CommandError::Internal
+            })
+    }
+
+    /// Reads the responsible gaming limits of the logged in player.
+    fn limits(&self) -> Result<LimitsStatus, CommandError> {
+        let player = self.authenticated_player()?;
+
+        self.server.accounts.limits(player).map_err(|err| {
+            error!(%player, error = %err, "Could not read limits");
+            CommandError::Internal
+        })
+    }
+
+    /// Checks that a spin of the given cost is within the limits of the player, assuming it is
+    /// lost.
+    fn check_limits(&self, cost: u64) -> Result<(), CommandError> {
+        let player = self.authenticated_player()?;
+        let status = self.limits()?;
+        let limits = status.limits;
+
+        if let Some(until) = status.excluded_until.filter(|_| status.is_excluded()) {
+            return Err(CommandError::SelfExcluded(until));
+        }
+
+        if let Some(max_wager) = limits.max_wager.filter(|max_wager| cost > *max_wager) {
+            return Err(CommandError::WagerAboveLimit { cost, max_wager });
+        }
+
+        let session_time = self.play_started_at.elapsed().unwrap_or_default();
+        if let Some(limit) = limits.session_time.filter(|limit| session_time >= *limit) {
+            return Err(CommandError::SessionTimeLimitReached(limit));
+        }
+
+        if let Some(limit) = limits.session_loss {
+            let session_loss = self
+                .server
+                .accounts
+                .net_loss(player, self.play_started_at)
+                .map_err(|err| {
+                    error!(%player, error = %err, "Could not read losses");
+                    CommandError::Internal
+                })?;
+            if session_loss + cost as i64 > limit as i64 {
+                return Err(CommandError::SessionLossLimitReached(limit));
+            }
+        }
+
+        if let Some(limit) = limits.daily_loss {
+            let daily_loss = self
+                .server
+                .accounts
+                .net_loss(player, SystemTime::now() - DAILY_LOSS_PERIOD)
+                .map_err(|err| {
+                    error!(%player, error = %err, "Could not read losses");
+                    CommandError::Internal
+                })?;
+            if daily_loss + cost as i64 > limit as i64 {
+                return Err(CommandError::DailyLossLimitReached(limit));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn schedule_reality_check(&mut self, interval: Option<Duration>) {
+        self.reality_check = interval.map(|interval| (interval, Instant::now() + interval));
+    }
+
+    /// Time of the next reality check, if the player set an interval.
+    pub fn next_reality_check(&self) -> Option<Instant> {
+        self.reality_check.map(|(_, at)| at)
+    }
+
+    /// Schedules the next reality check and returns the reminder to send, unless the client
+    /// didn't subscribe to it.
+    pub fn reality_check(&mut self) -> Option<ServerEvent> {
+        let (interval, _) = self.reality_check?;
+        self.schedule_reality_check(Some(interval));
+
+        if !self
+            .subscriptions
+            .lock()
+            .unwrap()
+            .contains(&EventKind::RealityCheck)
+        {
+            return None;
+        }
+        let player = self.player.as_deref()?;
+        let (wagered, won) = self
+            .server
+            .accounts
+            .wagered_and_won(player, self.play_started_at)
+            .inspect_err(|err| error!(%player, error = %err, "Could not read play totals"))
+            .ok()?;
+
+        Some(ServerEvent::RealityCheck {
+            elapsed: self.play_started_at.elapsed().unwrap_or_default(),
+            wagered,
+            won,
+        })
+    }
+
+    /// Logs the session into the player account with a new session token.
+    fn log_in(&mut self, player: String) -> Result<ServerResponse, CommandError> {
+        let token = generate_token();
+        let expires_at = self
+            .server
+            .accounts
+            .create_session(&player, &token, self.server.config.session_ttl())
+            .map_err(|err| {
+                error!(%player, error = %err, "Could not create session");
+                CommandError::Internal
+            })?;
+
+        self.resume(player, token, expires_at)
+    }
+
+    fn resume(
+        &mut self,
+        player: String,
+        token: String,
+        expires_at: SystemTime,
+    ) -> Result<ServerResponse, CommandError> {
+        self.player = Some(player.clone());
+        self.expires_at = expires_at;
+        self.game = None;
+        self.play_started_at = self
+            .server
+            .accounts
+            .resume_play_session(&player, self.server.config.session_break())
+            .map_err(|err| {
+                error!(%player, error = %err, "Could not resume play session");
+                CommandError::Internal
+            })?;
+        self.schedule_reality_check(self.limits()?.limits.reality_check);
+
+        Ok(ServerResponse::LoggedIn {
+            player,
+            balance: self.balance()?,
+            token,
+            expires_at,
+        })
+    }
+
+    /// Checks every field of the command against the loaded games and the session state.
+    fn validate(&self, command: &ClientCommand, games: &Games) -> Result<(), CommandError> {
+        if !matches!(
+            command,
+            ClientCommand::Register { .. }
+                | ClientCommand::Login { .. }
+                | ClientCommand::Resume { .. }
+                | ClientCommand::Status { .. }
+                | ClientCommand::Subscribe { .. }
+                | ClientCommand::Unsubscribe { .. }
+        ) {
+            self.authenticated_player()?;
+        }
+
+        match command {
+            ClientCommand::Register { player, password } => {
+                if !is_valid_player_name(player) {
+                    return Err(CommandError::InvalidPlayerName(player.clone()));
+                }
+
+                if password.chars().count() < MIN_PASSWORD_LENGTH {
+                    return Err(CommandError::PasswordTooShort);
+                }
+            }
+            ClientCommand::Login { .. } | ClientCommand::Resume { .. } => {}
+            ClientCommand::Init { game } => {
+                if !games.contains_key(game) {
+                    return Err(CommandError::UnknownGame(game.clone()));
+                }
+            }
+            ClientCommand::Play { game, bet, nonce } => {
+                let table = &games
+                    .get(game)
+                    .ok_or_else(|| CommandError::UnknownGame(game.clone()))?
+                    .table;
+
+                if self.game.as_ref() != Some(game) {
+                    return Err(CommandError::GameNotInitialized(game.clone()));
+                }
+
+                // The bet of a free spin is the one of the spin that awarded it
+                if self.free_spins(game)?.is_some() {
+                    return self.check_limits(0);
+                }
+
+                if *bet as u64 >= table.max_bet {
+                    return Err(CommandError::InvalidBet {
+                        bet: *bet,
+                        max_bet: table.max_bet,
+                    });
+                }
+
+                let cost = *bet as u64 + 1;
+                let balance = self.balance()?;
+                if cost > balance {
+                    return Err(CommandError::InsufficientBalance { balance, cost });
+                }
+
+                self.check_limits(cost)?;
+
+                if let (Some(nonce), Some(seed)) = (nonce, self.active_seed()?) {
+                    if let Some(last_nonce) = seed.last_nonce.filter(|last| nonce <= last) {
+                        return Err(CommandError::InvalidNonce(last_nonce));
+                    }
+                }
+            }
+            ClientCommand::Status { clock } => {
+                if clock.elapsed().is_err() {
+                    return Err(CommandError::InvalidClock);
+                }
+            }
+            ClientCommand::FairPlay { client_seed } => {
+                if let Some(client_seed) = client_seed {
+                    if !is_valid_client_seed(client_seed) {
+                        return Err(CommandError::InvalidClientSeed(client_seed.clone()));
+                    }
+                }
+            }
+            ClientCommand::SetLimits { limits } => {
+                if limits
+                    .reality_check
+                    .is_some_and(|interval| interval < MIN_REALITY_CHECK_INTERVAL)
+                {
+                    return Err(CommandError::InvalidLimits(format!(
+                        "reality checks must be at least {} apart",
+                        format_duration(MIN_REALITY_CHECK_INTERVAL)
+                    )));
+                }
+            }
+            ClientCommand::SelfExclude { duration } => {
+                if duration.is_zero() || *duration > MAX_SELF_EXCLUSION {
+                    return Err(CommandError::InvalidLimits(format!(
+                        "the self-exclusion period must be between 1s and {}",
+                        format_duration(MAX_SELF_EXCLUSION)
+                    )));
+                }
+            }
+            ClientCommand::ListGames
+            | ClientCommand::Subscribe { .. }
+            | ClientCommand::Unsubscribe { .. }
+            | ClientCommand::Statement { .. }
+            | ClientCommand::Limits => {}
+            ClientCommand::GetGame { game } => {
+                if !games.contains_key(game) {
+                    return Err(CommandError::UnknownGame(game.clone()));
+                }
+            }
+            ClientCommand::Gamble { game, guess } => {
+                let gamble = games
+                    .get(game)
+                    .ok_or_else(|| CommandError::UnknownGame(game.clone()))?
+                    .definition
+                    .gamble
+                    .as_ref()
+                    .ok_or_else(|| CommandError::NothingToGamble(game.clone()))?;
+
+                if !gamble.kind.outcomes().contains(guess) {
+                    return Err(CommandError::InvalidGuess(*guess));
+                }
+
+                let Some((_, amount, _)) =
+                    self.gamble.as_ref().filter(|(gambled, amount, played)| {
+                        gambled == game && gamble.gambles_left(*amount, *played) > 0
+                    })
+                else {
+                    return Err(CommandError::NothingToGamble(game.clone()));
+                };
+
+                // The win was already paid, so it must still be there to be gambled
+                let balance = self.balance()?;
+                if *amount > balance {
+                    return Err(CommandError::InsufficientBalance {
+                        balance,
+                        cost: *amount,
+                    });
+                }
+
+                self.check_limits(*amount)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Validates then executes the command, returning the response to send to the client.
+    pub fn handle_command(
+        &mut self,
+        command: ClientCommand,
+        games: &Games,
+    ) -> Result<ServerResponse, CommandError> {
+        self.validate(&command, games)?;
+
+        Ok(match command {
+            ClientCommand::Register { player, password } => {
+                let hash = hash_password(&password).map_err(|err| {
+                    error!(error = %err, "Could not hash password");
+                    CommandError::Internal
+                })?;
+
+                match self.server.accounts.register(
+                    &player,
+                    &hash,
+                    self.server.config.start_balance,
+                ) {
+                    Ok(Some(_)) => self.log_in(player)?,
+                    Ok(None) => return Err(CommandError::PlayerExists(player)),
+                    Err(err) => {
+                        error!(%player, error = %err, "Could not register player");
+                        return Err(CommandError::Internal);
+                    }
+                }
+            }
+            ClientCommand::Login { player, password } => {
+                let hash = self.server.accounts.password_hash(&player).map_err(|err| {
+                    error!(%player, error = %err, "Could not read account");
+                    CommandError::Internal
+                })?;
+
+                // Accounts without a password can't be logged into before an admin sets one
+                match hash {
+                    Some(hash) if verify_password(&password, &hash) => self.log_in(player)?,
+                    _ => return Err(CommandError::InvalidCredentials),
+                }
+            }
+            ClientCommand::Resume { token } => {
+                let session = self.server.accounts.session(&token).map_err(|err| {
+                    error!(error = %err, "Could not read session");
+                    CommandError::Internal
+                })?;
+
+                match session {
+                    Some((_, expires_at)) if SystemTime::now() >= expires_at => {
+                        return Err(CommandError::SessionExpired)
+                    }
+                    Some((player, expires_at)) => self.resume(player, token, expires_at)?,
+                    None => return Err(CommandError::InvalidCredentials),
+                }
+            }
+            ClientCommand::Init { game } => {
+                let max_bet = games[&game].table.max_bet;
+                let balance = self.balance()?;
+                let jackpot = games[&game]
+                    .jackpot
+                    .as_ref()
+                    .map(|jackpot| self.server.accounts.jackpot(&game, jackpot.definition.seed))
+                    .transpose()
+                    .map_err(|err| {
+                        error!(%game, error = %err, "Could not read jackpot");
+                        CommandError::Internal
+                    })?;
+                let free_spins = self.free_spins(&game)?.map_or(0, |f| f.remaining);
+                self.game = Some(game);
+                self.gamble = None;
+
+                ServerResponse::Init {
+                    balance,
+                    max_bet,
+                    jackpot,
+                    free_spins,
+                }
+            }
+            ClientCommand::Play { game, bet, nonce } => {
+                let free_spins = self.free_spins(&game)?;
+                let (free_spin, bet) = match free_spins {
+                    Some(free_spins) => (true, free_spins.bet as usize - 1),
+                    None => (false, bet),
+                };
+                let (table, _) = games[&game].spin_table(free_spin);
+                let (size, stops) = games[&game].draw_size(free_spin);
+                // Generator spec with its actual seed and index of the spin, if not provably fair
+                let (rng_result, rng_state, proof) = match self.active_seed()? {
+                    Some(seed) => {
+                        let nonce = nonce.unwrap_or(seed.last_nonce.map_or(0, |n| n + 1));
+                        // Validated above, but another session of the same player may have used the nonce since
+                        let used = self
+                            .server
+                            .accounts
+                            .use_nonce(&seed.commitment, nonce)
+                            .map_err(|err| {
+                                error!(nonce, error = %err, "Could not use nonce");
+                                CommandError::Internal
+                            })?;
+                        if !used {
+                            return Err(CommandError::InvalidNonce(
+                                seed.last_nonce.unwrap_or_default(),
+                            ));
+                        }
+
+                        let drawn =
+                            derive_stops(&seed.server_seed, &seed.client_seed, nonce, size, stops);
+                        let proof = SpinProof {
+                            commitment: seed.commitment,
+                            client_seed: seed.client_seed,
+                            nonce,
+                        };
+                        (drawn, None, Some(proof))
+                    }
+                    None => {
+                        let mut rng = games[&game].rng.lock().unwrap();
+                        let spec = RngSpec {
+                            kind: rng.spec().kind,
+                            seed: rng.seed(),
+                        };
+                        let draw = rng.draws();
+                        let drawn = rng.draw_stops(size, stops);
+                        (drawn, Some((spec, draw)), None)
+                    }
+                };
+                let outcome = games[&game].play(&rng_result, bet, free_spin);
+                let SpinOutcome {
+                    ref combo,
+                    win,
+                    free_spins_won,
+                    ..
+                } = outcome;
+
+                let to_symbols = |combo: &[u64]| -> Vec<String> {
+                    combo
+                        .iter()
+                        .map(|x| table.symbol_num_mapping[x].clone())
+                        .collect()
+                };
+                let symbols = to_symbols(&outcome.symbols);
+
+                // Validated above, but another session of the same player may have spent the balance since
+                let cost = bet as u64 + 1;
+                let player = self.authenticated_player()?;
+                let spin = SpinRecord {
+                    game: game.clone(),
+                    bet: cost,
+                    stops: outcome.result().to_vec(),
+                    result: symbols.clone(),
+                    win,
+                    free_spin,
+                    free_spins_won,
+                    rng: rng_state.map_or(FAIR_RNG.to_string(), |(spec, _)| spec.kind.to_string()),
+                    proof: proof.clone(),
+                    // Free spins don't wager anything, so they neither feed nor win the jackpot
+                    jackpot: games[&game]
+                        .jackpot
+                        .as_ref()
+                        .filter(|_| !free_spin)
+                        .map(|jackpot| JackpotSpin {
+                            seed: jackpot.definition.seed,
+                            contribution: jackpot.definition.contribution,
+                            hit: jackpot.is_hit(combo.as_deref()),
+                        }),
+                };
+                let settlement = match self.server.accounts.settle_spin(player, &spin) {
+                    Ok(Some(settlement)) => settlement,
+                    Ok(None) if free_spin => {
+                        error!(%player, "Free spin already played by another session");
+                        return Err(CommandError::Internal);
+                    }
+                    Ok(None) => {
+                        return Err(CommandError::InsufficientBalance {
+                            balance: self.balance()?,
+                            cost,
+                        })
+                    }
+                    Err(err) => {
+                        error!(%player, error = %err, "Could not settle spin");
+                        return Err(CommandError::Internal);
+                    }
+                };
+
+                let (balance, jackpot_win) = (settlement.balance, settlement.jackpot_win);
+                let record = AuditRecord {
+                    timestamp: SystemTime::now(),
+                    session: self.id,
+                    player: player.to_string(),
+                    game,
+                    bet,
+                    rng: rng_state.map_or(FAIR_RNG.to_string(), |(spec, _)| spec.to_string()),
+                    draw: rng_state.map(|(_, draw)| draw),
+                    draw_size: rng_state.map(|_| (size, stops)),
+                    proof: proof.clone(),
+                    stops: outcome.stops.clone(),
+                    respins: outcome.respins.clone(),
+                    symbols,
+                    combo: combo.as_deref().map(to_symbols),
+                    win,
+                    free_spin,
+                    jackpot: jackpot_win,
+                    balance,
+                };
+                let win = win + jackpot_win;
+                if let Some(amount) = settlement.jackpot {
+                    if jackpot_win > 0 {
+                        info!(game = %record.game, win = jackpot_win, "Jackpot hit");
+                    }
+                    self.server.bus.publish(ServerEvent::JackpotChanged {
+                        game: record.game.clone(),
+                        amount,
+                    });
+                }
+                if free_spin {
+                    self.server.metrics.free_spin(&record.game, win);
+                } else {
+                    let par = games[&record.game]
+                        .par
+                        .get(bet)
+                        .copied()
+                        .unwrap_or_default();
+                    self.server.metrics.spin(&record.game, cost, win, par);
+                }
+                if free_spins_won > 0 {
+                    info!(game = %record.game, free_spins = free_spins_won, "Free spins won");
+                }
+
+                // The spin is settled, a failure to audit it must not be reported to the player
+                if let Err(err) = self.server.audit.append(&record) {
+                    error!(error = %err, "Could not write spin to the audit log");
+                }
+
+                debug!(
+                    game = %record.game,
+                    bet,
+                    stops = ?outcome.result(),
+                    symbols = %record.symbols.join(" "),
+                    win,
+                    free_spin,
+                    balance,
+                    "Spin"
+                );
+
+                // Only the combo win of a paid spin can be gambled, not the jackpot
+                let gambles = games[&record.game]
+                    .definition
+                    .gamble
+                    .as_ref()
+                    .filter(|_| !free_spin)
+                    .map_or(0, |gamble| gamble.gambles_left(record.win, 0));
+                self.gamble = (gambles > 0).then(|| (record.game.clone(), record.win, 0));
+
+                ServerResponse::Spin {
+                    win,
+                    jackpot: jackpot_win,
+                    free_spin,
+                    free_spins: settlement.free_spins,
+                    balance,
+                    result: outcome.stops,
+                    respins: outcome.respins,
+                    gambles,
+                    proof,
+                }
+            }
+            ClientCommand::Gamble { game, guess } => {
+                // Validated above
+                let (_, stake, played) = self.gamble.take().unwrap();
+                let gamble = games[&game].definition.gamble.as_ref().unwrap();
+                let outcome = gamble.draw(&mut games[&game].gamble_rng.lock().unwrap());
+                let won = outcome == guess;
+
+                // Validated above, but another session of the same player may have spent the balance since
+                let player = self.authenticated_player()?;
+                let balance = match self
+                    .server
+                    .accounts
+                    .settle_gamble(player, &game, stake, won)
+                {
+                    Ok(Some(balance)) => balance,
+                    Ok(None) => {
+                        return Err(CommandError::InsufficientBalance {
+                            balance: self.balance()?,
+                            cost: stake,
+                        })
+                    }
+                    Err(err) => {
+                        error!(%player, error = %err, "Could not settle gamble");
+                        return Err(CommandError::Internal);
+                    }
+                };
+                debug!(%game, stake, %guess, %outcome, balance, "Gamble");
+
+                let win = if won { stake * 2 } else { 0 };
+                let gambles = gamble.gambles_left(win, played + 1);
+                self.gamble = (gambles > 0).then(|| (game, win, played + 1));
+
+                ServerResponse::Gamble {
+                    outcome,
+                    win,
+                    gambles,
+                    balance,
+                }
+            }
+            ClientCommand::Status { clock } => {
+                // Clock has been validated to be in the past
+                self.average_latency += (clock.elapsed().unwrap().as_secs_f64()
+                    - self.average_latency)
+                    / self.status_query_count as f64;
+                self.status_query_count += 1;
+
+                ServerResponse::Status(Status {
+                    server_status: ServerStatus::Connected,
+                    uptime: self.uptime.elapsed(),
+                    latency: Duration::from_secs_f64(self.average_latency),
+                })
+            }
+            ClientCommand::ListGames => {
+                let mut catalogue: Vec<GameInfo> =
+                    games.values().map(|g| g.definition.info.clone()).collect();
+                catalogue.sort_by(|a, b| a.id.cmp(&b.id));
+
+                ServerResponse::Games(catalogue)
+            }
+            ClientCommand::GetGame { game } => {
+                ServerResponse::Game(games[&game].definition.clone())
+            }
+            ClientCommand::Statement { game, limit } => {
+                let player = self.authenticated_player()?;
+                let statement = self
+                    .server
+                    .accounts
+                    .player_statement(player, game.as_deref(), limit.min(MAX_STATEMENT_ENTRIES))
+                    .map_err(|err| {
+                        error!(%player, error = %err, "Could not read statement");
+                        CommandError::Internal
+                    })?;
+
+                ServerResponse::Statement(statement)
+            }
+            ClientCommand::FairPlay { client_seed } => {
+                let player = self.authenticated_player()?;
+                let next = client_seed.map(|client_seed| {
+                    let server_seed = generate_server_seed();
+                    SeedPair {
+                        commitment: commitment(&server_seed),
+                        server_seed,
+                        client_seed,
+                        last_nonce: None,
+                    }
+                });
+                let revealed = self
+                    .server
+                    .accounts
+                    .rotate_seed(player, next.as_ref())
+                    .map_err(|err| {
+                        error!(%player, error = %err, "Could not rotate seeds");
+                        CommandError::Internal
+                    })?;
+
+                ServerResponse::FairPlay {
+                    commitment: next.map(|seed| seed.commitment),
+                    revealed,
+                }
+            }
+            ClientCommand::Limits => ServerResponse::Limits(self.limits()?),
+            ClientCommand::SetLimits { limits } => {
+                let player = self.authenticated_player()?;
+                let status = self
+                    .server
+                    .accounts
+                    .set_limits(player, &limits, self.server.config.limit_increase_delay())
+                    .map_err(|err| {
+                        error!(%player, error = %err, "Could not set limits");
+                        CommandError::Internal
+                    })?;
+                info!(
+                    ?limits,
+                    pending = status.pending.is_some(),
+                    "Changed limits"
+                );
+
+                self.schedule_reality_check(status.limits.reality_check);
+                ServerResponse::Limits(status)
+            }
+            ClientCommand::SelfExclude { duration } => {
+                let player = self.authenticated_player()?;
+                let status = self
+                    .server
+                    .accounts
+                    .self_exclude(player, SystemTime::now() + duration)
+                    .map_err(|err| {
+                        error!(%player, error = %err, "Could not exclude player");
+                        CommandError::Internal
+                    })?;
+                info!(duration = %format_duration(duration), "Player excluded themselves");
+
+                ServerResponse::Limits(status)
+            }
+            ClientCommand::Subscribe { events } => {
+                let mut subscriptions = self.subscriptions.lock().unwrap();
+                subscriptions.extend(events);
+
+                ServerResponse::Subscribed {
+                    events: subscriptions.iter().copied().sorted().collect(),
+                }
+            }
+            ClientCommand::Unsubscribe { events } => {
+                let mut subscriptions = self.subscriptions.lock().unwrap();
+                events.iter().for_each(|e| {
+                    subscriptions.remove(e);
+                });
+
+                ServerResponse::Subscribed {
+                    events: subscriptions.iter().copied().sorted().collect(),
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+    use std::sync::atomic::AtomicBool;
+
+    use slot_machine::accounts::AccountStore;
+    use slot_machine::audit::AuditLog;
+    use slot_machine::limits::PlayerLimits;
+    use tokio::sync::Notify;
+
+    use super::*;
+    use crate::config::Config;
+    use crate::events::EventBus;
+    use crate::games::GameCatalog;
+    use crate::metrics::Metrics;
+    use crate::rate_limit::AuthLimiter;
+
+    /// Session of a player logged in on a daemon with an in-memory accounts store, removing its
+    /// audit log when dropped.
+    struct Player {
+        session: Session,
+        games: Arc<Games>,
+        audit_log: PathBuf,
+    }
+
+    impl Player {
+        fn new(name: &str, limit_increase_delay_secs: u64) -> Self {
+            let config = Config {
+                games_folder: Path::new(env!("CARGO_MANIFEST_DIR")).join("data/games"),
+                start_balance: 1000,
+                limit_increase_delay_secs,
+                rng: "chacha20:42".parse().unwrap(),
+                ..Config::default()
+            };
+            let audit_log = std::env::temp_dir().join(format!(
+                "slot-machine-session-{}-{}.jsonl",
+                std::process::id(),
+                name
+            ));
+            let server = Arc::new(Server {
+                games: GameCatalog::new(&config.games_folder),
+                auth_limiter: AuthLimiter::new(&config.rate_limits),
+                config,
+                bus: Arc::new(EventBus::default()),
+                accounts: AccountStore::open(Path::new(":memory:")).unwrap(),
+                audit: AuditLog::open(&audit_log).unwrap(),
+                metrics: Metrics::default(),
+                running: AtomicBool::new(true),
+                stopping: Notify::new(),
+            });
+            server.games.reload(|_| server.config.rng).unwrap();
+
+            let mut player = Player {
+                session: Session::new(1, Default::default(), server.clone()),
+                games: server.games.snapshot(),
+                audit_log,
+            };
+            player.send(ClientCommand::Register {
+                player: name.to_string(),
+                password: "password".to_string(),
+            });
+            player.send(ClientCommand::Init {
+                game: "blaze7".to_string(),
+            });
+            player
+        }
+
+        fn send(&mut self, command: ClientCommand) -> ServerResponse {
+            self.try_send(command).unwrap()
+        }
+
+        fn try_send(&mut self, command: ClientCommand) -> Result<ServerResponse, CommandError> {
+            self.session.handle_command(command, &self.games)
+        }
+
+        fn play(&mut self, bet: usize) -> Result<ServerResponse, CommandError> {
+            self.try_send(ClientCommand::Play {
+                game: "blaze7".to_string(),
+                bet,
+                nonce: None,
+            })
+        }
+
+        fn set_limits(&mut self, limits: PlayerLimits) -> LimitsStatus {
+            match self.send(ClientCommand::SetLimits { limits }) {
+                ServerResponse::Limits(status) => status,
+                response => panic!("Unexpected response {:?}", response),
+            }
+        }
+
+        /// Settles a lost spin of `cost` coins, as played by another session.
+        fn lose(&self, cost: u64) {
+            let spin = SpinRecord {
+                game: "blaze7".to_string(),
+                bet: cost,
+                stops: vec![],
+                result: vec![],
+                win: 0,
+                free_spin: false,
+                free_spins_won: 0,
+                rng: FAIR_RNG.to_string(),
+                proof: None,
+                jackpot: None,
+            };
+            let player = self.session.player().unwrap();
+            self.session
+                .server
+                .accounts
+                .settle_spin(player, &spin)
+                .unwrap()
+                .unwrap();
+        }
+    }
+
+    impl Drop for Player {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.audit_log);
+        }
+    }
+
+    #[test]
+    fn wager_and_loss_limits_are_enforced() {
+        let mut player = Player::new("wager", 0);
+        player.set_limits(PlayerLimits {
+            max_wager: Some(2),
+            session_loss: Some(20),
+            daily_loss: Some(30),
+            ..Default::default()
+        });
+
+        assert_eq!(
+            player.play(2).unwrap_err(),
+            CommandError::WagerAboveLimit {
+                cost: 3,
+                max_wager: 2
+            }
+        );
+
+        // The spin is refused if losing it would go over the limit
+        player.lose(19);
+        assert_eq!(
+            player.play(1).unwrap_err(),
+            CommandError::SessionLossLimitReached(20)
+        );
+
+        // A new play session doesn't reset the daily loss, the losses being timed to the millisecond
+        std::thread::sleep(Duration::from_millis(2));
+        player.session.play_started_at = SystemTime::now();
+        player.lose(10);
+        assert_eq!(
+            player.play(1).unwrap_err(),
+            CommandError::DailyLossLimitReached(30)
+        );
+        assert!(player.play(0).is_ok());
+    }
+
+    #[test]
+    fn session_time_limit_is_enforced() {
+        let mut player = Player::new("time", 0);
+        player.set_limits(PlayerLimits {
+            session_time: Some(Duration::from_secs(60)),
+            ..Default::default()
+        });
+        assert!(player.play(0).is_ok());
+
+        player.session.play_started_at = SystemTime::now() - Duration::from_secs(60);
+        assert_eq!(
+            player.play(0).unwrap_err(),
+            CommandError::SessionTimeLimitReached(Duration::from_secs(60))
+        );
+    }
+
+    #[test]
+    fn loosened_limits_wait_for_the_increase_delay() {
+        let mut player = Player::new("delay", 24 * 60 * 60);
+        let max_wager = |max_wager| PlayerLimits {
+            max_wager: Some(max_wager),
+            ..Default::default()
+        };
+        player.set_limits(max_wager(2));
+
+        let status = player.set_limits(max_wager(3));
+        assert_eq!(status.limits, max_wager(2));
+        assert_eq!(status.pending.map(|p| p.limits), Some(max_wager(3)));
+        assert_eq!(
+            player.play(2).unwrap_err(),
+            CommandError::WagerAboveLimit {
+                cost: 3,
+                max_wager: 2
+            }
+        );
+
+        // A stricter limit applies at once
+        assert_eq!(player.set_limits(max_wager(1)).limits, max_wager(1));
+        assert!(player.play(1).is_err());
+        assert!(player.play(0).is_ok());
+
+        // Without delay, loosening applies at once
+        let mut player = Player::new("no-delay", 0);
+        player.set_limits(max_wager(1));
+        player.set_limits(max_wager(3));
+        assert!(player.play(2).is_ok());
+    }
+
+    #[test]
+    fn self_exclusion_can_only_be_extended() {
+        let mut player = Player::new("excluded", 0);
+        let exclude = |player: &mut Player, secs| match player.send(ClientCommand::SelfExclude {
+            duration: Duration::from_secs(secs),
+        }) {
+            ServerResponse::Limits(status) => status.excluded_until.unwrap(),
+            response => panic!("Unexpected response {:?}", response),
+        };
+
+        exclude(&mut player, 3600);
+        assert!(exclude(&mut player, 60) > SystemTime::now() + Duration::from_secs(3000));
+        assert!(matches!(
+            player.play(0).unwrap_err(),
+            CommandError::SelfExcluded(_)
+        ));
+    }
+}
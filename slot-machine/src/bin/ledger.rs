@@ -0,0 +1,101 @@
+//! Audit the transactions ledger of the daemon's accounts database.
+//!
+//! ```text
+//! ledger player <name> [game] [limit]   Statement of a player, optionally for a single game
+//! ledger game <game> [limit]            Statement of the house account of a game
+//! ledger check                          Reconcile the balances against the ledger
+//! ```
+use std::path::Path;
+use std::process::ExitCode;
+use std::time::UNIX_EPOCH;
+
+use itertools::Itertools;
+use slot_machine::accounts::{AccountStore, LedgerEntry, ACCOUNTS_DATABASE};
+
+const DEFAULT_LIMIT: usize = 50;
+
+fn print_statement(entries: &[LedgerEntry]) {
+    println!(
+        "{:>8} | {:>13} | {:<12} | {:<10} | {:<10} | {:<11} | {:>3} | {:<12} | {:<30} | {:>8} | {:>8}",
+        "id", "timestamp", "player", "kind", "game", "rng", "bet", "stops", "result", "amount", "balance"
+    );
+    println!("{:-<164}", "");
+
+    // Oldest first so that the running balance reads top to bottom
+    for entry in entries.iter().rev() {
+        println!(
+            "{:>8} | {:>13} | {:<12} | {:<10} | {:<10} | {:<11} | {:>3} | {:<12} | {:<30} | {:>+8} | {:>8}",
+            entry.id,
+            entry
+                .timestamp
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis(),
+            entry.player,
+            entry.kind,
+            entry.game.as_deref().unwrap_or("-"),
+            entry.rng.as_deref().unwrap_or("-"),
+            entry.bet.map_or("-".to_string(), |b| b.to_string()),
+            entry.stops.iter().join(" "),
+            entry.result.join(" "),
+            entry.amount,
+            entry.balance
+        );
+    }
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let accounts = AccountStore::open_read_only(Path::new(ACCOUNTS_DATABASE))
+        .expect("Could not open accounts database");
+
+    let parse_limit = |arg: Option<&String>| arg.map_or(Ok(DEFAULT_LIMIT), |l| l.parse::<usize>());
+
+    match args.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+        ["player", player, ..] => {
+            // The optional game argument can be omitted to give the limit directly
+            let (game, limit) = match args.get(2).map(|a| a.parse::<usize>()) {
+                Some(Ok(limit)) => (None, Ok(limit)),
+                Some(Err(_)) => (args.get(2).map(String::as_str), parse_limit(args.get(3))),
+                None => (None, Ok(DEFAULT_LIMIT)),
+            };
+            let Ok(limit) = limit else {
+                eprintln!("Invalid limit");
+                return ExitCode::FAILURE;
+            };
+
+            print_statement(
+                &accounts
+                    .player_statement(player, game, limit)
+                    .expect("Could not read statement"),
+            );
+        }
+        ["game", game, ..] => {
+            let Ok(limit) = parse_limit(args.get(2)) else {
+                eprintln!("Invalid limit");
+                return ExitCode::FAILURE;
+            };
+
+            print_statement(
+                &accounts
+                    .game_statement(game, limit)
+                    .expect("Could not read statement"),
+            );
+        }
+        ["check"] => {
+            let report = accounts.check().expect("Could not check the ledger");
+            print!("{}", report);
+
+            if !report.is_consistent() {
+                return ExitCode::FAILURE;
+            }
+            println!("[+] Ledger is consistent with the accounts balances");
+        }
+        _ => {
+            eprintln!("Usage: ledger player <name> [game] [limit] | ledger game <game> [limit] | ledger check");
+            return ExitCode::FAILURE;
+        }
+    }
+
+    ExitCode::SUCCESS
+}
@@ -0,0 +1,283 @@
+//! Replay the spins of the daemon's audit log against the current games.
+//!
+//! ```text
+//! replay [--games <folder>] [log]
+//!                 Re-evaluate every spin of the audit log (`AUDIT_LOG` by default) against the
+//!                 games of the folder (`GAMES_FOLDER` by default)
+//! ```
+//!
+//! Every spin is flagged if its respins, symbols, winning combo or payout no longer match the ones
+//! computed from its stops with the current par table. The stops drawn by a seeded generator are
+//! drawn again as well, the provably fair ones are checked by the `verify` binary instead. Draws
+//! missing from the sequence of a generator, of spins that couldn't be settled, are flagged: the
+//! later spins of the generator are then only evaluated from their recorded stops.
+use std::collections::HashMap;
+use std::path::Path;
+use std::process::ExitCode;
+
+use slot_machine::audit::{read_audit_log, AuditRecord, AUDIT_LOG};
+use slot_machine::game::Game;
+use slot_machine::rng::{RngSpec, SlotRng};
+use slot_machine::utils::take_option;
+use slot_machine::GAMES_FOLDER;
+
+/// Generator replaying the spins of a seeded generator of the daemon.
+struct Replayed {
+    /// `None` once draws are missing from the log, their sizes being unknown.
+    rng: Option<SlotRng>,
+    /// Index of the next draw expected in the log.
+    next: u64,
+}
+
+/// Checks a spin against the game, returning the reasons it doesn't match.
+fn replay_spin(
+    record: &AuditRecord,
+    game: &Game,
+    generators: &mut HashMap<(String, String), Replayed>,
+) -> Vec<String> {
+    let (table, _) = game.spin_table(record.free_spin);
+    let (reels, stops) = (table.reels[0].len(), table.reels.len());
+    // Logs written before the draw size was recorded were drawn with the game's
+    let (size, drawn_stops) = record
+        .draw_size
+        .unwrap_or_else(|| game.draw_size(record.free_spin));
+    let mut mismatches = vec![];
+
+    if let (Some(draw), Ok(spec @ RngSpec { seed: Some(_), .. })) =
+        (record.draw, record.rng.parse::<RngSpec>())
+    {
+        let key = (record.game.clone(), record.rng.clone());
+        let restarted = || Replayed {
+            rng: Some(SlotRng::new(spec)),
+            next: 0,
+        };
+        let replayed = generators.entry(key).or_insert_with(restarted);
+        // The daemon restarted with the same seed
+        if replayed.next > draw {
+            *replayed = restarted();
+        }
+        // The draws of spins that weren't settled aren't logged, and their sizes may differ
+        if replayed.next < draw && replayed.rng.is_some() {
+            mismatches.push(format!(
+                "unaudited draws {}..{} of {}, its later spins can't be drawn again",
+                replayed.next, draw, record.rng
+            ));
+            replayed.rng = None;
+        }
+        replayed.next = draw + 1;
+
+        if let Some(rng) = &mut replayed.rng {
+            let replayed = game.play(
+                &rng.draw_stops(size, drawn_stops),
+                record.bet,
+                record.free_spin,
+            );
+            if replayed.stops != record.stops || replayed.respins != record.respins {
+                let respins = replayed
+                    .respins
+                    .iter()
+                    .map(|r| format!(" then {:?}", r.stops));
+                mismatches.push(format!(
+                    "{} drew {:?}{}",
+                    record.rng,
+                    replayed.stops,
+                    respins.collect::<String>()
+                ));
+            }
+        }
+    }
+
+    if std::iter::once(&record.stops)
+        .chain(record.respins.iter().map(|r| &r.stops))
+        .any(|s| s.len() != reels || s.iter().any(|s| *s >= stops))
+    {
+        mismatches.push(format!(
+            "stops don't fit {} reels of {} stops",
+            reels, stops
+        ));
+        return mismatches;
+    }
+
+    let to_symbols = |combo: &[u64]| -> Vec<String> {
+        combo
+            .iter()
+            .map(|x| table.symbol_num_mapping[x].clone())
+            .collect()
+    };
+    // The respun reels of each step come from the stops recorded for it
+    let recorded: Vec<usize> = std::iter::once(&record.stops)
+        .chain(record.respins.iter().map(|r| &r.stops))
+        .flatten()
+        .copied()
+        .collect();
+    let outcome = game.play(&recorded, record.bet, record.free_spin);
+    let combo = outcome.combo.as_deref().map(to_symbols);
+    let win = outcome.win;
+
+    if outcome.respins != record.respins {
+        mismatches.push(format!("respins are now {:?}", outcome.respins));
+    }
+    let symbols = to_symbols(&outcome.symbols);
+    if symbols != record.symbols {
+        mismatches.push(format!("symbols are now {}", symbols.join(" ")));
+    }
+    if combo != record.combo {
+        mismatches.push(format!(
+            "combo is now {}",
+            combo.map_or("none".to_string(), |c| c.join(" "))
+        ));
+    }
+    if win != record.win {
+        mismatches.push(format!("pays {} instead of {}", win, record.win));
+    }
+
+    mismatches
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let mut args: Vec<&str> = args.iter().map(String::as_str).collect();
+    let folder = match take_option(&mut args, "--games") {
+        Ok(folder) => Path::new(folder.unwrap_or(GAMES_FOLDER)),
+        Err(err) => {
+            eprintln!("{}\nUsage: replay [--games <folder>] [log]", err);
+            return ExitCode::FAILURE;
+        }
+    };
+    let path = args.first().copied().unwrap_or(AUDIT_LOG);
+    let records = match read_audit_log(Path::new(&path)) {
+        Ok(records) => records,
+        Err(err) => {
+            eprintln!("Could not open audit log {:?}: {}", path, err);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let mut games: HashMap<String, Option<Game>> = HashMap::new();
+    let mut generators = HashMap::new();
+    let (mut replayed, mut flagged) = (0, 0);
+
+    for (line, record) in records {
+        let record = match record {
+            Ok(record) => record,
+            Err(err) => {
+                println!("[!] Line {}: unreadable record ({})", line, err);
+                flagged += 1;
+                continue;
+            }
+        };
+
+        let game = games.entry(record.game.clone()).or_insert_with(|| {
+            Game::load(&folder.join(&record.game))
+                .map_err(|err| println!("[!] Could not load game \"{}\": {}", record.game, err))
+                .ok()
+        });
+        let mismatches = match game {
+            Some(game) => replay_spin(&record, game, &mut generators),
+            None => vec!["game is not available".to_string()],
+        };
+
+        replayed += 1;
+        if !mismatches.is_empty() {
+            println!(
+                "[!] Line {} (session {}, \"{}\" on \"{}\", stops {:?}): {}",
+                line,
+                record.session,
+                record.player,
+                record.game,
+                record.stops,
+                mismatches.join(", ")
+            );
+            flagged += 1;
+        }
+    }
+
+    println!("[+] {} spins replayed", replayed);
+    if flagged > 0 {
+        println!("[!] {} spins don't match the current games", flagged);
+        return ExitCode::FAILURE;
+    }
+    println!("[+] Every spin matches the current games");
+
+    ExitCode::SUCCESS
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::SystemTime;
+
+    use super::*;
+
+    /// Draws the next spin of the generator on the game, as the daemon logs it.
+    fn draw_spin(game: &Game, rng: &mut SlotRng) -> AuditRecord {
+        let (table, _) = game.spin_table(false);
+        let to_symbols = |combo: &[u64]| -> Vec<String> {
+            combo
+                .iter()
+                .map(|x| table.symbol_num_mapping[x].clone())
+                .collect()
+        };
+        let (size, stops) = game.draw_size(false);
+        let draw = rng.draws();
+        let outcome = game.play(&rng.draw_stops(size, stops), 0, false);
+
+        AuditRecord {
+            timestamp: SystemTime::now(),
+            session: 1,
+            player: "player".to_string(),
+            game: "generic".to_string(),
+            bet: 0,
+            rng: rng.spec().to_string(),
+            draw: Some(draw),
+            draw_size: Some((size, stops)),
+            proof: None,
+            stops: outcome.stops.clone(),
+            respins: outcome.respins.clone(),
+            symbols: to_symbols(&outcome.symbols),
+            combo: outcome.combo.as_deref().map(to_symbols),
+            win: outcome.win,
+            free_spin: false,
+            jackpot: 0,
+            balance: 0,
+        }
+    }
+
+    #[test]
+    fn unaudited_draws_are_reported() {
+        let game =
+            Game::load(&Path::new(env!("CARGO_MANIFEST_DIR")).join("data/games/generic")).unwrap();
+        let spec = "chacha20:42".parse().unwrap();
+        let mut rng = SlotRng::new(spec);
+        let mut generators = HashMap::new();
+
+        let first = draw_spin(&game, &mut rng);
+        assert!(replay_spin(&first, &game, &mut generators).is_empty());
+
+        // A spin that wasn't settled, of another size than the logged ones
+        rng.draw_stops(2, 5);
+        let after_gap = draw_spin(&game, &mut rng);
+        assert_eq!(
+            replay_spin(&after_gap, &game, &mut generators),
+            ["unaudited draws 1..2 of chacha20:42, its later spins can't be drawn again"]
+        );
+        // The later spins are still evaluated from their recorded stops
+        let later = draw_spin(&game, &mut rng);
+        assert!(replay_spin(&later, &game, &mut generators).is_empty());
+        let mut later = draw_spin(&game, &mut rng);
+        later.win += 1;
+        assert_eq!(
+            replay_spin(&later, &game, &mut generators),
+            [format!("pays {} instead of {}", later.win - 1, later.win)]
+        );
+
+        // The daemon restarted with the same seed
+        let mut rng = SlotRng::new(spec);
+        let mut restarted = draw_spin(&game, &mut rng);
+        assert!(replay_spin(&restarted, &game, &mut generators).is_empty());
+        restarted = draw_spin(&game, &mut rng);
+        restarted.stops[0] = (restarted.stops[0] + 1) % game.table.reels.len();
+        assert!(replay_spin(&restarted, &game, &mut generators)
+            .iter()
+            .any(|mismatch| mismatch.starts_with("chacha20:42 drew")));
+    }
+}
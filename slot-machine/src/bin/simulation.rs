@@ -6,45 +6,48 @@
 //!
 //! It is however currently hard-coded to work only for the `generic` and `blaze7` (RTP-only) games
 //! implemented in the [project's repo](https://github.com/Krow10/learn-rust/blob/main/slot-machine/data/games).
+//!
+//! ```text
+//! simulation [<kind>[:<seed>]]    Simulate the `generic` game with the given generator
+//! ```
 use itertools::Itertools;
-use rand::Rng;
 use std::collections::HashMap;
+use std::path::Path;
 use std::time::Instant;
 
-use slot_machine::par_table::{ParTable, ParTableFiles};
+use slot_machine::game::Game;
+use slot_machine::par_table::ParTable;
+use slot_machine::rng::{RngSpec, SlotRng};
+use slot_machine::GAMES_FOLDER;
 
-/// Starts the simulation for the given game files.
+/// Starts the simulation for the given game's par table.
 ///
-/// For each of the `n_simulations` loop it will draw a random spin and store the resulting combo as
-/// well as update the hit ratio if the combo is winning.
+/// For each of the `n_simulations` loop it will draw a random spin from `rng` and store the
+/// resulting combo as well as update the hit ratio if the combo is winning.
 ///
 /// The results are displayed directly to the console in the form of a table with variations from the
 /// hard-coded theoretical values.
-fn run_simulation(files: Vec<String>, n_simulations: u64) {
+fn run_simulation(table: &ParTable, rng: &mut SlotRng, n_simulations: u64) {
     const EXPECTED_PAYOUTS_RATIO: [f64; 3] = [0.76080, 0.85495, 0.9270];
     const EXPECTED_HIT_RATIO: f64 = 0.14212;
 
-    let mut table = ParTable::default();
-    table
-        .parse_from_csv(ParTableFiles::try_from(files).unwrap())
-        .expect("Could not parse par table from csv files");
-
     let mut simulated_payout_ratio = 0.0f64;
     let mut simulated_hit_ratio = 0.0f64;
     let mut draws = HashMap::<String, f64>::new();
 
-    println!("[*] Starting {} spin simulations", n_simulations);
+    println!(
+        "[*] Starting {} spin simulations with {}",
+        n_simulations,
+        rng.spec()
+    );
 
     let n_coins = 1;
     let non_winning_combo = String::from("--");
 
     let now = Instant::now();
     for _i in 1..n_simulations {
-        let rng_iter = rand::thread_rng().sample_iter(rand::distributions::Uniform::from(
-            0..=table.reels.len() - 1, // Account for indexes, start at 0
-        ));
         // In real-life applications the numbers are being constantly re-generated and picked just on input
-        let rng_result: Vec<usize> = rng_iter.take(3).collect();
+        let rng_result: Vec<usize> = rng.draw_stops(3, table.reels.len());
         let spin_result: Vec<u64> = rng_result
             .iter()
             .enumerate()
@@ -108,7 +111,7 @@ fn run_simulation(files: Vec<String>, n_simulations: u64) {
             - EXPECTED_PAYOUTS_RATIO[n_coins - 1]
     );
 
-    println!("");
+    println!();
 
     println!(
         "{:<9} {:<12} {:<12} {:<12}",
@@ -166,13 +169,12 @@ fn run_simulation(files: Vec<String>, n_simulations: u64) {
 
 /// Runs a million rools for the `generic` game.
 fn main() {
-    run_simulation(
-        vec![
-            "data/generic/symbols.csv".to_string(),
-            "data/generic/paytable.csv".to_string(),
-            "data/generic/reels.csv".to_string(),
-            "".to_string(),
-        ],
-        10u64.pow(6),
-    );
+    let spec: RngSpec = std::env::args()
+        .nth(1)
+        .map_or(Ok(RngSpec::default()), |s| s.parse())
+        .expect("Invalid generator");
+    let game =
+        Game::load(&Path::new(GAMES_FOLDER).join("generic")).expect("Could not load the game");
+
+    run_simulation(&game.table, &mut SlotRng::new(spec), 10u64.pow(6));
 }
@@ -0,0 +1,191 @@
+//! Manage a running daemon through its admin socket.
+//!
+//! ```text
+//! slotctl [--socket <path>] sessions          List the connected clients with their balances
+//! slotctl [--socket <path>] kick <session>    Disconnect a client
+//! slotctl [--socket <path>] credit <player> <coins>
+//! slotctl [--socket <path>] debit <player> <coins>
+//! slotctl [--socket <path>] password <player> <password>
+//! slotctl [--socket <path>] games             List the loaded games
+//! slotctl [--socket <path>] enable <game>
+//! slotctl [--socket <path>] disable <game>
+//! slotctl [--socket <path>] reload            Reload the games folder
+//! slotctl [--socket <path>] metrics           Print the Prometheus metrics
+//! slotctl [--socket <path>] shutdown          Disconnect the clients and stop the daemon
+//! ```
+use std::io::BufReader;
+use std::os::unix::net::UnixStream;
+use std::process::ExitCode;
+
+use anyhow::{anyhow, bail, Context, Result};
+use slot_machine::admin::{AdminCommand, AdminResponse, ADMIN_SOCKET};
+use slot_machine::utils::{read_socket_message, send_socket_message};
+
+const USAGE: &str = "Usage: slotctl [--socket <path>] <command>
+
+Commands:
+  sessions                 List the connected clients with their balances
+  kick <session>           Disconnect a client
+  credit <player> <coins>  Add coins to a player balance
+  debit <player> <coins>   Remove coins from a player balance
+  password <player> <password>
+                           Set the password of a player, logging out its sessions
+  games                    List the loaded games
+  enable <game>            Serve a disabled game again
+  disable <game>           Stop serving a game to the clients
+  reload                   Reload the games folder
+  metrics                  Print the Prometheus metrics
+  shutdown                 Disconnect the clients and stop the daemon";
+
+fn parse_command(args: &[&str]) -> Result<AdminCommand> {
+    let coins = |coins: &str| -> Result<i64> {
+        coins
+            .parse::<u32>()
+            .map(i64::from)
+            .map_err(|_| anyhow!("Invalid amount {:?}", coins))
+    };
+
+    Ok(match args {
+        ["sessions"] => AdminCommand::Sessions,
+        ["kick", session] => AdminCommand::Kick {
+            session: session
+                .parse()
+                .map_err(|_| anyhow!("Invalid session {:?}", session))?,
+        },
+        ["credit", player, amount] => AdminCommand::Adjust {
+            player: player.to_string(),
+            amount: coins(amount)?,
+        },
+        ["debit", player, amount] => AdminCommand::Adjust {
+            player: player.to_string(),
+            amount: -coins(amount)?,
+        },
+        ["password", player, password] => AdminCommand::SetPassword {
+            player: player.to_string(),
+            password: password.to_string(),
+        },
+        ["games"] => AdminCommand::Games,
+        ["enable", game] => AdminCommand::EnableGame {
+            game: game.to_string(),
+        },
+        ["disable", game] => AdminCommand::DisableGame {
+            game: game.to_string(),
+        },
+        ["reload"] => AdminCommand::Reload,
+        ["metrics"] => AdminCommand::Metrics,
+        ["shutdown"] => AdminCommand::Shutdown,
+        _ => bail!("Invalid command"),
+    })
+}
+
+fn send_command(socket: &str, command: &AdminCommand) -> Result<AdminResponse> {
+    let stream = UnixStream::connect(socket)
+        .with_context(|| format!("Could not connect to the admin socket {}", socket))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = stream;
+
+    send_socket_message(&mut writer, serde_json::to_string(command)?)?;
+    let response =
+        read_socket_message(&mut reader)?.ok_or(anyhow!("The daemon closed the connection"))?;
+
+    Ok(serde_json::from_str(&response)?)
+}
+
+fn print_response(response: AdminResponse) -> ExitCode {
+    match response {
+        AdminResponse::Sessions(sessions) => {
+            println!(
+                "{:>6} | {:<28} | {:<12} | {:>8} | {:>9}",
+                "id", "peer", "player", "balance", "connected"
+            );
+            println!("{:-<75}", "");
+            for session in sessions {
+                println!(
+                    "{:>6} | {:<28} | {:<12} | {:>8} | {:>8}s",
+                    session.id,
+                    session.peer,
+                    session.player.as_deref().unwrap_or("-"),
+                    session.balance.map_or("-".to_string(), |b| b.to_string()),
+                    session.connected_for.as_secs()
+                );
+            }
+        }
+        AdminResponse::Balance { player, balance } => {
+            println!("Balance of {} is now {}", player, balance)
+        }
+        AdminResponse::Games(games) => {
+            for game in games {
+                println!(
+                    "{:<12} {:<24} {}",
+                    game.id,
+                    game.name,
+                    if game.enabled { "enabled" } else { "disabled" }
+                );
+            }
+        }
+        AdminResponse::Reloaded {
+            added,
+            updated,
+            removed,
+            failed,
+        } => {
+            let list = |games: Vec<String>| {
+                if games.is_empty() {
+                    "-".to_string()
+                } else {
+                    games.join(", ")
+                }
+            };
+            println!("Added: {}", list(added));
+            println!("Updated: {}", list(updated));
+            println!("Removed: {}", list(removed));
+            for (game, err) in failed.iter() {
+                println!("Failed: {} ({})", game, err);
+            }
+            if !failed.is_empty() {
+                return ExitCode::FAILURE;
+            }
+        }
+        AdminResponse::Metrics(metrics) => print!("{}", metrics),
+        AdminResponse::Done => println!("Done"),
+        AdminResponse::Error(err) => {
+            eprintln!("{}", err);
+            return ExitCode::FAILURE;
+        }
+    }
+
+    ExitCode::SUCCESS
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let mut args: Vec<&str> = args.iter().map(String::as_str).collect();
+
+    let socket = match args[..] {
+        ["--socket", socket, ..] => {
+            args.drain(..2);
+            socket
+        }
+        _ => ADMIN_SOCKET,
+    };
+    if args.is_empty() || args[0] == "--help" {
+        println!("{}", USAGE);
+        return ExitCode::SUCCESS;
+    }
+
+    let command = match parse_command(&args) {
+        Ok(command) => command,
+        Err(err) => {
+            eprintln!("{}\n\n{}", err, USAGE);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    match send_command(socket, &command) {
+        Ok(response) => print_response(response),
+        Err(err) => {
+            eprintln!("{:#}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
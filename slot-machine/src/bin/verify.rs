@@ -0,0 +1,195 @@
+//! Verify provably fair spins once their server seed has been revealed.
+//!
+//! ```text
+//! verify spin <game> <server_seed> <client_seed> <nonce> <bet> [commitment]
+//!                                 Recompute a single spin, checking the seed against its commitment
+//! verify player <name> [limit]    Recompute the provably fair spins of a player from the ledger
+//! ```
+//!
+//! The bet is given as displayed by the client (starting at 1). The games are read from
+//! `GAMES_FOLDER` and the ledger from `ACCOUNTS_DATABASE`, like the daemon does by default, or from
+//! the paths given with `--games <folder>` and `--accounts <path>`.
+use std::collections::HashMap;
+use std::path::Path;
+use std::process::ExitCode;
+
+use itertools::Itertools;
+use slot_machine::accounts::{AccountStore, TransactionKind, ACCOUNTS_DATABASE};
+use slot_machine::fairness::{commitment, replay_spin};
+use slot_machine::game::Game;
+use slot_machine::utils::take_option;
+use slot_machine::GAMES_FOLDER;
+
+const DEFAULT_LIMIT: usize = 500;
+
+const USAGE: &str = "Usage: verify [--games <folder>] [--accounts <path>] spin <game> <server_seed> <client_seed> <nonce> <bet> [commitment] | verify [--games <folder>] [--accounts <path>] player <name> [limit]";
+
+fn load_game(games: &Path, game: &str) -> Option<Game> {
+    Game::load(&games.join(game))
+        .map_err(|err| eprintln!("Could not load game \"{}\": {}", game, err))
+        .ok()
+}
+
+fn verify_spin(games: &Path, args: &[&str]) -> ExitCode {
+    let [game, server_seed, client_seed, nonce, bet, rest @ ..] = args else {
+        eprintln!(
+            "Usage: verify spin <game> <server_seed> <client_seed> <nonce> <bet> [commitment]"
+        );
+        return ExitCode::FAILURE;
+    };
+    let (Ok(nonce), Ok(bet @ 1..)) = (nonce.parse::<u64>(), bet.parse::<usize>()) else {
+        eprintln!("Invalid nonce or bet");
+        return ExitCode::FAILURE;
+    };
+    let Some(game) = load_game(games, game) else {
+        return ExitCode::FAILURE;
+    };
+
+    let hash = commitment(server_seed);
+    println!("Commitment : {}", hash);
+    if let Some(expected) = rest.first() {
+        if hash != *expected {
+            println!("[!] Server seed doesn't match the commitment {}", expected);
+            return ExitCode::FAILURE;
+        }
+        println!("[+] Server seed matches the commitment");
+    }
+
+    let outcome = replay_spin(&game, false, server_seed, client_seed, nonce, bet - 1);
+    let symbols = outcome
+        .symbols
+        .iter()
+        .map(|s| &game.table.symbol_num_mapping[s])
+        .join(" ");
+    println!("Stops      : {}", outcome.stops.iter().join(" "));
+    for respin in &outcome.respins {
+        println!("Respin     : {}", respin.stops.iter().join(" "));
+    }
+    println!("Symbols    : {}", symbols);
+    println!("Win        : {}", outcome.win);
+
+    ExitCode::SUCCESS
+}
+
+fn verify_player(accounts: &AccountStore, games: &Path, player: &str, limit: usize) -> ExitCode {
+    let statement = accounts
+        .player_statement(player, None, limit)
+        .expect("Could not read statement");
+
+    // Spins without a win have no win transaction
+    let wins: HashMap<(&str, u64), i64> = statement
+        .iter()
+        .filter(|e| e.kind == TransactionKind::Win)
+        .filter_map(|e| {
+            let proof = e.proof.as_ref()?;
+            Some(((proof.commitment.as_str(), proof.nonce), e.amount))
+        })
+        .collect();
+
+    let mut loaded = HashMap::new();
+    let (mut verified, mut pending, mut mismatches) = (0, 0, 0);
+    for entry in statement.iter().rev() {
+        let (
+            Some(proof),
+            Some(game),
+            Some(bet),
+            TransactionKind::Wager | TransactionKind::FreeSpin,
+        ) = (&entry.proof, &entry.game, entry.bet, entry.kind)
+        else {
+            continue;
+        };
+
+        let Some(seed) = accounts
+            .revealed_seed(&proof.commitment)
+            .expect("Could not read seeds")
+        else {
+            pending += 1;
+            continue;
+        };
+
+        let Some(game) = loaded
+            .entry(game.clone())
+            .or_insert_with(|| load_game(games, game))
+        else {
+            mismatches += 1;
+            continue;
+        };
+
+        let outcome = replay_spin(
+            game,
+            entry.kind == TransactionKind::FreeSpin,
+            &seed.server_seed,
+            &proof.client_seed,
+            proof.nonce,
+            bet as usize - 1,
+        );
+        let (stops, win) = (outcome.result(), outcome.win);
+        let recorded_win = wins
+            .get(&(proof.commitment.as_str(), proof.nonce))
+            .copied()
+            .unwrap_or(0);
+
+        if commitment(&seed.server_seed) != proof.commitment
+            || stops != entry.stops
+            || win as i64 != recorded_win
+        {
+            println!(
+                "[!] Transaction #{} (nonce {}): recorded {:?} winning {}, recomputed {:?} winning {}",
+                entry.id, proof.nonce, entry.stops, recorded_win, stops, win
+            );
+            mismatches += 1;
+        } else {
+            verified += 1;
+        }
+    }
+
+    println!(
+        "[+] {} spins verified, {} waiting for their server seed to be revealed",
+        verified, pending
+    );
+    if mismatches > 0 {
+        println!("[!] {} spins could not be verified", mismatches);
+        return ExitCode::FAILURE;
+    }
+
+    ExitCode::SUCCESS
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let mut args: Vec<&str> = args.iter().map(String::as_str).collect();
+    let (games, accounts) = match (
+        take_option(&mut args, "--games"),
+        take_option(&mut args, "--accounts"),
+    ) {
+        (Ok(games), Ok(accounts)) => (
+            Path::new(games.unwrap_or(GAMES_FOLDER)),
+            Path::new(accounts.unwrap_or(ACCOUNTS_DATABASE)),
+        ),
+        (Err(err), _) | (_, Err(err)) => {
+            eprintln!("{}\n{}", err, USAGE);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    match args[..] {
+        ["spin", ref rest @ ..] => verify_spin(games, rest),
+        ["player", player, ref rest @ ..] => {
+            let Ok(limit) = rest
+                .first()
+                .map_or(Ok(DEFAULT_LIMIT), |l| l.parse::<usize>())
+            else {
+                eprintln!("Invalid limit");
+                return ExitCode::FAILURE;
+            };
+            let accounts =
+                AccountStore::open_read_only(accounts).expect("Could not open accounts database");
+
+            verify_player(&accounts, games, player, limit)
+        }
+        _ => {
+            eprintln!("{}", USAGE);
+            ExitCode::FAILURE
+        }
+    }
+}
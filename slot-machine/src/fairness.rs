@@ -0,0 +1,175 @@
+//! Provably fair spins using a commit–reveal scheme.
+//!
+//! When a player enables the provably fair mode, the server draws a secret server seed and only
+//! sends its [`commitment`] (SHA-256 hash) to the client along with the client seed chosen by the
+//! player. Each spin then gives a nonce, strictly increasing for the same server seed, and the reel
+//! stops are derived deterministically from the three values with [`derive_stops`].
+//!
+//! The server seed is revealed when the player rotates it (or disables the mode), at which point
+//! every spin made with it can be recomputed with [`replay_spin`] and checked against the
+//! commitment received beforehand, e.g. with the `verify` binary.
+
+use hmac::{Hmac, Mac};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::game::{Game, SpinOutcome};
+use crate::utils::to_hex;
+
+/// Name of the generator recorded with the provably fair spins.
+pub const FAIR_RNG: &str = "hmac-sha256";
+/// Maximum length of a client seed.
+pub const MAX_CLIENT_SEED_LENGTH: usize = 64;
+
+const SERVER_SEED_BYTES: usize = 32;
+
+/// Checks that a client seed is non-empty, at most [`MAX_CLIENT_SEED_LENGTH`] characters and only
+/// made of ASCII letters, digits, `-` and `_`.
+pub fn is_valid_client_seed(client_seed: &str) -> bool {
+    !client_seed.is_empty()
+        && client_seed.len() <= MAX_CLIENT_SEED_LENGTH
+        && client_seed
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Inputs of a provably fair spin, recorded with the spin so that it can be verified once the
+/// server seed is revealed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SpinProof {
+    /// Commitment of the server seed used for the spin.
+    pub commitment: String,
+    /// Client seed chosen by the player.
+    pub client_seed: String,
+    /// Nonce given by the client for the spin.
+    pub nonce: u64,
+}
+
+/// A server seed with the client seed it is used with. It is only sent to the client once revealed,
+/// with everything needed to verify the spins made with it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SeedPair {
+    /// The server seed, whose SHA-256 hash is the commitment.
+    pub server_seed: String,
+    /// Commitment sent to the client before the spins.
+    pub commitment: String,
+    /// Client seed used with the server seed.
+    pub client_seed: String,
+    /// Last nonce used with the server seed, if any spin was made.
+    pub last_nonce: Option<u64>,
+}
+
+/// Generates a random hex-encoded server seed from the OS random source.
+pub fn generate_server_seed() -> String {
+    let mut bytes = [0u8; SERVER_SEED_BYTES];
+    OsRng.fill_bytes(&mut bytes);
+
+    to_hex(&bytes)
+}
+
+/// Returns the commitment of a server seed: the hex-encoded SHA-256 hash of the seed.
+pub fn commitment(server_seed: &str) -> String {
+    to_hex(&Sha256::digest(server_seed.as_bytes()))
+}
+
+/// Derives the reel stops of a spin from the seeds and nonce.
+///
+/// The stops are drawn from the HMAC-SHA256 of `<client_seed>:<nonce>:<round>` keyed with the
+/// server seed, read as big-endian 32-bit integers. Integers that would bias the modulo are
+/// discarded and the `round` is incremented whenever more bytes are needed.
+pub fn derive_stops(
+    server_seed: &str,
+    client_seed: &str,
+    nonce: u64,
+    reels: usize,
+    stops: usize,
+) -> Vec<usize> {
+    let stops = stops as u64;
+    // Largest multiple of `stops` that fits, so that every stop is equally likely
+    let zone = (1u64 << 32) / stops * stops;
+
+    let mut result = Vec::with_capacity(reels);
+    let mut round = 0u64;
+    while result.len() < reels {
+        let mut mac = Hmac::<Sha256>::new_from_slice(server_seed.as_bytes())
+            .expect("HMAC accepts keys of any size");
+        mac.update(format!("{}:{}:{}", client_seed, nonce, round).as_bytes());
+
+        for chunk in mac.finalize().into_bytes().chunks_exact(4) {
+            let n = u32::from_be_bytes(chunk.try_into().unwrap()) as u64;
+            if n < zone && result.len() < reels {
+                result.push((n % stops) as usize);
+            }
+        }
+        round += 1;
+    }
+
+    result
+}
+
+/// Recomputes a provably fair spin of a game, with its respins if it triggers some.
+pub fn replay_spin(
+    game: &Game,
+    free_spin: bool,
+    server_seed: &str,
+    client_seed: &str,
+    nonce: u64,
+    bet: usize,
+) -> SpinOutcome {
+    let (size, stops) = game.draw_size(free_spin);
+    let drawn = derive_stops(server_seed, client_seed, nonce, size, stops);
+
+    game.play(&drawn, bet, free_spin)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn commitment_is_the_sha256_of_the_seed() {
+        assert_eq!(
+            commitment("server seed"),
+            "a4e53dc2f480b8fce6fe688b1317658b446299df23ad533394406427c8c19557"
+        );
+    }
+
+    #[test]
+    fn derived_stops_match_known_vectors() {
+        assert_eq!(
+            derive_stops("server seed", "client-seed", 0, 3, 64),
+            [19, 19, 36]
+        );
+        assert_eq!(
+            derive_stops("server seed", "client-seed", 1, 3, 64),
+            [21, 55, 2]
+        );
+        // More stops than a single HMAC provides, drawn over two rounds
+        assert_eq!(
+            derive_stops("server seed", "client-seed", 7, 12, 22),
+            [14, 4, 0, 18, 14, 5, 20, 17, 11, 12, 14, 10]
+        );
+    }
+
+    #[test]
+    fn generated_server_seeds_are_unique() {
+        let seed = generate_server_seed();
+
+        assert_eq!(seed.len(), 2 * SERVER_SEED_BYTES);
+        assert_ne!(seed, generate_server_seed());
+    }
+
+    #[test]
+    fn client_seeds_are_validated() {
+        assert!(is_valid_client_seed("lucky-seed_42"));
+        assert!(is_valid_client_seed(&"a".repeat(MAX_CLIENT_SEED_LENGTH)));
+        assert!(!is_valid_client_seed(""));
+        assert!(!is_valid_client_seed(
+            &"a".repeat(MAX_CLIENT_SEED_LENGTH + 1)
+        ));
+        assert!(!is_valid_client_seed("with space"));
+        assert!(!is_valid_client_seed("colon:seed"));
+    }
+}
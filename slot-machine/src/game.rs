@@ -0,0 +1,911 @@
+//! Games loaded from the `GAMES_FOLDER`, combining the par table used by the server to compute the
+//! spins with the display-level definition sent to the clients.
+
+use std::{
+    collections::HashMap,
+    fmt, fs,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::par_table::{for_each_combination, ParStats, ParTable, ParTableFiles};
+use crate::rng::SlotRng;
+
+/// Name of the optional file describing a game's metadata.
+pub const GAME_INFO_FILE: &str = "gameinfo.json";
+/// Name of the file mapping the display symbols of a game to their display names.
+pub const DISPLAY_FILE: &str = "display.csv";
+/// Name of the optional file declaring the progressive jackpot of a game.
+pub const JACKPOT_FILE: &str = "jackpot.json";
+/// Name of the optional file declaring the free spins bonus feature of a game.
+pub const FREE_SPINS_FILE: &str = "freespins.json";
+/// Name of the optional file declaring the respins of a game.
+pub const RESPIN_FILE: &str = "respin.json";
+/// Name of the optional file declaring the gamble offered after the wins of a game.
+pub const GAMBLE_FILE: &str = "gamble.json";
+
+/// Descriptive information about a game, read from the game's `gameinfo.json` file.
+///
+/// Every field is optional in the file, missing values are filled with defaults.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GameInfo {
+    /// Game string identifier (subfolder name in `GAMES_FOLDER`).
+    pub id: String,
+    /// Display name of the game.
+    pub name: String,
+    /// Author of the game.
+    pub author: String,
+    /// Version of the game.
+    pub version: String,
+    /// Help message describing the rules of the game.
+    pub help: String,
+}
+
+impl Default for GameInfo {
+    fn default() -> Self {
+        Self {
+            id: String::new(),
+            name: String::new(),
+            author: "Unknown".to_string(),
+            version: "0.0.1".to_string(),
+            help: String::new(),
+        }
+    }
+}
+
+/// Everything a client needs to display a game, without the paytable internals.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GameDefinition {
+    /// Game metadata.
+    pub info: GameInfo,
+    /// Reels of the game as display symbol identifiers, stored by rows like [`ParTable::reels`].
+    pub reels: Vec<Vec<String>>,
+    /// Mapping of a display symbol identifier to its display name in the symbols catalog
+    /// (`display_symbols.json`).
+    pub display_symbols: HashMap<String, String>,
+    /// Reels of the free spins, if the game has a free spins feature with its own reel set.
+    #[serde(default)]
+    pub free_spins_reels: Option<Vec<Vec<String>>>,
+    /// Gamble offered after the wins of the game, if it declares one.
+    #[serde(default)]
+    pub gamble: Option<Gamble>,
+}
+
+/// Progressive jackpot of a game, read from the game's `jackpot.json` file.
+///
+/// A share of every wager on the game is added to a pool shared by all the players, which is paid
+/// on top of the combo payout to the player hitting the jackpot combo, then reset to its seed.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Jackpot {
+    /// Value of the pool when it is created and after each hit.
+    pub seed: u64,
+    /// Percentage of each wager added to the pool.
+    pub contribution: f64,
+    /// Combo of the paytable winning the pool, as space separated symbol identifiers (e.g.
+    /// `"B7 B7 DJ"`). It wins whatever the bet size, as long as it is the winning combo of the spin.
+    pub combo: String,
+}
+
+/// Free spins bonus feature of a game, read from the game's `freespins.json` file.
+///
+/// A spin displaying the scatter symbol on at least `count` reels awards `spins` free spins, played
+/// with the bet of the triggering spin. Their wins are multiplied by `multiplier`, and they are
+/// drawn from their own reel set if `reels` is given.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FreeSpins {
+    /// Display symbol identifier triggering the free spins.
+    pub scatter: String,
+    /// Minimum number of reels displaying the scatter symbol.
+    pub count: usize,
+    /// Number of free spins awarded.
+    pub spins: u32,
+    /// Multiplier of the wins of the free spins.
+    #[serde(default = "default_multiplier")]
+    pub multiplier: u64,
+    /// Whether free spins can award more free spins.
+    #[serde(default = "default_retrigger")]
+    pub retrigger: bool,
+    /// CSV file of the game folder holding the reels of the free spins, in the format of
+    /// `reels.csv`. The reels of the game are used if not given.
+    #[serde(default)]
+    pub reels: Option<String>,
+}
+
+fn default_multiplier() -> u64 {
+    1
+}
+
+fn default_retrigger() -> bool {
+    true
+}
+
+/// Progressive jackpot of a loaded game.
+pub struct JackpotFeature {
+    /// Declaration of the jackpot.
+    pub definition: Jackpot,
+    /// Winning combo of the paytable paying the pool.
+    pub combo: Vec<u64>,
+}
+
+impl JackpotFeature {
+    /// Whether a spin with the given winning combo hits the jackpot.
+    pub fn is_hit(&self, combo: Option<&[u64]>) -> bool {
+        combo == Some(self.combo.as_slice())
+    }
+}
+
+/// Free spins feature of a loaded game.
+pub struct FreeSpinsFeature {
+    /// Declaration of the feature.
+    pub definition: FreeSpins,
+    /// Par table of the free spins, with their own reel set.
+    pub table: ParTable,
+    /// Bitmask of the scatter symbol.
+    pub scatter: u64,
+}
+
+/// Double-or-nothing gamble of a game, read from the game's `gamble.json` file.
+///
+/// The win of a paid spin can be risked on a card colour or a coin face, doubling it when guessed
+/// right and losing it otherwise. The doubled win can be gambled again, up to `rounds` times in a
+/// row and as long as doubling it doesn't exceed `max_win`. Even odds leave the return to player of
+/// the game unchanged.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Gamble {
+    /// What the player guesses.
+    pub kind: GambleKind,
+    /// Maximum number of gambles in a row on the same win.
+    pub rounds: u32,
+    /// Maximum amount a gamble can win.
+    pub max_win: u64,
+}
+
+/// What the player guesses in a gamble, each outcome being equally likely.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GambleKind {
+    /// Colour of a card drawn from a full deck.
+    Card,
+    /// Face of a coin flip.
+    Coin,
+}
+
+/// A card colour or a coin face, guessed or drawn in a gamble.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GambleGuess {
+    /// Red card (hearts or diamonds).
+    Red,
+    /// Black card (spades or clubs).
+    Black,
+    /// Coin landing on heads.
+    Heads,
+    /// Coin landing on tails.
+    Tails,
+}
+
+impl fmt::Display for GambleGuess {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad(&format!("{:?}", self))
+    }
+}
+
+impl GambleKind {
+    /// The two outcomes of a gamble of this kind.
+    pub fn outcomes(&self) -> [GambleGuess; 2] {
+        match self {
+            GambleKind::Card => [GambleGuess::Red, GambleGuess::Black],
+            GambleKind::Coin => [GambleGuess::Heads, GambleGuess::Tails],
+        }
+    }
+}
+
+impl Gamble {
+    /// Number of gambles left on `amount` after `played` gambles in a row: it can be doubled again
+    /// while rounds are left and the doubled amount doesn't exceed the maximum win.
+    pub fn gambles_left(&self, amount: u64, played: u32) -> u32 {
+        let mut left = 0;
+        let mut amount = amount;
+        while amount > 0 && played + left < self.rounds && amount * 2 <= self.max_win {
+            amount *= 2;
+            left += 1;
+        }
+
+        left
+    }
+
+    /// Draws the outcome of a gamble, as a single stop out of the two outcomes of the kind.
+    pub fn draw(&self, rng: &mut SlotRng) -> GambleGuess {
+        self.kind.outcomes()[rng.draw_stops(1, 2)[0]]
+    }
+}
+
+/// Respin of a game, read as one of the triggers listed in the game's `respin.json` file.
+///
+/// A spin paying nothing and matching `combo` locks the `hold` reels and spins the others again, up
+/// to `respins` times as long as the result still pays nothing and matches the combo. The result of
+/// the last respin is the one paid. The first trigger of the file matched by the spin applies.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Respin {
+    /// Combo triggering the respins, as space separated symbol identifiers in the format of the
+    /// paytable combos (e.g. `"7J 7J XX"`).
+    pub combo: String,
+    /// Reels locked during the respins, numbered from 1.
+    pub hold: Vec<usize>,
+    /// Maximum number of respins.
+    #[serde(default = "default_respins")]
+    pub respins: u32,
+}
+
+fn default_respins() -> u32 {
+    1
+}
+
+/// Respin trigger of a loaded game.
+pub struct RespinTrigger {
+    /// Declaration of the trigger.
+    pub definition: Respin,
+    /// Symbols matched on each reel by the trigger combo, as a bitmask.
+    pub combo: Vec<u64>,
+}
+
+impl RespinTrigger {
+    /// Whether the symbols displayed by a spin match the trigger combo.
+    pub fn matches(&self, symbols: &[u64]) -> bool {
+        self.combo.iter().zip(symbols).all(|(m, s)| m & s == *s)
+    }
+
+    /// Whether a reel (numbered from 0) is locked during the respins.
+    pub fn holds(&self, reel: usize) -> bool {
+        self.definition.hold.contains(&(reel + 1))
+    }
+}
+
+/// Reels respun after a spin, as reported to the clients.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RespinStep {
+    /// Reels locked during the respin, numbered from 0.
+    pub held: Vec<usize>,
+    /// Reel stops after the respin, the held reels keeping theirs.
+    pub stops: Vec<usize>,
+}
+
+/// Outcome of a spin played with [`Game::play`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpinOutcome {
+    /// Reel stops of the spin, before any respin.
+    pub stops: Vec<usize>,
+    /// Respins following the spin, the last one giving the result.
+    pub respins: Vec<RespinStep>,
+    /// Symbols displayed by the result.
+    pub symbols: Vec<u64>,
+    /// Winning combo of the paytable matched by the result, if any.
+    pub combo: Option<Vec<u64>>,
+    /// Amount won with the combo, multiplied for a free spin.
+    pub win: u64,
+    /// Number of free spins awarded by the result.
+    pub free_spins_won: u32,
+}
+
+impl SpinOutcome {
+    /// Reel stops of the result, after the respins.
+    pub fn result(&self) -> &[usize] {
+        self.respins.last().map_or(&self.stops, |step| &step.stops)
+    }
+}
+
+/// A game loaded by the server.
+pub struct Game {
+    /// Par table used for computing the spins results.
+    pub table: ParTable,
+    /// Display-level definition sent to the clients.
+    pub definition: GameDefinition,
+    /// Progressive jackpot of the game, if it declares one. It is only won on paid spins.
+    pub jackpot: Option<JackpotFeature>,
+    /// Free spins feature of the game, if it declares one.
+    pub free_spins: Option<FreeSpinsFeature>,
+    /// Respin triggers of the game, in the order they are matched.
+    pub respins: Vec<RespinTrigger>,
+    /// Expected outcome of each bet size, including the respins, the free spins and the progressive
+    /// jackpot.
+    pub par: Vec<ParStats>,
+    /// Generator drawing the spins of the game, unseeded ChaCha20 until configured otherwise. A
+    /// new version of the game can share it with the previous one, so that its sequence goes on.
+    pub rng: Arc<Mutex<SlotRng>>,
+    /// Generator drawing the gambles of the game, apart from the spins so that the sequence of
+    /// spins of a seeded generator can be drawn again.
+    pub gamble_rng: Mutex<SlotRng>,
+}
+
+impl Game {
+    /// Loads a game from its folder. The folder name is used as the game identifier.
+    pub fn load(folder: &Path) -> Result<Game> {
+        let id = folder
+            .file_name()
+            .and_then(|f| f.to_str())
+            .ok_or_else(|| anyhow!("Invalid game folder {:?}", folder))?
+            .to_string();
+
+        let csv_files: Vec<String> = fs::read_dir(folder)?
+            .map(|p| Ok(p?.path().display().to_string()))
+            .collect::<Result<_>>()?;
+
+        let mut table = ParTable::default();
+        table.parse_from_csv(
+            ParTableFiles::try_from(csv_files)
+                .map_err(|_| anyhow!("Missing CSV files for game \"{}\"", id))?,
+        )?;
+
+        let mut info: GameInfo = match fs::read_to_string(folder.join(GAME_INFO_FILE)) {
+            Ok(f) => serde_json::from_str(&f)
+                .with_context(|| format!("Could not parse {} for \"{}\"", GAME_INFO_FILE, id))?,
+            Err(_) => GameInfo::default(),
+        };
+        if info.name.is_empty() {
+            info.name = id.clone();
+        }
+        info.id = id;
+
+        let mut rdr = csv::Reader::from_path(folder.join(DISPLAY_FILE))?;
+        let display_symbols = rdr
+            .deserialize::<(String, String)>()
+            .collect::<Result<HashMap<_, _>, _>>()?;
+
+        let free_spins: Option<FreeSpins> = match fs::read_to_string(folder.join(FREE_SPINS_FILE)) {
+            Ok(f) => Some(serde_json::from_str(&f).with_context(|| {
+                format!("Could not parse {} for \"{}\"", FREE_SPINS_FILE, info.id)
+            })?),
+            Err(_) => None,
+        };
+        let free_spins = free_spins
+            .map(|definition| {
+                let table = match &definition.reels {
+                    Some(file) => table
+                        .with_reels(&folder.join(file).display().to_string())
+                        .with_context(|| {
+                            format!("Could not load the free spins reels of \"{}\"", info.id)
+                        })?,
+                    None => table.clone(),
+                };
+                let scatter = *table
+                    .symbol_str_mapping
+                    .get(&definition.scatter)
+                    .ok_or_else(|| {
+                        anyhow!(
+                            "Unknown scatter symbol \"{}\" in {} of \"{}\"",
+                            definition.scatter,
+                            FREE_SPINS_FILE,
+                            info.id
+                        )
+                    })?;
+
+                if !(1..=table.reels[0].len()).contains(&definition.count) {
+                    return Err(anyhow!(
+                        "Free spins of \"{}\" must be triggered by 1 to {} scatter symbols",
+                        info.id,
+                        table.reels[0].len()
+                    ));
+                }
+
+                Ok::<_, anyhow::Error>(FreeSpinsFeature {
+                    definition,
+                    table,
+                    scatter,
+                })
+            })
+            .transpose()?;
+
+        let to_display = |table: &ParTable| -> Vec<Vec<String>> {
+            table
+                .reels
+                .iter()
+                .map(|row| {
+                    row.iter()
+                        .map(|s| table.symbol_num_mapping[s].clone())
+                        .collect()
+                })
+                .collect()
+        };
+        let reels = to_display(&table);
+        let free_spins_reels = free_spins
+            .as_ref()
+            .filter(|feature| feature.definition.reels.is_some())
+            .map(|feature| to_display(&feature.table));
+        if let Some(symbol) = reels
+            .iter()
+            .chain(free_spins_reels.iter().flatten())
+            .flatten()
+            .find(|s| !display_symbols.contains_key(*s))
+        {
+            return Err(anyhow!(
+                "Missing display symbol for \"{}\" in {} of \"{}\"",
+                symbol,
+                DISPLAY_FILE,
+                info.id
+            ));
+        }
+
+        let respins: Vec<Respin> = match fs::read_to_string(folder.join(RESPIN_FILE)) {
+            Ok(f) => serde_json::from_str(&f)
+                .with_context(|| format!("Could not parse {} for \"{}\"", RESPIN_FILE, info.id))?,
+            Err(_) => vec![],
+        };
+        let reel_count = table.reels[0].len();
+        let respins = respins
+            .into_iter()
+            .map(|definition| {
+                let combo = table
+                    .parse_combo(&definition.combo)
+                    .ok()
+                    .filter(|combo| combo.len() == reel_count)
+                    .ok_or_else(|| {
+                        anyhow!(
+                            "Invalid respin combo \"{}\" in {} of \"{}\"",
+                            definition.combo,
+                            RESPIN_FILE,
+                            info.id
+                        )
+                    })?;
+                if definition.respins == 0
+                    || definition.hold.len() >= reel_count
+                    || definition
+                        .hold
+                        .iter()
+                        .any(|r| !(1..=reel_count).contains(r))
+                {
+                    return Err(anyhow!(
+                        "Respin \"{}\" of \"{}\" must hold some of the reels 1 to {} at least once",
+                        definition.combo,
+                        info.id,
+                        reel_count
+                    ));
+                }
+
+                Ok(RespinTrigger {
+                    combo: combo.iter().map(|x| table.combo_symbols[x]).collect(),
+                    definition,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let jackpot: Option<Jackpot> = match fs::read_to_string(folder.join(JACKPOT_FILE)) {
+            Ok(f) => Some(serde_json::from_str(&f).with_context(|| {
+                format!("Could not parse {} for \"{}\"", JACKPOT_FILE, info.id)
+            })?),
+            Err(_) => None,
+        };
+        let jackpot = jackpot
+            .map(|jackpot| {
+                let combo = table
+                    .parse_combo(&jackpot.combo)
+                    .ok()
+                    .filter(|combo| table.paytable.contains_key(combo))
+                    .ok_or_else(|| {
+                        anyhow!(
+                            "Jackpot combo \"{}\" of \"{}\" is not in the paytable",
+                            jackpot.combo,
+                            info.id
+                        )
+                    })?;
+                if !(0.0..100.0).contains(&jackpot.contribution) {
+                    return Err(anyhow!(
+                        "Jackpot contribution of \"{}\" must be a percentage below 100",
+                        info.id
+                    ));
+                }
+
+                Ok(JackpotFeature {
+                    definition: jackpot,
+                    combo,
+                })
+            })
+            .transpose()?;
+
+        let gamble: Option<Gamble> =
+            match fs::read_to_string(folder.join(GAMBLE_FILE)) {
+                Ok(f) => Some(serde_json::from_str(&f).with_context(|| {
+                    format!("Could not parse {} for \"{}\"", GAMBLE_FILE, info.id)
+                })?),
+                Err(_) => None,
+            };
+        if gamble
+            .as_ref()
+            .is_some_and(|gamble| gamble.rounds == 0 || gamble.max_win == 0)
+        {
+            return Err(anyhow!(
+                "Gamble of \"{}\" must allow at least one round and a maximum win",
+                info.id
+            ));
+        }
+
+        let scatter = free_spins
+            .as_ref()
+            .map(|feature| (feature.scatter, feature.definition.count));
+        let jackpot_combo = jackpot.as_ref().map(|jackpot| jackpot.combo.as_slice());
+        let respun = respin_stats(&table, &respins, jackpot_combo, scatter);
+        let mut par = table.par_stats();
+        for (bet, (stats, respin)) in par.iter_mut().zip(&respun).enumerate() {
+            stats.rtp += respin.win / (bet + 1) as f64;
+            stats.hit_frequency += respin.hit;
+        }
+
+        if let Some(feature) = &free_spins {
+            let FreeSpins {
+                count,
+                spins,
+                multiplier,
+                retrigger,
+                ..
+            } = feature.definition;
+            let free_respun = respin_stats(&feature.table, &respins, None, scatter);
+            let free_par = feature.table.par_stats();
+            let trigger = table.scatter_frequency(feature.scatter, count);
+            let retrigger_frequency = feature.table.scatter_frequency(feature.scatter, count);
+
+            for (bet, stats) in par.iter_mut().enumerate() {
+                let trigger = trigger + respun[bet].scatter;
+                let retrigger = if retrigger {
+                    retrigger_frequency + free_respun[bet].scatter
+                } else {
+                    0.0
+                };
+                // Each free spin awards `spins` more with the retrigger probability
+                if spins as f64 * retrigger >= 1.0 {
+                    return Err(anyhow!(
+                        "Free spins of \"{}\" retrigger too often to ever end",
+                        info.id
+                    ));
+                }
+
+                // Free spins are played with the bet of the triggering spin
+                let expected_spins = spins as f64 / (1.0 - spins as f64 * retrigger);
+                let free_rtp = free_par[bet].rtp + free_respun[bet].win / (bet + 1) as f64;
+                stats.rtp += trigger * expected_spins * multiplier as f64 * free_rtp;
+            }
+        }
+
+        if let Some(JackpotFeature {
+            definition: jackpot,
+            combo,
+        }) = &jackpot
+        {
+            // Every contribution is paid back by a hit, on top of the seed
+            let frequency = table.combo_frequency(combo);
+            for (bet, stats) in par.iter_mut().enumerate() {
+                let frequency = frequency + respun[bet].jackpot;
+                stats.rtp += jackpot.contribution / 100.0
+                    + jackpot.seed as f64 * frequency / (bet + 1) as f64;
+                if table.paytable[combo].get(bet).copied().unwrap_or_default() == 0 {
+                    stats.hit_frequency += frequency;
+                }
+            }
+        }
+
+        Ok(Game {
+            par,
+            jackpot,
+            free_spins,
+            respins,
+            table,
+            definition: GameDefinition {
+                info,
+                reels,
+                display_symbols,
+                free_spins_reels,
+                gamble,
+            },
+            rng: Arc::new(Mutex::new(SlotRng::default())),
+            gamble_rng: Mutex::new(SlotRng::default()),
+        })
+    }
+
+    /// Par table and win multiplier of a spin, free spins having their own.
+    pub fn spin_table(&self, free_spin: bool) -> (&ParTable, u64) {
+        match &self.free_spins {
+            Some(feature) if free_spin => (&feature.table, feature.definition.multiplier),
+            _ => (&self.table, 1),
+        }
+    }
+
+    /// Number of free spins awarded by a spin displaying `symbols`.
+    pub fn free_spins_won(&self, symbols: &[u64], free_spin: bool) -> u32 {
+        match &self.free_spins {
+            Some(FreeSpinsFeature {
+                definition,
+                scatter,
+                ..
+            }) if !free_spin || definition.retrigger => {
+                let scatters = symbols.iter().filter(|s| *s == scatter).count();
+                if scatters >= definition.count {
+                    definition.spins
+                } else {
+                    0
+                }
+            }
+            _ => 0,
+        }
+    }
+
+    /// Number of reel stops to draw for a spin and number of stops of each reel. The stops of the
+    /// spin are followed by the stops of every respin it may trigger, one per reel each time.
+    pub fn draw_size(&self, free_spin: bool) -> (usize, usize) {
+        let (table, _) = self.spin_table(free_spin);
+        let respins = self
+            .respins
+            .iter()
+            .map(|trigger| trigger.definition.respins as usize)
+            .max()
+            .unwrap_or_default();
+
+        (table.reels[0].len() * (1 + respins), table.reels.len())
+    }
+
+    /// Plays a spin from the reel stops drawn for it (see [`Game::draw_size`]), respinning the reels
+    /// while it triggers a respin. Draws shorter than needed end the respins early.
+    pub fn play(&self, drawn: &[usize], bet: usize, free_spin: bool) -> SpinOutcome {
+        let (table, multiplier) = self.spin_table(free_spin);
+        let reels = table.reels[0].len();
+        let pays = |result: &Option<(Vec<u64>, u64)>| result.as_ref().is_some_and(|(_, w)| *w > 0);
+
+        let mut stops = drawn[..reels].to_vec();
+        let mut symbols = table.symbols_at(&stops);
+        let mut result = table.calculate_win(symbols.clone(), bet);
+        let mut respins = vec![];
+        let trigger = self
+            .respins
+            .iter()
+            .find(|trigger| !pays(&result) && trigger.matches(&symbols));
+        if let Some(trigger) = trigger {
+            let held: Vec<usize> = (0..reels).filter(|r| trigger.holds(*r)).collect();
+            for respin in drawn[reels..]
+                .chunks_exact(reels)
+                .take(trigger.definition.respins as usize)
+            {
+                (0..reels)
+                    .filter(|r| !trigger.holds(*r))
+                    .for_each(|r| stops[r] = respin[r]);
+                symbols = table.symbols_at(&stops);
+                result = table.calculate_win(symbols.clone(), bet);
+                respins.push(RespinStep {
+                    held: held.clone(),
+                    stops: stops.clone(),
+                });
+
+                if pays(&result) || !trigger.matches(&symbols) {
+                    break;
+                }
+            }
+        }
+
+        let (combo, win) = result.map_or((None, 0), |(combo, win)| (Some(combo), win * multiplier));
+        SpinOutcome {
+            stops: drawn[..reels].to_vec(),
+            respins,
+            free_spins_won: self.free_spins_won(&symbols, free_spin),
+            symbols,
+            combo,
+            win,
+        }
+    }
+}
+
+/// Expected change of the outcome of a spin caused by the respins, for a bet size.
+#[derive(Debug, Clone, Copy, Default)]
+struct RespinStats {
+    /// Amount won.
+    win: f64,
+    /// Probability of winning something.
+    hit: f64,
+    /// Probability of hitting the jackpot combo.
+    jackpot: f64,
+    /// Probability of triggering the free spins.
+    scatter: f64,
+}
+
+/// Computes the [`RespinStats`] of each bet size by evaluating every combination of reel symbols
+/// triggering a respin, along with every combination of the respun reels. The jackpot combo and the
+/// scatter symbol with its count tell how often the results hit them.
+fn respin_stats(
+    table: &ParTable,
+    triggers: &[RespinTrigger],
+    jackpot: Option<&[u64]>,
+    scatter: Option<(u64, usize)>,
+) -> Vec<RespinStats> {
+    let mut stats = vec![RespinStats::default(); table.max_bet as usize];
+    if triggers.is_empty() {
+        return stats;
+    }
+
+    // Stops displaying the same symbol have the same outcome, so only the symbols are evaluated
+    let reel_symbols = table.reel_symbols();
+    let matcher = table.combo_matcher();
+
+    for (bet, stats) in stats.iter_mut().enumerate() {
+        // Payout of a result at the bet size, along with the values summed up in the stats
+        let outcome = |symbols: &[u64]| -> (u64, [f64; 4]) {
+            let combo = matcher(symbols);
+            let win = combo.and_then(|c| table.paytable[c].get(bet)).copied();
+            let hit_jackpot = win.is_some() && combo.map(|c| c.as_slice()) == jackpot;
+            let hit_scatter = scatter.is_some_and(|(symbol, count)| {
+                symbols.iter().filter(|s| **s == symbol).count() >= count
+            });
+            let win = win.unwrap_or_default();
+
+            (
+                win,
+                [
+                    win as f64,
+                    (win > 0) as u8 as f64,
+                    hit_jackpot as u8 as f64,
+                    hit_scatter as u8 as f64,
+                ],
+            )
+        };
+        // Expected outcome of the respins of a trigger, which only depends on the held symbols
+        let respin = |trigger: &RespinTrigger, held: &[u64]| -> [f64; 4] {
+            let reels: Vec<Vec<(u64, f64)>> = reel_symbols
+                .iter()
+                .enumerate()
+                .map(|(r, symbols)| match trigger.holds(r) {
+                    true => vec![(held[r], 1.0)],
+                    false => symbols.clone(),
+                })
+                .collect();
+            let (mut settled, mut again, mut repeated) = ([0.0; 4], 0.0, [0.0; 4]);
+
+            for_each_combination(&reels, |symbols, p| {
+                let (win, value) = outcome(symbols);
+                let sum = if win == 0 && trigger.matches(symbols) {
+                    again += p;
+                    &mut repeated
+                } else {
+                    &mut settled
+                };
+                sum.iter_mut().zip(value).for_each(|(sum, v)| *sum += v * p);
+            });
+
+            // The last respin is paid whatever its result, the previous ones only end the respins
+            // when they pay or stop matching the trigger
+            let mut expected: [f64; 4] = std::array::from_fn(|i| settled[i] + repeated[i]);
+            for _ in 1..trigger.definition.respins {
+                expected = std::array::from_fn(|i| settled[i] + again * expected[i]);
+            }
+            expected
+        };
+
+        let mut respun: HashMap<(usize, Vec<u64>), [f64; 4]> = HashMap::new();
+        let mut total = [0.0; 4];
+        for_each_combination(&reel_symbols, |symbols, p| {
+            let (win, before) = outcome(symbols);
+            let Some(t) = triggers
+                .iter()
+                .position(|trigger| win == 0 && trigger.matches(symbols))
+            else {
+                return;
+            };
+
+            let held = (0..symbols.len())
+                .map(|r| if triggers[t].holds(r) { symbols[r] } else { 0 })
+                .collect::<Vec<_>>();
+            let after = *respun
+                .entry((t, held))
+                .or_insert_with_key(|(_, held)| respin(&triggers[t], held));
+            total
+                .iter_mut()
+                .zip(after.iter().zip(before))
+                .for_each(|(total, (after, before))| *total += (after - before) * p);
+        });
+
+        let [win, hit, jackpot, scatter] = total;
+        *stats = RespinStats {
+            win,
+            hit,
+            jackpot,
+            scatter,
+        };
+    }
+
+    stats
+}
+
+/// Loads all the games found in the subfolders of `folder`, indexed by their identifier.
+pub fn load_games(folder: impl AsRef<Path>) -> Result<HashMap<String, Game>> {
+    let mut games = HashMap::new();
+
+    for path in fs::read_dir(folder)? {
+        let path = path?;
+        if !path.metadata()?.is_dir() {
+            continue;
+        }
+
+        let game = Game::load(&path.path())?;
+        games.insert(game.definition.info.id.clone(), game);
+    }
+
+    Ok(games)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes the files of a game to a temporary folder, removed when dropped.
+    struct GameFolder(std::path::PathBuf);
+
+    impl GameFolder {
+        fn new(name: &str, files: &[(&str, &str)]) -> Self {
+            let folder =
+                std::env::temp_dir().join(format!("slot-machine-{}-{}", name, std::process::id()));
+            fs::create_dir_all(&folder).unwrap();
+            for (file, content) in files {
+                fs::write(folder.join(file), content).unwrap();
+            }
+            GameFolder(folder)
+        }
+    }
+
+    impl Drop for GameFolder {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn respins_par_matches_every_draw_played() {
+        let folder = GameFolder::new(
+            "respin",
+            &[
+                ("symbols.csv", "Symbol,Ref\nBL,BL\nA,A\nB,B\nNA,B|BL\n"),
+                (
+                    "paytable.csv",
+                    "Combo,\"Pay 1\"\n\"A A A\",20\n\"B B B\",10\n",
+                ),
+                (
+                    "reels.csv",
+                    "\"Reel 1\",\"Reel 2\",\"Reel 3\"\nA,A,A\nA,B,B\nB,BL,BL\nBL,BL,BL\n",
+                ),
+                ("display.csv", "Symbol,Display\nBL,blank\nA,seven\nB,bar\n"),
+                (
+                    RESPIN_FILE,
+                    r#"[
+                        {"combo": "A A NA", "hold": [1, 2], "respins": 2},
+                        {"combo": "NA B B", "hold": [2, 3]}
+                    ]"#,
+                ),
+            ],
+        );
+        let game = Game::load(&folder.0).unwrap();
+
+        // Three sevens pay 20/32, three bars 10/64. Two sevens (3/32) respin the third reel up to
+        // twice for a seven (1/4 each time), a blank and two bars (1/64) the first reel once for a
+        // bar (1/4).
+        let expected = 20.0 / 32.0
+            + 10.0 / 64.0
+            + 3.0 / 32.0 * 20.0 * (1.0 / 4.0 + 3.0 / 4.0 * 1.0 / 4.0)
+            + 1.0 / 64.0 * 10.0 / 4.0;
+        assert!((game.par[0].rtp - expected).abs() < 1e-12);
+
+        let (size, stops) = game.draw_size(false);
+        assert_eq!((size, stops), (9, 4));
+        let (mut won, mut hits, mut draws) = (0, 0, 0);
+        for mut index in 0..stops.pow(size as u32) {
+            let drawn: Vec<usize> = (0..size)
+                .map(|_| {
+                    let stop = index % stops;
+                    index /= stops;
+                    stop
+                })
+                .collect();
+            let outcome = game.play(&drawn, 0, false);
+            won += outcome.win;
+            hits += (outcome.win > 0) as u64;
+            draws += 1;
+        }
+
+        assert!((game.par[0].rtp - won as f64 / draws as f64).abs() < 1e-12);
+        assert!((game.par[0].hit_frequency - hits as f64 / draws as f64).abs() < 1e-12);
+    }
+}
@@ -0,0 +1,88 @@
+//! Responsible gaming limits of the player accounts.
+//!
+//! Players set their own [`PlayerLimits`] with [`SetLimits`](crate::protocol::ClientCommand::SetLimits),
+//! which the daemon checks before every spin. A stricter limit applies at once, while loosening a
+//! limit only applies after a delay (a day by default), so that it can't be lifted in the heat of
+//! the moment. For the same reason, a [`SelfExclude`](crate::protocol::ClientCommand::SelfExclude)
+//! period can only be extended, never shortened.
+//!
+//! Losses are the coins wagered minus the coins won: over the last 24 hours for the daily limit,
+//! and since the play session started for the session limit. A play session starts when the player
+//! logs in and goes on across logins and reconnections, until the player neither logged in nor
+//! played for a break (half an hour by default), so that logging in again doesn't reset the
+//! session limits.
+
+use std::time::{Duration, SystemTime};
+
+use serde::{Deserialize, Serialize};
+
+/// Period over which the daily loss limit applies.
+pub const DAILY_LOSS_PERIOD: Duration = Duration::from_secs(24 * 60 * 60);
+/// Minimum interval between two reality checks.
+pub const MIN_REALITY_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+/// Maximum length of a self-exclusion period.
+pub const MAX_SELF_EXCLUSION: Duration = Duration::from_secs(100 * 365 * 24 * 60 * 60);
+
+/// Limits of a player account, `None` meaning no limit.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PlayerLimits {
+    /// Maximum loss over the last 24 hours.
+    pub daily_loss: Option<u64>,
+    /// Maximum loss since the play session started.
+    pub session_loss: Option<u64>,
+    /// Maximum cost of a single spin.
+    pub max_wager: Option<u64>,
+    /// Maximum time spent playing since the play session started.
+    pub session_time: Option<Duration>,
+    /// Interval of the [`RealityCheck`](crate::protocol::ServerEvent::RealityCheck) reminders.
+    pub reality_check: Option<Duration>,
+}
+
+impl PlayerLimits {
+    /// Returns the strictest value of each limit between both.
+    pub fn strictest(&self, other: &PlayerLimits) -> PlayerLimits {
+        fn min<T: Ord>(a: Option<T>, b: Option<T>) -> Option<T> {
+            match (a, b) {
+                (Some(a), Some(b)) => Some(a.min(b)),
+                (a, b) => a.or(b),
+            }
+        }
+
+        PlayerLimits {
+            daily_loss: min(self.daily_loss, other.daily_loss),
+            session_loss: min(self.session_loss, other.session_loss),
+            max_wager: min(self.max_wager, other.max_wager),
+            session_time: min(self.session_time, other.session_time),
+            reality_check: min(self.reality_check, other.reality_check),
+        }
+    }
+}
+
+/// Loosened limits waiting for their delay to pass.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PendingLimits {
+    /// Limits requested by the player.
+    pub limits: PlayerLimits,
+    /// Time at which they replace the current limits.
+    pub effective_at: SystemTime,
+}
+
+/// Limits and self-exclusion of a player account.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LimitsStatus {
+    /// Limits currently enforced.
+    pub limits: PlayerLimits,
+    /// Loosened limits that will apply later, if any.
+    pub pending: Option<PendingLimits>,
+    /// End of the self-exclusion period, if the player excluded themselves.
+    pub excluded_until: Option<SystemTime>,
+}
+
+impl LimitsStatus {
+    /// Returns `true` if the player can't play until the end of a self-exclusion period.
+    pub fn is_excluded(&self) -> bool {
+        self.excluded_until
+            .is_some_and(|until| SystemTime::now() < until)
+    }
+}
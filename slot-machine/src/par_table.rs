@@ -64,10 +64,20 @@ impl ParTableFiles {
     }
 }
 
+/// Expected outcome of the spins of a bet size, as given by the par sheet of a game.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ParStats {
+    /// Return to player, the average amount won per coin wagered.
+    pub rtp: f64,
+    /// Share of the spins that win something.
+    pub hit_frequency: f64,
+}
+
 /// Holds a game's information and can calculate the winnings given a set of random reel indexes.
 ///
 /// It uses a bitmask representation of symbols in order to generate the required reference symbols and
 /// check for combos using bitwise operations.
+#[derive(Clone)]
 pub struct ParTable {
     /// Mapping of a symbol bitmask to its identifier.
     pub symbol_num_mapping: HashMap<Symbol, String>,
@@ -84,9 +94,9 @@ pub struct ParTable {
     pub max_bet: u64,
 }
 
-impl ParTable {
+impl Default for ParTable {
     /// Initializes the par table with empty fields.
-    pub fn default() -> ParTable {
+    fn default() -> ParTable {
         ParTable {
             symbol_num_mapping: HashMap::<Symbol, String>::new(),
             symbol_str_mapping: HashMap::<String, Symbol>::new(),
@@ -96,17 +106,18 @@ impl ParTable {
             max_bet: 1,
         }
     }
+}
 
+impl ParTable {
     fn combo_from_string(&self, s: String, delimiter: char) -> Result<Combo> {
         Ok(s.split(delimiter)
             .map(|k| {
-                *self
-                    .symbol_str_mapping
+                self.symbol_str_mapping
                     .get(k)
+                    .copied()
                     .ok_or(ParTableParseError::SymbolNotFoundError)
-                    .unwrap()
             })
-            .collect())
+            .collect::<Result<_, _>>()?)
     }
 
     fn parse_symbols(&mut self, file: &str) -> Result<()> {
@@ -124,16 +135,9 @@ impl ParTable {
             self.symbol_num_mapping.insert(key, symbol.clone());
             self.symbol_str_mapping.insert(symbol.clone(), key);
             if reference.contains('|') {
-                self.combo_symbols.insert(
-                    key,
-                    reference.split('|').fold(0u64, |acc, k| {
-                        acc | *self
-                            .symbol_str_mapping
-                            .get(k)
-                            .ok_or(ParTableParseError::SymbolNotFoundError)
-                            .unwrap()
-                    }),
-                );
+                let reference = self.combo_from_string(reference, '|')?;
+                self.combo_symbols
+                    .insert(key, reference.iter().fold(0u64, |acc, k| acc | k));
             } else if reference.contains('!') {
                 self.combo_symbols.insert(
                     key,
@@ -162,7 +166,7 @@ impl ParTable {
             }
 
             self.paytable
-                .insert(self.combo_from_string(combo, ' ').unwrap(), pays);
+                .insert(self.combo_from_string(combo, ' ')?, pays);
         }
 
         Ok(())
@@ -175,8 +179,19 @@ impl ParTable {
         for result in rdr.deserialize() {
             let row: Vec<String> = result?;
 
-            self.reels
-                .push(self.combo_from_string(row.join(" "), ' ').unwrap());
+            let row = self.combo_from_string(row.join(" "), ' ')?;
+            if self
+                .reels
+                .first()
+                .is_some_and(|first| first.len() != row.len())
+            {
+                return Err(ParTableParseError::InvalidReelsError.into());
+            }
+            self.reels.push(row);
+        }
+
+        if self.reels.is_empty() || self.reels[0].is_empty() {
+            return Err(ParTableParseError::InvalidReelsError.into());
         }
 
         Ok(())
@@ -189,24 +204,200 @@ impl ParTable {
         self.parse_reels(files.reels_file.as_str())
     }
 
+    /// Returns a copy of the par table with the reels of another CSV file, e.g. the reel set of a
+    /// bonus feature.
+    pub fn with_reels(&self, file: &str) -> Result<ParTable> {
+        let mut table = self.clone();
+        table.reels.clear();
+        table.parse_reels(file)?;
+
+        Ok(table)
+    }
+
     /// Tries to match the given spin result with a winning combo from the pay table and returns
     /// the corresponding payout amount (depending on the size of the bet). If it doesn't match,
-    /// the spin is a loss.
+    /// or if the bet is out of range for the winning combo, the spin is a loss.
     pub fn calculate_win(&self, spin: Combo, bet: usize) -> Option<(Combo, u64)> {
+        let win_combo = self.winning_combo(&spin, &self.combos_by_payout())?;
+
+        Some((win_combo.to_vec(), *self.paytable.get(win_combo)?.get(bet)?))
+    }
+
+    /// Combos of the paytable sorted from the highest paying to the lowest.
+    fn combos_by_payout(&self) -> Vec<&Combo> {
         let mut sorted_combos: Vec<&Combo> = self.paytable.keys().collect();
         sorted_combos.sort_by_key(|c| self.paytable.get(*c).unwrap());
+        sorted_combos.reverse();
+
+        sorted_combos
+    }
 
-        if let Some(win_combo) = sorted_combos.iter().rev().find(|c| {
+    /// Returns the first combo of `sorted_combos` matched by the spin.
+    fn winning_combo<'a>(&self, spin: &Combo, sorted_combos: &[&'a Combo]) -> Option<&'a Combo> {
+        sorted_combos.iter().copied().find(|c| {
             c.iter()
                 .enumerate()
                 .all(|(i, x)| self.combo_symbols.get(x).unwrap() & spin[i] == spin[i])
-        }) {
-            Some((
-                win_combo.to_vec(),
-                *self.paytable.get(&**win_combo).unwrap().get(bet).unwrap(),
-            ))
-        } else {
-            None
+        })
+    }
+
+    /// Parses a combo written as whitespace separated symbol identifiers (e.g. `"B7 B7 DJ"`).
+    pub fn parse_combo(&self, s: &str) -> Result<Combo> {
+        self.combo_from_string(s.split_whitespace().collect::<Vec<_>>().join(" "), ' ')
+    }
+
+    /// Returns a function matching a spin result with its winning combo of the paytable, whatever it
+    /// pays. Faster than [`ParTable::calculate_win`] when evaluating many spins.
+    pub fn combo_matcher<'a>(&'a self) -> impl Fn(&[Symbol]) -> Option<&'a Combo> + 'a {
+        // Symbols matched on each reel by the combos, looked up once for every spin
+        let masks: Vec<(Combo, &Combo)> = self
+            .combos_by_payout()
+            .into_iter()
+            .map(|c| (c.iter().map(|x| self.combo_symbols[x]).collect(), c))
+            .collect();
+
+        move |spin| {
+            masks
+                .iter()
+                .find(|(mask, _)| mask.iter().zip(spin).all(|(m, s)| m & s == *s))
+                .map(|(_, combo)| *combo)
+        }
+    }
+
+    /// Returns the distinct symbols displayed by each reel, with the probability of a stop of the
+    /// reel displaying them.
+    pub fn reel_symbols(&self) -> Vec<Vec<(Symbol, f64)>> {
+        let reels = self.reels.first().map_or(0, |r| r.len());
+        (0..reels)
+            .map(|reel| {
+                let mut symbols: Vec<(Symbol, f64)> = Vec::new();
+                for row in &self.reels {
+                    match symbols.iter_mut().find(|(s, _)| *s == row[reel]) {
+                        Some((_, p)) => *p += 1.0,
+                        None => symbols.push((row[reel], 1.0)),
+                    }
+                }
+                symbols
+                    .iter_mut()
+                    .for_each(|(_, p)| *p /= self.reels.len() as f64);
+                symbols
+            })
+            .collect()
+    }
+
+    /// Evaluates every combination of reel stops, calling `f` with the winning combo of each
+    /// combination that matches one and the probability of the combination. The stops displaying
+    /// the same symbol are evaluated once.
+    fn for_each_winning_combo(&self, mut f: impl FnMut(&Combo, f64)) {
+        let matcher = self.combo_matcher();
+        for_each_combination(&self.reel_symbols(), |spin, p| {
+            if let Some(combo) = matcher(spin) {
+                f(combo, p);
+            }
+        });
+    }
+
+    /// Computes the [`ParStats`] of each bet size (index of the payouts vector) by evaluating every
+    /// combination of reel stops, each bet costing one more coin than its index.
+    pub fn par_stats(&self) -> Vec<ParStats> {
+        let mut wins = vec![0.0; self.max_bet as usize];
+        let mut hits = vec![0.0; self.max_bet as usize];
+
+        // The winning combo doesn't depend on the bet, only its payout does
+        self.for_each_winning_combo(|combo, p| {
+            for (bet, win) in self.paytable[combo].iter().enumerate() {
+                wins[bet] += *win as f64 * p;
+                hits[bet] += (*win > 0) as u8 as f64 * p;
+            }
+        });
+
+        wins.iter()
+            .zip(hits)
+            .enumerate()
+            .map(|(bet, (win, hit))| ParStats {
+                rtp: win / (bet + 1) as f64,
+                hit_frequency: hit,
+            })
+            .collect()
+    }
+
+    /// Share of the combinations of reel stops whose winning combo is `combo`, whatever it pays.
+    pub fn combo_frequency(&self, combo: &Combo) -> f64 {
+        let mut hits = 0.0;
+        self.for_each_winning_combo(|c, p| {
+            if c == combo {
+                hits += p
+            }
+        });
+
+        hits
+    }
+
+    /// Share of the combinations of reel stops displaying `symbol` on at least `count` reels.
+    pub fn scatter_frequency(&self, symbol: Symbol, count: usize) -> f64 {
+        let reels = self.reels.first().map_or(0, |r| r.len());
+        // Probability of each number of reels displaying the symbol, adding one reel at a time
+        let mut counts = vec![1.0];
+        for reel in 0..reels {
+            let p = self.reels.iter().filter(|row| row[reel] == symbol).count() as f64
+                / self.reels.len() as f64;
+            let mut next = vec![0.0; counts.len() + 1];
+            counts.iter().enumerate().for_each(|(n, q)| {
+                next[n] += q * (1.0 - p);
+                next[n + 1] += q * p;
+            });
+            counts = next;
+        }
+
+        counts.iter().skip(count).sum()
+    }
+
+    /// Returns the symbols displayed by the given reel stops (one row index per reel).
+    pub fn symbols_at(&self, stops: &[usize]) -> Combo {
+        stops
+            .iter()
+            .enumerate()
+            .map(|(reel, stop)| self.reels[*stop][reel])
+            .collect()
+    }
+
+    /// Returns the symbols displayed by the given reel stops and the amount won for the bet.
+    pub fn spin(&self, stops: &[usize], bet: usize) -> (Combo, u64) {
+        let spin = self.symbols_at(stops);
+        let win = self
+            .calculate_win(spin.clone(), bet)
+            .map_or(0, |(_, win)| win);
+
+        (spin, win)
+    }
+}
+
+/// Calls `f` with every combination of one symbol per reel, given the symbols of each reel with
+/// their probability, along with the probability of the combination.
+pub fn for_each_combination(reels: &[Vec<(Symbol, f64)>], mut f: impl FnMut(&[Symbol], f64)) {
+    if reels.iter().any(|symbols| symbols.is_empty()) {
+        return;
+    }
+    // Index of the symbol of each reel, incremented like the digits of a number
+    let mut indices = vec![0; reels.len()];
+    let mut spin: Vec<Symbol> = reels.iter().map(|symbols| symbols[0].0).collect();
+
+    loop {
+        let p = reels
+            .iter()
+            .zip(&indices)
+            .map(|(symbols, i)| symbols[*i].1)
+            .product();
+        f(&spin, p);
+
+        let Some(reel) = (0..reels.len()).find(|r| indices[*r] + 1 < reels[*r].len()) else {
+            return;
+        };
+        indices[reel] += 1;
+        spin[reel] = reels[reel][indices[reel]].0;
+        for r in 0..reel {
+            indices[r] = 0;
+            spin[r] = reels[r][0].0;
         }
     }
 }
@@ -236,7 +427,7 @@ impl Display for ParTable {
         write!(f, "{:-<18} {:-<3}", "", "")?;
 
         (1..=sorted_paytable[0].1.len()).for_each(|_| write!(f, "{:-<12}", "").unwrap());
-        writeln!(f, "").unwrap();
+        writeln!(f).unwrap();
 
         sorted_paytable.sort_by(|a, b| a.1.cmp(b.1).then(a.0.cmp(b.0)));
         sorted_paytable.iter().for_each(|(combo, pay)| {
@@ -251,31 +442,31 @@ impl Display for ParTable {
             .expect("Cannot format ParTable");
             pay.iter()
                 .for_each(|p| write!(f, " {:<12}", p).expect("Cannot format ParTable"));
-            writeln!(f, "").unwrap();
+            writeln!(f).unwrap();
         });
 
         writeln!(f, "{:=<51}", "").unwrap();
 
         (1..=self.reels[0].len()).for_each(|x| write!(f, "Reel {:<5} ", x).unwrap());
-        writeln!(f, "").unwrap();
+        writeln!(f).unwrap();
         (1..=self.reels[0].len()).for_each(|_| write!(f, "{:-<6}{:<5}", "", "").unwrap());
-        writeln!(f, "").unwrap();
+        writeln!(f).unwrap();
 
         self.reels.iter().for_each(|r| {
             r.iter().for_each(|n| {
                 write!(f, "{:^6}{:<5}", self.symbol_num_mapping.get(n).unwrap(), "").unwrap();
             });
-            writeln!(f, "").unwrap();
+            writeln!(f).unwrap();
         });
 
         writeln!(f, "{:=<51}", "").unwrap();
 
         write!(f, "Symbol ").unwrap();
         (1..=self.reels[0].len()).for_each(|x| write!(f, "Count(Reel {:1}) ", x).unwrap());
-        writeln!(f, "").unwrap();
+        writeln!(f).unwrap();
         write!(f, "{:-<6} ", "").unwrap();
         (1..=self.reels[0].len()).for_each(|_| write!(f, "{:-<13}{:<1}", "", "").unwrap());
-        writeln!(f, "").unwrap();
+        writeln!(f).unwrap();
 
         let counts: HashMap<u64, Vec<u64>> =
             HashMap::from_iter(sorted_symbols.iter().map(|(symbol, _)| {
@@ -298,7 +489,7 @@ impl Display for ParTable {
             self.reels[0].iter().enumerate().for_each(|(i, _)| {
                 write!(f, "{:^13}{:<1}", counts.get(s).unwrap()[i], "").unwrap();
             });
-            writeln!(f, "").unwrap();
+            writeln!(f).unwrap();
         });
 
         Ok(())
@@ -315,6 +506,8 @@ pub enum ParTableParseError {
     TooMuchSymbolsError,
     /// Raised when a given identifier is not corresponding to any symbol in the `symbols_str_mapping`.
     SymbolNotFoundError,
+    /// Raised when the reels have no stops or a different number of symbols on each row.
+    InvalidReelsError,
 }
 
 impl Error for ParTableParseError {}
@@ -326,6 +519,9 @@ impl fmt::Display for ParTableParseError {
                 write!(f, "Too much symbols in table (max 64)")
             }
             ParTableParseError::SymbolNotFoundError => write!(f, "Symbol not found for pattern"),
+            ParTableParseError::InvalidReelsError => {
+                write!(f, "Reels are empty or of different sizes")
+            }
         }
     }
 }
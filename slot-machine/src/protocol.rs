@@ -2,13 +2,62 @@
 //!
 //! Leveraging `serde`, the messages are serialized to JSON before being sent over the socket connection.
 
-use std::time::{Duration, SystemTime};
+use std::{
+    error::Error,
+    fmt,
+    time::{Duration, SystemTime},
+};
 
 use serde::{Deserialize, Serialize};
 
+use crate::accounts::LedgerEntry;
+use crate::fairness::{SeedPair, SpinProof, MAX_CLIENT_SEED_LENGTH};
+use crate::game::{GambleGuess, GameDefinition, GameInfo, RespinStep};
+use crate::limits::{LimitsStatus, PlayerLimits};
+use crate::utils::format_duration;
+
+/// Maximum length of a player name.
+pub const MAX_PLAYER_NAME_LENGTH: usize = 32;
+/// Minimum length of an account password.
+pub const MIN_PASSWORD_LENGTH: usize = 8;
+/// Maximum number of entries sent in a statement.
+pub const MAX_STATEMENT_ENTRIES: usize = 500;
+
+/// Checks that a player name is non-empty, at most [`MAX_PLAYER_NAME_LENGTH`] characters and only
+/// made of ASCII letters, digits, `-` and `_`.
+pub fn is_valid_player_name(player: &str) -> bool {
+    !player.is_empty()
+        && player.len() <= MAX_PLAYER_NAME_LENGTH
+        && player
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
 /// The client commands that can be sent to the server.
 #[derive(Debug, Serialize, Deserialize)]
 pub enum ClientCommand {
+    /// Sent to create a new player account with the starting balance and log into it.
+    Register {
+        /// Player name (letters, digits, `-` and `_`, at most [`MAX_PLAYER_NAME_LENGTH`] characters).
+        player: String,
+        /// Account password, at least [`MIN_PASSWORD_LENGTH`] characters.
+        password: String,
+    },
+    /// Sent to log into an existing player account. Until the client is logged in, every other
+    /// command is rejected except for [`ClientCommand::Register`], [`ClientCommand::Resume`],
+    /// [`ClientCommand::Status`] and the events subscriptions.
+    Login {
+        /// Player name of the account.
+        player: String,
+        /// Account password.
+        password: String,
+    },
+    /// Sent to log back in with the session token of a previous [`ServerResponse::LoggedIn`],
+    /// e.g. after a reconnection.
+    Resume {
+        /// Session token given by the server.
+        token: String,
+    },
     /// Sent at the start of the game to retrieve the balance and maximum bet allowed for the game.
     Init {
         /// Game string identifier (subfolder name in `GAMES_FOLDER`).
@@ -16,12 +65,20 @@ pub enum ClientCommand {
     },
     /// Sent to ask the server for a random spin result. The bet information is needed to compute
     /// the eventual payout.
+    ///
+    /// While the player has free spins left on the game, the spin is a free spin played with the
+    /// bet of the spin that awarded it, whatever `bet` is given.
     Play {
         /// Game string identifier (subfolder name in `GAMES_FOLDER`).
         game: String,
         /// Bet size. It corresponds to the index of the payouts vector stored in the par table.
         /// So the interface actually displays this value + 1.
         bet: usize,
+        /// Nonce of the spin in the provably fair mode (see [`ClientCommand::FairPlay`]), ignored
+        /// otherwise. It must be greater than the last nonce used with the current server seed,
+        /// which is incremented if none is given.
+        #[serde(default)]
+        nonce: Option<u64>,
     },
     /// Sent to retrieve the status of the server. The `clock` is used by the server to compute the
     /// latency of the client.
@@ -29,10 +86,182 @@ pub enum ClientCommand {
         /// Timestamp of the client system time at the time of the request.
         clock: SystemTime,
     },
+    /// Sent to retrieve the catalogue of games available on the server.
+    ListGames,
+    /// Sent to retrieve the display-level definition of a game (reels, display symbols, metadata).
+    GetGame {
+        /// Game string identifier (subfolder name in `GAMES_FOLDER`).
+        game: String,
+    },
+    /// Sent to start receiving the given kinds of events pushed by the server.
+    Subscribe {
+        /// Kinds of events to receive, in addition to the current subscriptions.
+        events: Vec<EventKind>,
+    },
+    /// Sent to stop receiving the given kinds of events pushed by the server.
+    Unsubscribe {
+        /// Kinds of events to stop receiving.
+        events: Vec<EventKind>,
+    },
+    /// Sent to retrieve the most recent transactions of the player from the ledger.
+    Statement {
+        /// Only include the transactions of this game identifier.
+        game: Option<String>,
+        /// Maximum number of transactions, capped to [`MAX_STATEMENT_ENTRIES`].
+        limit: usize,
+    },
+    /// Sent to enable the provably fair mode or rotate its seeds (see the
+    /// [`fairness`](crate::fairness) module). The current server seed, if any, is revealed and a
+    /// new one is committed to for the following spins.
+    FairPlay {
+        /// Client seed to use with the new server seed, or `None` to disable the mode.
+        client_seed: Option<String>,
+    },
+    /// Sent to retrieve the responsible gaming limits of the player (see the
+    /// [`limits`](crate::limits) module).
+    Limits,
+    /// Sent to change the responsible gaming limits of the player. Stricter limits apply at once,
+    /// looser ones after a delay.
+    SetLimits {
+        /// The new limits, replacing all the current ones.
+        limits: PlayerLimits,
+    },
+    /// Sent to stop playing for a cool-off or self-exclusion period. It can be extended but not
+    /// shortened.
+    SelfExclude {
+        /// Length of the period starting now, at most
+        /// [`MAX_SELF_EXCLUSION`](crate::limits::MAX_SELF_EXCLUSION).
+        duration: Duration,
+    },
+    /// Sent to gamble the win of the last spin, or of the last gamble won, on a double-or-nothing
+    /// round (see [`Gamble`](crate::game::Gamble)). Spinning again collects the win instead.
+    Gamble {
+        /// Game string identifier (subfolder name in `GAMES_FOLDER`).
+        game: String,
+        /// Card colour or coin face guessed, depending on the gamble of the game.
+        guess: GambleGuess,
+    },
 }
 
-/// The states of the client / server connection.
+impl ClientCommand {
+    /// Names of every command, as returned by [`ClientCommand::name`].
+    pub const NAMES: [&'static str; 16] = [
+        "Register",
+        "Login",
+        "Resume",
+        "Init",
+        "Play",
+        "Status",
+        "ListGames",
+        "GetGame",
+        "Subscribe",
+        "Unsubscribe",
+        "Statement",
+        "FairPlay",
+        "Limits",
+        "SetLimits",
+        "SelfExclude",
+        "Gamble",
+    ];
+
+    /// Returns the name of the command, without its fields (e.g. to label metrics).
+    pub fn name(&self) -> &'static str {
+        match self {
+            ClientCommand::Register { .. } => "Register",
+            ClientCommand::Login { .. } => "Login",
+            ClientCommand::Resume { .. } => "Resume",
+            ClientCommand::Init { .. } => "Init",
+            ClientCommand::Play { .. } => "Play",
+            ClientCommand::Status { .. } => "Status",
+            ClientCommand::ListGames => "ListGames",
+            ClientCommand::GetGame { .. } => "GetGame",
+            ClientCommand::Subscribe { .. } => "Subscribe",
+            ClientCommand::Unsubscribe { .. } => "Unsubscribe",
+            ClientCommand::Statement { .. } => "Statement",
+            ClientCommand::FairPlay { .. } => "FairPlay",
+            ClientCommand::Limits => "Limits",
+            ClientCommand::SetLimits { .. } => "SetLimits",
+            ClientCommand::SelfExclude { .. } => "SelfExclude",
+            ClientCommand::Gamble { .. } => "Gamble",
+        }
+    }
+}
+
+/// Kinds of events that a client can subscribe to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum EventKind {
+    /// See [`ServerEvent::BalanceChanged`].
+    Balance,
+    /// See [`ServerEvent::JackpotChanged`].
+    Jackpot,
+    /// See [`ServerEvent::GamesChanged`].
+    Games,
+    /// See [`ServerEvent::Shutdown`].
+    Shutdown,
+    /// See [`ServerEvent::Heartbeat`].
+    Heartbeat,
+    /// See [`ServerEvent::RealityCheck`].
+    RealityCheck,
+}
+
+/// Events pushed by the server to the subscribed clients, without being requested.
 #[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ServerEvent {
+    /// The client balance was changed by another session.
+    BalanceChanged {
+        /// The new balance.
+        balance: u64,
+    },
+    /// The value of a progressive jackpot changed.
+    JackpotChanged {
+        /// Game string identifier (subfolder name in `GAMES_FOLDER`).
+        game: String,
+        /// Current value of the jackpot.
+        amount: u64,
+    },
+    /// The games available on the server were reloaded.
+    GamesChanged {
+        /// The new games catalogue.
+        games: Vec<GameInfo>,
+    },
+    /// The server is shutting down and will close the connection.
+    Shutdown {
+        /// Reason for the shutdown.
+        message: String,
+    },
+    /// Sent periodically to let the client know the connection is alive.
+    Heartbeat {
+        /// Timestamp of the server system time at the time of the event.
+        clock: SystemTime,
+    },
+    /// Sent at the reality check interval of the player limits, as a reminder of the time and
+    /// money spent since the play session started (see the [`limits`](crate::limits) module).
+    RealityCheck {
+        /// Time since the play session started.
+        elapsed: Duration,
+        /// Coins wagered since the play session started.
+        wagered: u64,
+        /// Coins won since the play session started.
+        won: u64,
+    },
+}
+
+impl ServerEvent {
+    /// Returns the kind of the event, used to match the client subscriptions.
+    pub fn kind(&self) -> EventKind {
+        match self {
+            ServerEvent::BalanceChanged { .. } => EventKind::Balance,
+            ServerEvent::JackpotChanged { .. } => EventKind::Jackpot,
+            ServerEvent::GamesChanged { .. } => EventKind::Games,
+            ServerEvent::Shutdown { .. } => EventKind::Shutdown,
+            ServerEvent::Heartbeat { .. } => EventKind::Heartbeat,
+            ServerEvent::RealityCheck { .. } => EventKind::RealityCheck,
+        }
+    }
+}
+
+/// The states of the client / server connection.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum ServerStatus {
     /// Server is not running.
     Stopped,
@@ -63,33 +292,308 @@ impl Default for Status {
     }
 }
 
-/// The server responses that will be sent to the client. 
+/// The server responses that will be sent to the client.
 #[derive(Debug, Serialize, Deserialize)]
 pub enum ServerResponse {
+    /// In response to the client registering, logging in or resuming a session.
+    LoggedIn {
+        /// Player name of the account.
+        player: String,
+        /// Player balance stored on the account.
+        balance: u64,
+        /// Session token that can be used with [`ClientCommand::Resume`] until it expires.
+        token: String,
+        /// Expiration time of the session, after which the client must log in again.
+        expires_at: SystemTime,
+    },
     /// In response to the client starting a new game.
     Init {
-        /// Client balance. It is shared across all games that the client plays. Resets on every connection.
+        /// Player balance. It is shared across all games and sessions of the player account.
         balance: u64,
         /// Maximum bet that the requested game allows.
         max_bet: u64,
+        /// Current value of the progressive jackpot of the game, if it has one. It is then kept up
+        /// to date by [`ServerEvent::JackpotChanged`].
+        #[serde(default)]
+        jackpot: Option<u64>,
+        /// Free spins left to the player on the game, e.g. after a reconnection.
+        #[serde(default)]
+        free_spins: u32,
     },
-    /// In response to the client requesting a spin. 
+    /// In response to the client requesting a spin.
     Spin {
-        /// The amount won on the spin.
+        /// The amount won on the spin, including the progressive jackpot.
         win: u64,
+        /// The part of `win` paid by the progressive jackpot of the game.
+        #[serde(default)]
+        jackpot: u64,
+        /// Whether the spin was a free spin, drawn from the free spins reels if the game has them.
+        #[serde(default)]
+        free_spin: bool,
+        /// Free spins left to the player on the game, including the ones won with the spin.
+        #[serde(default)]
+        free_spins: u32,
         /// The new balance adjusted for the cost of the bet and win amount.
         balance: u64,
         /// The spin result as a vector of reels position. Hence, the size of the vector is equal
         /// to the number of reels of the game.
         result: Vec<usize>,
+        /// Respins triggered by the result, in the order they are played. The last one gives the
+        /// result paid by `win`.
+        #[serde(default)]
+        respins: Vec<RespinStep>,
+        /// Number of gambles the win can be risked on with [`ClientCommand::Gamble`], 0 if the game
+        /// has no gamble, for free spins and for losing spins.
+        #[serde(default)]
+        gambles: u32,
+        /// Inputs of the spin if it was made in the provably fair mode.
+        proof: Option<SpinProof>,
+    },
+    /// In response to the client gambling a win.
+    Gamble {
+        /// Card colour or coin face drawn, the gamble is won if it is the one guessed.
+        outcome: GambleGuess,
+        /// The amount won: twice the amount gambled if won, 0 if lost.
+        win: u64,
+        /// Number of gambles the new win can be risked on.
+        gambles: u32,
+        /// The new balance adjusted for the gamble.
+        balance: u64,
     },
     /// Sent when an invalid request is received or when a request could not be fulfilled.
     Error {
-        /// Error code identifier.
+        /// Error code identifier (see [`CommandError::code`]).
         code: u64,
         /// Error message.
         message: String,
     },
     /// In response to the client requesting the server status.
     Status(Status),
+    /// In response to the client requesting the games catalogue.
+    Games(Vec<GameInfo>),
+    /// In response to the client requesting a game definition.
+    Game(GameDefinition),
+    /// In response to the client changing its subscriptions.
+    Subscribed {
+        /// Kinds of events the client is now subscribed to.
+        events: Vec<EventKind>,
+    },
+    /// Pushed by the server for the kinds of events the client subscribed to.
+    Event(ServerEvent),
+    /// In response to the client requesting its statement, most recent transaction first.
+    Statement(Vec<LedgerEntry>),
+    /// In response to the client changing its provably fair seeds.
+    FairPlay {
+        /// Commitment (SHA-256 hash) of the new server seed, `None` if the mode was disabled.
+        commitment: Option<String>,
+        /// The previous seeds, to verify the spins made with them.
+        revealed: Option<SeedPair>,
+    },
+    /// In response to the client requesting or changing its responsible gaming limits.
+    Limits(LimitsStatus),
+}
+
+/// Reasons for the server to reject a client command.
+///
+/// Commands are fully validated against the session state before being executed, so a rejected
+/// command never alters the balance. Each variant maps to a stable error code that is sent back to
+/// the client in a [`ServerResponse::Error`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CommandError {
+    /// The balance doesn't cover the cost of the requested bet.
+    InsufficientBalance {
+        /// Current balance of the client.
+        balance: u64,
+        /// Cost of the requested bet.
+        cost: u64,
+    },
+    /// The requested game is not loaded by the server.
+    UnknownGame(String),
+    /// The bet index is out of range for the requested game.
+    InvalidBet {
+        /// Requested bet index.
+        bet: usize,
+        /// Maximum bet that the requested game allows.
+        max_bet: u64,
+    },
+    /// A spin was requested for a game that hasn't been initialized with [`ClientCommand::Init`].
+    GameNotInitialized(String),
+    /// The client clock is ahead of the server's, so the latency can't be computed.
+    InvalidClock,
+    /// The message could not be parsed as a [`ClientCommand`].
+    MalformedCommand,
+    /// The command requires the client to be logged in (see [`ClientCommand::Login`]).
+    NotAuthenticated,
+    /// The player name doesn't follow the naming rules of [`ClientCommand::Register`].
+    InvalidPlayerName(String),
+    /// The server failed to process the command (e.g. the accounts storage is unavailable).
+    Internal,
+    /// The player name or password is wrong, or the session token is unknown.
+    InvalidCredentials,
+    /// An account already exists with the requested player name.
+    PlayerExists(String),
+    /// The session token expired, the client must log in again.
+    SessionExpired,
+    /// The password is shorter than [`MIN_PASSWORD_LENGTH`].
+    PasswordTooShort,
+    /// The client seed is empty, longer than [`MAX_CLIENT_SEED_LENGTH`] or has characters other
+    /// than ASCII letters, digits, `-` and `_`.
+    InvalidClientSeed(String),
+    /// The nonce of a provably fair spin is not greater than the last one used with the server seed.
+    InvalidNonce(u64),
+    /// The client sent too many commands of this type, it can try again after `retry_after`.
+    RateLimited {
+        /// Time until the command is accepted again.
+        retry_after: Duration,
+    },
+    /// The spin could make the player lose more than their daily loss limit.
+    DailyLossLimitReached(u64),
+    /// The spin could make the player lose more than their session loss limit.
+    SessionLossLimitReached(u64),
+    /// The cost of the spin is above the wager limit of the player.
+    WagerAboveLimit {
+        /// Cost of the requested bet.
+        cost: u64,
+        /// Maximum cost of a spin set by the player.
+        max_wager: u64,
+    },
+    /// The player has been playing for longer than their session time limit.
+    SessionTimeLimitReached(Duration),
+    /// The player excluded themselves from playing until the given time.
+    SelfExcluded(SystemTime),
+    /// The requested limits or self-exclusion period are invalid (e.g. a reality check interval
+    /// shorter than [`MIN_REALITY_CHECK_INTERVAL`](crate::limits::MIN_REALITY_CHECK_INTERVAL)).
+    InvalidLimits(String),
+    /// There is no win to gamble on the requested game: the game has no gamble, the last spin
+    /// didn't win, was a free spin, or its win can't be gambled again.
+    NothingToGamble(String),
+    /// The guess doesn't fit the gamble of the game (e.g. a coin face for a card colour).
+    InvalidGuess(GambleGuess),
+}
+
+impl CommandError {
+    /// Returns the error code identifier sent to the client.
+    pub fn code(&self) -> u64 {
+        match self {
+            CommandError::InsufficientBalance { .. } => 1,
+            CommandError::UnknownGame(_) => 2,
+            CommandError::InvalidBet { .. } => 3,
+            CommandError::GameNotInitialized(_) => 4,
+            CommandError::InvalidClock => 5,
+            CommandError::MalformedCommand => 6,
+            CommandError::NotAuthenticated => 7,
+            CommandError::InvalidPlayerName(_) => 8,
+            CommandError::Internal => 9,
+            CommandError::InvalidCredentials => 10,
+            CommandError::PlayerExists(_) => 11,
+            CommandError::SessionExpired => 12,
+            CommandError::PasswordTooShort => 13,
+            CommandError::InvalidClientSeed(_) => 14,
+            CommandError::InvalidNonce(_) => 15,
+            CommandError::RateLimited { .. } => 16,
+            CommandError::DailyLossLimitReached(_) => 17,
+            CommandError::SessionLossLimitReached(_) => 18,
+            CommandError::WagerAboveLimit { .. } => 19,
+            CommandError::SessionTimeLimitReached(_) => 20,
+            CommandError::SelfExcluded(_) => 21,
+            CommandError::InvalidLimits(_) => 22,
+            CommandError::NothingToGamble(_) => 23,
+            CommandError::InvalidGuess(_) => 24,
+        }
+    }
+}
+
+impl Error for CommandError {}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CommandError::InsufficientBalance { balance, cost } => write!(
+                f,
+                "Insufficent balance ({} for a bet of {}), thank you for playing !",
+                balance, cost
+            ),
+            CommandError::UnknownGame(game) => write!(f, "Unknown game \"{}\"", game),
+            CommandError::InvalidBet { bet, max_bet } => write!(
+                f,
+                "Invalid bet size {} (must be between 1 and {})",
+                bet.saturating_add(1),
+                max_bet
+            ),
+            CommandError::GameNotInitialized(game) => {
+                write!(f, "Game \"{}\" must be initialized before playing", game)
+            }
+            CommandError::InvalidClock => write!(f, "Client clock is ahead of the server"),
+            CommandError::MalformedCommand => write!(f, "Unrecognized client command"),
+            CommandError::NotAuthenticated => write!(f, "Player must be logged in"),
+            CommandError::InvalidPlayerName(player) => {
+                write!(f, "Invalid player name \"{}\"", player)
+            }
+            CommandError::Internal => write!(f, "Internal server error"),
+            CommandError::InvalidCredentials => write!(f, "Invalid player name or password"),
+            CommandError::PlayerExists(player) => {
+                write!(f, "Player \"{}\" is already registered", player)
+            }
+            CommandError::SessionExpired => write!(f, "Session expired, please log in again"),
+            CommandError::PasswordTooShort => write!(
+                f,
+                "Password must be at least {} characters long",
+                MIN_PASSWORD_LENGTH
+            ),
+            CommandError::InvalidClientSeed(client_seed) => write!(
+                f,
+                "Invalid client seed \"{}\" (at most {} letters, digits, '-' or '_')",
+                client_seed, MAX_CLIENT_SEED_LENGTH
+            ),
+            CommandError::InvalidNonce(last_nonce) => write!(
+                f,
+                "Nonce must be greater than the last one used ({})",
+                last_nonce
+            ),
+            CommandError::RateLimited { retry_after } => write!(
+                f,
+                "Too many requests, try again in {:.1}s",
+                (retry_after.as_secs_f64() * 10.0).ceil() / 10.0
+            ),
+            CommandError::DailyLossLimitReached(limit) => write!(
+                f,
+                "Daily loss limit of {} reached, take a break and come back tomorrow !",
+                limit
+            ),
+            CommandError::SessionLossLimitReached(limit) => {
+                write!(f, "Session loss limit of {} reached, take a break !", limit)
+            }
+            CommandError::WagerAboveLimit { cost, max_wager } => write!(
+                f,
+                "Bet of {} is above your wager limit of {}",
+                cost, max_wager
+            ),
+            CommandError::SessionTimeLimitReached(limit) => write!(
+                f,
+                "Session time limit of {} reached, take a break !",
+                format_duration(*limit)
+            ),
+            CommandError::SelfExcluded(until) => write!(
+                f,
+                "You excluded yourself from playing for another {}",
+                format_duration(until.duration_since(SystemTime::now()).unwrap_or_default())
+            ),
+            CommandError::InvalidLimits(reason) => write!(f, "Invalid limits: {}", reason),
+            CommandError::NothingToGamble(game) => {
+                write!(f, "No win to gamble on \"{}\"", game)
+            }
+            CommandError::InvalidGuess(guess) => {
+                write!(f, "Cannot guess {} on this gamble", guess)
+            }
+        }
+    }
+}
+
+impl From<CommandError> for ServerResponse {
+    fn from(err: CommandError) -> Self {
+        ServerResponse::Error {
+            code: err.code(),
+            message: err.to_string(),
+        }
+    }
 }
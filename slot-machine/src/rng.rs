@@ -0,0 +1,245 @@
+//! Random number generators drawing the reel stops of the spins.
+//!
+//! The generator is chosen with a [`RngSpec`] written as `<kind>[:<seed>]` (e.g. `chacha20`,
+//! `pcg64:42`). Seeded generators always draw the same sequence of spins, which makes the results
+//! reproducible for tests and debugging, while unseeded ones draw their seed from the OS random
+//! source. That seed is kept (see [`SlotRng::seed`]) so that the spins of the audit log can be
+//! reproduced as well.
+
+use std::fmt;
+use std::str::FromStr;
+
+use rand::distributions::{Distribution, Uniform};
+use rand::rngs::OsRng;
+use rand::{RngCore, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+use rand_pcg::Pcg64;
+use serde_with::{DeserializeFromStr, SerializeDisplay};
+
+/// Kinds of random number generators available for the spins.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RngKind {
+    /// ChaCha stream cipher with 20 rounds, cryptographically secure.
+    #[default]
+    ChaCha20,
+    /// Permuted congruential generator (PCG XSL RR 128/64), fast but predictable.
+    Pcg64,
+    /// Operating system random source, which can't be seeded.
+    Os,
+}
+
+impl fmt::Display for RngKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad(match self {
+            RngKind::ChaCha20 => "chacha20",
+            RngKind::Pcg64 => "pcg64",
+            RngKind::Os => "os",
+        })
+    }
+}
+
+impl FromStr for RngKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "chacha20" => Ok(RngKind::ChaCha20),
+            "pcg64" => Ok(RngKind::Pcg64),
+            "os" => Ok(RngKind::Os),
+            _ => Err(format!(
+                "Unknown generator \"{}\" (expected chacha20, pcg64 or os)",
+                s
+            )),
+        }
+    }
+}
+
+/// Choice of a generator and its optional seed, written as `<kind>[:<seed>]`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, SerializeDisplay, DeserializeFromStr)]
+pub struct RngSpec {
+    /// Kind of generator.
+    pub kind: RngKind,
+    /// Seed of the generator, drawn from the OS random source if `None`.
+    pub seed: Option<u64>,
+}
+
+impl fmt::Display for RngSpec {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.seed {
+            Some(seed) => write!(f, "{}:{}", self.kind, seed),
+            None => write!(f, "{}", self.kind),
+        }
+    }
+}
+
+impl FromStr for RngSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (kind, seed) = match s.split_once(':') {
+            Some((kind, seed)) => (
+                kind.parse()?,
+                Some(
+                    seed.parse()
+                        .map_err(|_| format!("Invalid generator seed \"{}\"", seed))?,
+                ),
+            ),
+            None => (s.parse()?, None),
+        };
+
+        if kind == RngKind::Os && seed.is_some() {
+            return Err("The OS generator can't be seeded".to_string());
+        }
+
+        Ok(RngSpec { kind, seed })
+    }
+}
+
+/// A generator built from a [`RngSpec`], drawing the reel stops of a game.
+pub struct SlotRng {
+    spec: RngSpec,
+    seed: Option<u64>,
+    draws: u64,
+    rng: Box<dyn RngCore + Send>,
+}
+
+impl SlotRng {
+    /// Builds the generator described by the spec.
+    pub fn new(spec: RngSpec) -> Self {
+        let seed = match spec.kind {
+            RngKind::Os => None,
+            _ => Some(spec.seed.unwrap_or_else(|| OsRng.next_u64())),
+        };
+        let rng: Box<dyn RngCore + Send> = match (spec.kind, seed) {
+            (RngKind::ChaCha20, Some(seed)) => Box::new(ChaCha20Rng::seed_from_u64(seed)),
+            (RngKind::Pcg64, Some(seed)) => Box::new(Pcg64::seed_from_u64(seed)),
+            _ => Box::new(OsRng),
+        };
+
+        Self {
+            spec,
+            seed,
+            draws: 0,
+            rng,
+        }
+    }
+
+    /// Returns the spec the generator was built from.
+    pub fn spec(&self) -> RngSpec {
+        self.spec
+    }
+
+    /// Returns the seed of the generator, including the one drawn for an unseeded spec. The OS
+    /// generator has none.
+    pub fn seed(&self) -> Option<u64> {
+        self.seed
+    }
+
+    /// Returns the number of spins drawn with [`SlotRng::draw_stops`] so far.
+    pub fn draws(&self) -> u64 {
+        self.draws
+    }
+
+    /// Draws one stop (row index) uniformly for each of the `reels`, out of `stops` rows.
+    pub fn draw_stops(&mut self, reels: usize, stops: usize) -> Vec<usize> {
+        let distribution = Uniform::from(0..stops);
+        self.draws += 1;
+
+        (0..reels)
+            .map(|_| distribution.sample(&mut self.rng))
+            .collect()
+    }
+}
+
+impl Default for SlotRng {
+    fn default() -> Self {
+        Self::new(RngSpec::default())
+    }
+}
+
+impl RngCore for SlotRng {
+    fn next_u32(&mut self) -> u32 {
+        self.rng.next_u32()
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.rng.next_u64()
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.rng.fill_bytes(dest)
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.rng.try_fill_bytes(dest)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn seeded_generators_draw_the_same_stops() {
+        for kind in [RngKind::ChaCha20, RngKind::Pcg64] {
+            let spec = RngSpec {
+                kind,
+                seed: Some(42),
+            };
+            let mut first = SlotRng::new(spec);
+            let mut second = SlotRng::new(spec);
+
+            for _ in 0..100 {
+                assert_eq!(first.draw_stops(5, 64), second.draw_stops(5, 64));
+            }
+            assert_eq!(first.seed(), Some(42));
+            assert_eq!(first.draws(), 100);
+        }
+    }
+
+    #[test]
+    fn unseeded_generators_keep_their_seed() {
+        let mut unseeded = SlotRng::new("pcg64".parse().unwrap());
+        let mut seeded = SlotRng::new(RngSpec {
+            kind: RngKind::Pcg64,
+            seed: unseeded.seed(),
+        });
+
+        assert_eq!(unseeded.draw_stops(3, 22), seeded.draw_stops(3, 22));
+        assert_eq!(SlotRng::new("os".parse().unwrap()).seed(), None);
+    }
+
+    #[test]
+    fn specs_round_trip_through_strings() {
+        for spec in [
+            "chacha20",
+            "chacha20:7",
+            "pcg64",
+            "pcg64:18446744073709551615",
+            "os",
+        ] {
+            assert_eq!(spec.parse::<RngSpec>().unwrap().to_string(), spec);
+        }
+        assert_eq!(
+            "pcg64:42".parse::<RngSpec>(),
+            Ok(RngSpec {
+                kind: RngKind::Pcg64,
+                seed: Some(42)
+            })
+        );
+    }
+
+    #[test]
+    fn invalid_specs_are_rejected() {
+        for spec in [
+            "os:1",
+            "mt19937",
+            "chacha20:",
+            "chacha20:-1",
+            "pcg64:seed",
+            "",
+        ] {
+            assert!(spec.parse::<RngSpec>().is_err(), "{} was accepted", spec);
+        }
+    }
+}
@@ -0,0 +1,547 @@
+//! Transports used for the client / server connection.
+//!
+//! The server can listen on any number of addresses, each one given as a URL-style string:
+//! - `unix:///tmp/slot_machine.sock` for a UNIX socket (local connections only).
+//! - `tcp://0.0.0.0:7777` for plain TCP.
+//! - `tls://0.0.0.0:7778` for TCP wrapped in TLS.
+//! - `ws://0.0.0.0:7779` for WebSocket (e.g. for browser front-ends), where each text message holds
+//!   a single JSON command or response.
+//!
+//! For TLS, the server uses a self-signed certificate generated on first start (see
+//! [`load_or_generate_certificate`]). Clients trust this exact certificate only (pinning), so it
+//! must be copied to every client machine. The certificate path can be given to the client with
+//! the `cert` query parameter of the address (e.g. `tls://192.168.1.10:7778?cert=./cert.pem`),
+//! otherwise `TLS_CERT_FILE` is used.
+//!
+//! [`Connection`] is the blocking client side of every transport, the daemon accepts the
+//! connections asynchronously.
+
+use std::{
+    fmt::{self, Display},
+    fs::{self, OpenOptions},
+    io::{self, Read, Write},
+    net::{Shutdown, TcpStream},
+    os::unix::{
+        fs::{OpenOptionsExt, PermissionsExt},
+        net::UnixStream,
+    },
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use tungstenite::{Message, WebSocket};
+
+use anyhow::{anyhow, bail, Context, Result};
+use rustls::{
+    client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+    crypto::{ring, verify_tls12_signature, verify_tls13_signature, CryptoProvider},
+    pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer, ServerName, UnixTime},
+    ClientConfig, ClientConnection, DigitallySignedStruct, SignatureScheme,
+};
+
+/// Default address for the client / server connection.
+pub const DEFAULT_ADDRESS: &str = "unix:///tmp/slot_machine.sock";
+/// Path of the TLS certificate used by the server and trusted by the client.
+pub const TLS_CERT_FILE: &str = "./data/tls/cert.pem";
+/// Default path of the TLS private key used by the server.
+pub const TLS_KEY_FILE: &str = "./data/tls/key.pem";
+
+/// Interval at which a blocked WebSocket read releases the connection for writing.
+const WEBSOCKET_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Address of the server for a given transport.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Address {
+    /// UNIX socket file path.
+    Unix(PathBuf),
+    /// Plain TCP `host:port`.
+    Tcp(String),
+    /// TLS over TCP `host:port`, along with the path of the certificate to trust.
+    Tls(String, PathBuf),
+    /// WebSocket `host:port`.
+    WebSocket(String),
+}
+
+impl FromStr for Address {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (scheme, rest) = s
+            .split_once("://")
+            .ok_or_else(|| anyhow!("Missing scheme in address \"{}\"", s))?;
+
+        match scheme {
+            "unix" if !rest.is_empty() => Ok(Address::Unix(PathBuf::from(rest))),
+            "tcp" if !rest.is_empty() => Ok(Address::Tcp(rest.to_string())),
+            "tls" => {
+                let (host, query) = rest.split_once('?').unwrap_or((rest, ""));
+                let cert = query
+                    .split('&')
+                    .find_map(|param| param.strip_prefix("cert="))
+                    .unwrap_or(TLS_CERT_FILE);
+
+                if host.is_empty() {
+                    return Err(anyhow!("Missing host in address \"{}\"", s));
+                }
+
+                Ok(Address::Tls(host.to_string(), PathBuf::from(cert)))
+            }
+            "ws" if !rest.is_empty() => Ok(Address::WebSocket(
+                rest.split_once('/')
+                    .map_or(rest, |(host, _)| host)
+                    .to_string(),
+            )),
+            _ => Err(anyhow!("Unsupported address \"{}\"", s)),
+        }
+    }
+}
+
+impl Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Address::Unix(path) => write!(f, "unix://{}", path.display()),
+            Address::Tcp(host) => write!(f, "tcp://{}", host),
+            Address::Tls(host, _) => write!(f, "tls://{}", host),
+            Address::WebSocket(host) => write!(f, "ws://{}", host),
+        }
+    }
+}
+
+/// A TLS session over TCP that can be shared between a reading and a writing thread.
+///
+/// The TLS session is only locked while processing data, never while blocking on the socket, so
+/// the writing side can keep sending messages.
+#[derive(Debug)]
+pub struct TlsStream {
+    socket: TcpStream,
+    session: Arc<Mutex<rustls::Connection>>,
+}
+
+impl TlsStream {
+    fn new(socket: TcpStream, session: rustls::Connection) -> Self {
+        Self {
+            socket,
+            session: Arc::new(Mutex::new(session)),
+        }
+    }
+
+    fn try_clone(&self) -> io::Result<Self> {
+        Ok(Self {
+            socket: self.socket.try_clone()?,
+            session: self.session.clone(),
+        })
+    }
+
+    fn flush_tls(&mut self, session: &mut rustls::Connection) -> io::Result<()> {
+        while session.wants_write() {
+            session.write_tls(&mut self.socket)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Read for TlsStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut raw = [0u8; 16384];
+
+        loop {
+            match self.session.lock().unwrap().reader().read(buf) {
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => {}
+                result => return result,
+            }
+
+            let n = self.socket.read(&mut raw)?;
+            if n == 0 {
+                return Ok(0);
+            }
+
+            let session = self.session.clone();
+            let mut session = session.lock().unwrap();
+            let mut tls_data = &raw[..n];
+            while !tls_data.is_empty() {
+                session.read_tls(&mut tls_data)?;
+                session
+                    .process_new_packets()
+                    .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+            }
+
+            // Send handshake messages or alerts triggered by the received packets
+            self.flush_tls(&mut session)?;
+        }
+    }
+}
+
+impl Write for TlsStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let session = self.session.clone();
+        let mut session = session.lock().unwrap();
+        let n = session.writer().write(buf)?;
+        self.flush_tls(&mut session)?;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let session = self.session.clone();
+        let mut session = session.lock().unwrap();
+        session.writer().flush()?;
+        self.flush_tls(&mut session)?;
+        self.socket.flush()
+    }
+}
+
+/// A WebSocket connection exposed as a stream of newline-terminated messages, so the same code
+/// can handle the messages of every transport.
+///
+/// Each text (or binary) message received is read as a single line and each line written is sent
+/// as a single text message. Pings are answered automatically.
+///
+/// As the WebSocket can't be split in a reading and a writing half, reads are polled at a fixed
+/// interval so the writing side gets access to the connection while waiting for a message.
+#[derive(Debug)]
+pub struct WebSocketStream {
+    socket: TcpStream,
+    websocket: Arc<Mutex<WebSocket<TcpStream>>>,
+    read_buffer: Vec<u8>,
+    write_buffer: Vec<u8>,
+}
+
+impl WebSocketStream {
+    fn new(socket: TcpStream, websocket: WebSocket<TcpStream>) -> io::Result<Self> {
+        socket.set_read_timeout(Some(WEBSOCKET_POLL_INTERVAL))?;
+
+        Ok(Self {
+            socket,
+            websocket: Arc::new(Mutex::new(websocket)),
+            read_buffer: vec![],
+            write_buffer: vec![],
+        })
+    }
+
+    fn try_clone(&self) -> io::Result<Self> {
+        Ok(Self {
+            socket: self.socket.try_clone()?,
+            websocket: self.websocket.clone(),
+            read_buffer: vec![],
+            write_buffer: vec![],
+        })
+    }
+
+    fn with_websocket<T>(
+        &self,
+        f: impl FnOnce(&mut WebSocket<TcpStream>) -> io::Result<T>,
+    ) -> io::Result<T> {
+        f(&mut self.websocket.lock().unwrap())
+    }
+}
+
+fn websocket_error(err: tungstenite::Error) -> io::Error {
+    match err {
+        tungstenite::Error::Io(err) => err,
+        tungstenite::Error::ConnectionClosed | tungstenite::Error::AlreadyClosed => {
+            io::Error::from(io::ErrorKind::ConnectionAborted)
+        }
+        err => io::Error::other(err),
+    }
+}
+
+impl Read for WebSocketStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.read_buffer.is_empty() {
+            let message = match self.with_websocket(|w| w.read().map_err(websocket_error)) {
+                Ok(message) => message,
+                Err(err)
+                    if matches!(
+                        err.kind(),
+                        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                    ) =>
+                {
+                    continue
+                }
+                Err(err) if err.kind() == io::ErrorKind::ConnectionAborted => return Ok(0),
+                Err(err) => return Err(err),
+            };
+
+            match message {
+                Message::Text(text) => self.read_buffer.extend_from_slice(text.as_bytes()),
+                Message::Binary(data) => self.read_buffer.extend_from_slice(&data),
+                Message::Close(_) => return Ok(0),
+                Message::Ping(_) | Message::Pong(_) | Message::Frame(_) => continue,
+            }
+            self.read_buffer.push(b'\n');
+        }
+
+        let n = buf.len().min(self.read_buffer.len());
+        buf[..n].copy_from_slice(&self.read_buffer[..n]);
+        self.read_buffer.drain(..n);
+        Ok(n)
+    }
+}
+
+impl Write for WebSocketStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.write_buffer.extend_from_slice(buf);
+
+        while let Some(end) = self.write_buffer.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = self.write_buffer.drain(..=end).collect();
+            let text = String::from_utf8_lossy(&line[..end]).to_string();
+            self.with_websocket(|w| w.send(Message::text(text)).map_err(websocket_error))?;
+        }
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.with_websocket(|w| w.flush().map_err(websocket_error))
+    }
+}
+
+/// A connection between the client and the server, whatever the transport.
+#[derive(Debug)]
+pub enum Connection {
+    /// UNIX socket connection.
+    Unix(UnixStream),
+    /// Plain TCP connection.
+    Tcp(TcpStream),
+    /// TLS connection.
+    Tls(TlsStream),
+    /// WebSocket connection.
+    WebSocket(WebSocketStream),
+}
+
+impl Connection {
+    /// Connects to the server at the given address.
+    pub fn connect(address: &Address) -> Result<Connection> {
+        Ok(match address {
+            Address::Unix(path) => Connection::Unix(UnixStream::connect(path)?),
+            Address::Tcp(host) => Connection::Tcp(TcpStream::connect(host)?),
+            Address::Tls(host, cert) => {
+                let pinned_cert = CertificateDer::from_pem_file(cert)
+                    .with_context(|| format!("Could not read TLS certificate {:?}", cert))?;
+                let config =
+                    ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+                        .with_safe_default_protocol_versions()?
+                        .dangerous()
+                        .with_custom_certificate_verifier(Arc::new(PinnedCertVerifier {
+                            cert: pinned_cert,
+                            provider: ring::default_provider(),
+                        }))
+                        .with_no_client_auth();
+
+                let server_name = host.rsplit_once(':').map_or(host.as_str(), |(h, _)| h);
+                let server_name = ServerName::try_from(server_name.to_string())?;
+                let session = ClientConnection::new(Arc::new(config), server_name)?;
+
+                let mut socket = TcpStream::connect(host)?;
+                let mut session = rustls::Connection::from(session);
+                // Complete the handshake right away to report certificate errors on connection
+                while session.is_handshaking() {
+                    session.complete_io(&mut socket)?;
+                }
+
+                Connection::Tls(TlsStream::new(socket, session))
+            }
+            Address::WebSocket(host) => {
+                let socket = TcpStream::connect(host)?;
+                let (websocket, _) =
+                    tungstenite::client(format!("ws://{}/", host), socket.try_clone()?)?;
+
+                Connection::WebSocket(WebSocketStream::new(socket, websocket)?)
+            }
+        })
+    }
+
+    /// Creates a new handle to the same connection, e.g. for reading and writing from different
+    /// threads.
+    pub fn try_clone(&self) -> io::Result<Connection> {
+        Ok(match self {
+            Connection::Unix(stream) => Connection::Unix(stream.try_clone()?),
+            Connection::Tcp(stream) => Connection::Tcp(stream.try_clone()?),
+            Connection::Tls(stream) => Connection::Tls(stream.try_clone()?),
+            Connection::WebSocket(stream) => Connection::WebSocket(stream.try_clone()?),
+        })
+    }
+
+    /// Describes the remote end of the connection in the address format, e.g.
+    /// `tcp://127.0.0.1:54420`. Clients of a UNIX socket are usually unnamed.
+    pub fn peer(&self) -> String {
+        let (scheme, socket) = match self {
+            Connection::Unix(stream) => {
+                let path = stream
+                    .peer_addr()
+                    .ok()
+                    .and_then(|a| a.as_pathname().map(|p| p.display().to_string()));
+                return format!("unix://{}", path.unwrap_or_default());
+            }
+            Connection::Tcp(stream) => ("tcp", stream),
+            Connection::Tls(stream) => ("tls", &stream.socket),
+            Connection::WebSocket(stream) => ("ws", &stream.socket),
+        };
+
+        socket
+            .peer_addr()
+            .map_or(format!("{}://", scheme), |a| format!("{}://{}", scheme, a))
+    }
+
+    /// Shuts down the read, write, or both halves of the connection.
+    pub fn shutdown(&self, how: Shutdown) -> io::Result<()> {
+        match self {
+            Connection::Unix(stream) => stream.shutdown(how),
+            Connection::Tcp(stream) => stream.shutdown(how),
+            Connection::Tls(stream) => stream.socket.shutdown(how),
+            Connection::WebSocket(stream) => stream.socket.shutdown(how),
+        }
+    }
+}
+
+impl Read for Connection {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Connection::Unix(stream) => stream.read(buf),
+            Connection::Tcp(stream) => stream.read(buf),
+            Connection::Tls(stream) => stream.read(buf),
+            Connection::WebSocket(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Write for Connection {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Connection::Unix(stream) => stream.write(buf),
+            Connection::Tcp(stream) => stream.write(buf),
+            Connection::Tls(stream) => stream.write(buf),
+            Connection::WebSocket(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Connection::Unix(stream) => stream.flush(),
+            Connection::Tcp(stream) => stream.flush(),
+            Connection::Tls(stream) => stream.flush(),
+            Connection::WebSocket(stream) => stream.flush(),
+        }
+    }
+}
+
+/// Loads the server certificate and private key from the given PEM files, generating a new
+/// self-signed pair if neither exists yet. Fails if only one of them exists, as the other one can't
+/// be generated for it. An existing key readable by other users is restricted to its owner.
+pub fn load_or_generate_certificate(
+    cert_file: &Path,
+    key_file: &Path,
+) -> Result<(Vec<CertificateDer<'static>>, PrivateKeyDer<'static>)> {
+    match (cert_file.exists(), key_file.exists()) {
+        (true, true) => {
+            let mut permissions = fs::metadata(key_file)?.permissions();
+            if permissions.mode() & 0o077 != 0 {
+                permissions.set_mode(0o600);
+                fs::set_permissions(key_file, permissions).with_context(|| {
+                    format!(
+                        "Could not restrict the permissions of TLS key {:?}",
+                        key_file
+                    )
+                })?;
+            }
+        }
+        (false, false) => {
+            let generated = rcgen::generate_simple_self_signed(vec!["localhost".to_string()])?;
+
+            if let Some(folder) = cert_file.parent() {
+                fs::create_dir_all(folder)?;
+            }
+            if let Some(folder) = key_file.parent() {
+                fs::create_dir_all(folder)?;
+            }
+
+            fs::write(cert_file, generated.cert.pem())?;
+            // Only the daemon's user can read the private key
+            OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .mode(0o600)
+                .open(key_file)?
+                .write_all(generated.key_pair.serialize_pem().as_bytes())?;
+        }
+        (true, false) => bail!(
+            "TLS certificate {:?} found without its key {:?}, restore the key or remove the \
+             certificate to generate a new pair",
+            cert_file,
+            key_file
+        ),
+        (false, true) => bail!(
+            "TLS key {:?} found without its certificate {:?}, restore the certificate or remove \
+             the key to generate a new pair",
+            key_file,
+            cert_file
+        ),
+    }
+
+    let certs = CertificateDer::pem_file_iter(cert_file)?.collect::<Result<Vec<_>, _>>()?;
+    let key = PrivateKeyDer::from_pem_file(key_file)?;
+
+    Ok((certs, key))
+}
+
+/// Only accepts the exact certificate generated by the server, whatever the name used to reach it.
+#[derive(Debug)]
+struct PinnedCertVerifier {
+    cert: CertificateDer<'static>,
+    provider: CryptoProvider,
+}
+
+impl ServerCertVerifier for PinnedCertVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        if end_entity.as_ref() == self.cert.as_ref() {
+            Ok(ServerCertVerified::assertion())
+        } else {
+            Err(rustls::Error::InvalidCertificate(
+                rustls::CertificateError::UnknownIssuer,
+            ))
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider
+            .signature_verification_algorithms
+            .supported_schemes()
+    }
+}
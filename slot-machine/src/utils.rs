@@ -1,6 +1,9 @@
-//! Utility functions used across the different binaries. 
+//! Utility functions used across the different binaries.
 
-use std::{io::Write, os::unix::net::UnixStream};
+use std::io::{self, BufRead, Read, Write};
+use std::time::Duration;
+
+use crate::MAX_BYTES_READ;
 
 /// Returns the binary representation of a 64bit integer grouped by bytes.
 pub fn format_binary(n: u64) -> String {
@@ -13,9 +16,67 @@ pub fn format_binary(n: u64) -> String {
     )
 }
 
+/// Returns the lowercase hexadecimal representation of the bytes.
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Returns a human readable duration rounded down to the minute (e.g. `1d 2h 30m`), or to the
+/// second below a minute.
+pub fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    if seconds < 60 {
+        return format!("{}s", seconds);
+    }
+
+    let (days, hours, minutes) = (seconds / 86400, seconds / 3600 % 24, seconds / 60 % 60);
+    [(days, "d"), (hours, "h"), (minutes, "m")]
+        .iter()
+        .filter(|(value, _)| *value > 0)
+        .map(|(value, unit)| format!("{}{}", value, unit))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Removes a `<option> <value>` pair from the command line arguments, returning its value if it
+/// was given, or an error if the value is missing.
+pub fn take_option<'a>(args: &mut Vec<&'a str>, option: &str) -> Result<Option<&'a str>, String> {
+    let Some(i) = args.iter().position(|arg| *arg == option) else {
+        return Ok(None);
+    };
+    let value = *args
+        .get(i + 1)
+        .ok_or_else(|| format!("Missing value for {}", option))?;
+    args.drain(i..=i + 1);
+
+    Ok(Some(value))
+}
+
 /// Write a message to a socket stream and appending a newline character at the end.
 /// The stream is also flushed after the write operation.
-pub fn send_socket_message(stream: &mut UnixStream, message: String) {
-    writeln!(stream, "{}", message).expect("Could not send message to server");
-    stream.flush().expect("Could not flush");
+pub fn send_socket_message(stream: &mut impl Write, message: String) -> io::Result<()> {
+    writeln!(stream, "{}", message)?;
+    stream.flush()
+}
+
+/// Reads a single newline-terminated message from a socket stream, without the trailing newline.
+///
+/// Returns `None` when the stream is closed. Messages longer than `MAX_BYTES_READ` are truncated
+/// and the rest of the line is discarded.
+pub fn read_socket_message(reader: &mut impl BufRead) -> io::Result<Option<String>> {
+    let mut buf = Vec::new();
+    let bytes_read = reader
+        .by_ref()
+        .take(MAX_BYTES_READ)
+        .read_until(b'\n', &mut buf)?;
+
+    if bytes_read == 0 {
+        return Ok(None);
+    }
+
+    if buf.last() != Some(&b'\n') && bytes_read as u64 == MAX_BYTES_READ {
+        reader.skip_until(b'\n')?;
+    }
+
+    Ok(Some(String::from_utf8_lossy(&buf).trim_end().to_string()))
 }
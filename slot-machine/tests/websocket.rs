@@ -0,0 +1,121 @@
+//! Serves the games to a WebSocket client through a running daemon.
+
+use std::fs;
+use std::net::{TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use slot_machine::protocol::{ClientCommand, ServerResponse};
+use tungstenite::stream::MaybeTlsStream;
+use tungstenite::{Message, WebSocket};
+
+// Time given to the daemon to load the games and start listening
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Daemon listening on a WebSocket address, killed with its files when dropped.
+struct Daemon {
+    process: Child,
+    folder: PathBuf,
+    address: String,
+}
+
+impl Daemon {
+    fn start() -> Daemon {
+        // Bind on an ephemeral port to find a free one for the daemon
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let address = format!("ws://127.0.0.1:{}", port);
+        let folder = std::env::temp_dir().join(format!("slot-machine-ws-{}", std::process::id()));
+        fs::create_dir_all(&folder).unwrap();
+        // Ignore the configuration file of the repository, if any
+        fs::write(folder.join("daemon.json"), "{}").unwrap();
+
+        let process = Command::new(env!("CARGO_BIN_EXE_daemon"))
+            .args(["--config", folder.join("daemon.json").to_str().unwrap()])
+            .args(["--listen", &address])
+            .args(["--games", "data/games"])
+            .args(["--accounts", folder.join("accounts.db").to_str().unwrap()])
+            .args(["--audit-log", folder.join("audit.jsonl").to_str().unwrap()])
+            .args([
+                "--admin-socket",
+                folder.join("admin.sock").to_str().unwrap(),
+            ])
+            .args(["--log-level", "off", "--no-watch"])
+            .stdout(Stdio::null())
+            .spawn()
+            .expect("Could not start the daemon");
+
+        Daemon {
+            process,
+            folder,
+            address,
+        }
+    }
+}
+
+impl Drop for Daemon {
+    fn drop(&mut self) {
+        let _ = self.process.kill();
+        let _ = self.process.wait();
+        let _ = fs::remove_dir_all(&self.folder);
+    }
+}
+
+#[test]
+fn list_games_over_websocket() {
+    let mut daemon = Daemon::start();
+
+    let started = Instant::now();
+    let (mut socket, _) = loop {
+        match tungstenite::connect(&daemon.address) {
+            Ok(connected) => break connected,
+            Err(err) if started.elapsed() > STARTUP_TIMEOUT => {
+                panic!("Could not connect to {}: {}", daemon.address, err)
+            }
+            Err(_) => {
+                if let Some(status) = daemon.process.try_wait().unwrap() {
+                    panic!("The daemon exited with {}", status);
+                }
+                thread::sleep(Duration::from_millis(100))
+            }
+        }
+    };
+
+    let registered = request(
+        &mut socket,
+        ClientCommand::Register {
+            player: "websocket".to_string(),
+            password: "websocket password".to_string(),
+        },
+    );
+    assert!(
+        matches!(registered, ServerResponse::LoggedIn { .. }),
+        "Unexpected response: {:?}",
+        registered
+    );
+
+    let ServerResponse::Games(games) = request(&mut socket, ClientCommand::ListGames) else {
+        panic!("Unexpected response to ListGames");
+    };
+    let ids: Vec<&str> = games.iter().map(|game| game.id.as_str()).collect();
+    assert!(ids.contains(&"blaze7"), "blaze7 missing from {:?}", ids);
+    assert!(ids.contains(&"generic"), "generic missing from {:?}", ids);
+}
+
+// Each command is sent in a text message and answered by another one
+fn request(
+    socket: &mut WebSocket<MaybeTlsStream<TcpStream>>,
+    command: ClientCommand,
+) -> ServerResponse {
+    socket
+        .send(Message::text(serde_json::to_string(&command).unwrap()))
+        .unwrap();
+    let response = socket.read().unwrap();
+
+    serde_json::from_str(response.to_text().unwrap()).unwrap()
+}